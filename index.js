@@ -57,6 +57,13 @@ import('./pkg')
             }; 
         }
 
+        // at most this many draws per chain and this many milliseconds per
+        // batch of the sampling - then the browser gets the hand back
+        const STEP_DRAWS = BigInt(100000);
+        const STEP_MILLIS = 40;
+        // the RunOptions of the runs (see run_with) - the defaults
+        const RUN_OPTIONS = {};
+
         const sample = (onSuccess) => {
             status.textContent = "Running...";
 
@@ -76,20 +83,46 @@ import('./pkg')
                 console.log(`tuning: ${tuning_value}`);
                 console.log(`samples: ${samples_value}`);
                 
-                setTimeout(() => {
-                    wasm.run_with("trace_plot", "posterior", seed, input_data, chain_count, tuning_value, samples_value);
-                        const end = Date.now();
-                        const elapsed = end - start;
-                        results.textContent = `Elapsed: ${elapsed}ms`;     
-                        
-                        setTimeout(() => {
-                            plot();
+                // the chains are drawn in short batches: the page stays
+                // responsive and shows the partial traces in between
+                const step = () => {
+                    let fit;
+                    try {
+                        fit = wasm.step_streaming_run("trace_plot", STEP_DRAWS, STEP_MILLIS);
+                    } catch (e) {
+                        console.error(e);
+                        status.textContent = "Error: " + e;
+                        return;
+                    }
+                    if (fit === undefined) {
+                        setTimeout(step, 0);
+                        return;
+                    }
+
+                    const end = Date.now();
+                    const elapsed = end - start;
+                    results.textContent = `Elapsed: ${elapsed}ms`;
+                    console.log(fit);
 
-                            if (onSuccess) {
-                                onSuccess();
-                            }
-                            status.textContent = "Ready";
+                    setTimeout(() => {
+                        plot();
+
+                        if (onSuccess) {
+                            onSuccess();
+                        }
+                        status.textContent = "Ready";
                     }, 10);
+                };
+
+                setTimeout(() => {
+                    try {
+                        wasm.start_streaming_run(seed, input_data, chain_count, tuning_value, samples_value, RUN_OPTIONS, "posterior");
+                    } catch (e) {
+                        console.error(e);
+                        status.textContent = "Error: " + e;
+                        return;
+                    }
+                    step();
                 }, 10);
             }, 10);
         }
@@ -102,7 +135,7 @@ import('./pkg')
                 const ctx = canvas.getContext("2d");
                 ctx.clearRect(0, 0, canvas.width, canvas.height);
 
-                input.value = wasm.prepare(raw.value);
+                input.value = wasm.prepare(raw.value, "TMAX");
 
                 if (onSuccess) {
                     onSuccess();
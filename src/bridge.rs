@@ -0,0 +1,291 @@
+//! Bridge sampling estimate of the log marginal likelihood
+//!
+//! The marginal likelihood `p(y) = ∫ p(y | θ) p(θ) dθ` is the normalizing
+//! constant of the posterior. The ratio of the marginal likelihoods of two
+//! models fitted on the same data is their Bayes factor.
+//!
+//! The estimate bridges the posterior draws and draws of a normal proposal
+//! fitted on them - on the unconstrained scale of the parameters - with the
+//! iterative scheme of Meng & Wong (1996), as in Gronau et al. (2017).
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use rand_distr::{Distribution, StandardNormal};
+
+use crate::chain::Support;
+use crate::stats::{mean, quantile};
+use crate::MyError;
+
+/// Maximum number of iterations of the fixed-point scheme.
+const MAX_ITERATIONS: usize = 1_000;
+
+/// Relative change of the estimate at which the iterations stop.
+const TOLERANCE: f64 = 1e-10;
+
+/// Estimates the log marginal likelihood from posterior draws.
+///
+/// `log_joint` is the normalized log prior plus the normalized log likelihood
+/// of a position - `None` if a prior is improper. `supports` is the support of
+/// each parameter of the draws.
+///
+/// Half of the draws fit the proposal and the other half enter the bridge -
+/// interleaved so that each chain contributes to both. Fails if the priors
+/// are improper or if there are too few draws to fit the proposal.
+pub(crate) fn log_marginal_likelihood(
+    draws: &[Vec<f64>],
+    supports: &[Support],
+    log_joint: impl Fn(&[f64]) -> Option<f64>,
+    seed: u64,
+) -> Result<f64, MyError> {
+    let dim = supports.len();
+
+    // enough draws for a well-defined covariance of the proposal
+    let needed = 4 * (dim + 1);
+    if draws.len() < needed {
+        return Err(MyError::NotEnoughDraws {
+            draws: draws.len(),
+            needed,
+        });
+    }
+
+    // the posterior draws on the unconstrained scale
+    let unconstrained = draws
+        .iter()
+        .map(|draw| {
+            draw.iter()
+                .zip(supports)
+                .map(|(x, support)| support.unconstrain(*x))
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+
+    let fit = unconstrained.iter().step_by(2).cloned().collect::<Vec<_>>();
+    let bridge = unconstrained
+        .iter()
+        .skip(1)
+        .step_by(2)
+        .cloned()
+        .collect::<Vec<_>>();
+
+    let proposal = NormalProposal::fit(&fit);
+
+    // log density of the unnormalized posterior on the unconstrained scale
+    let log_q = |u: &[f64]| -> Result<f64, MyError> {
+        let x = u
+            .iter()
+            .zip(supports)
+            .map(|(u, support)| support.constrain(*u))
+            .collect::<Vec<_>>();
+        let log_jacobian = u
+            .iter()
+            .zip(supports)
+            .map(|(u, support)| support.log_jacobian(*u))
+            .sum::<f64>();
+
+        let log_joint = log_joint(&x).ok_or(MyError::ImproperPrior)? + log_jacobian;
+        Ok(if log_joint.is_nan() {
+            f64::NEG_INFINITY
+        } else {
+            log_joint
+        })
+    };
+
+    // log ratios of the posterior to the proposal - at the posterior draws
+    // and at as many draws of the proposal
+    let mut rng = StdRng::seed_from_u64(seed);
+    let l1 = bridge
+        .iter()
+        .map(|u| Ok(log_q(u)? - proposal.log_pdf(u)))
+        .collect::<Result<Vec<_>, MyError>>()?;
+    let l2 = (0..bridge.len())
+        .map(|_| {
+            let u = proposal.sample(&mut rng);
+            Ok(log_q(&u)? - proposal.log_pdf(&u))
+        })
+        .collect::<Result<Vec<_>, MyError>>()?;
+
+    Ok(iterate(&l1, &l2))
+}
+
+/// Runs the fixed-point scheme on the log ratios of the posterior to the
+/// proposal at the posterior draws (`l1`) and at the proposal draws (`l2`).
+///
+/// Returns the log of the estimate.
+fn iterate(l1: &[f64], l2: &[f64]) -> f64 {
+    let (n1, n2) = (l1.len() as f64, l2.len() as f64);
+    let (s1, s2) = (n1 / (n1 + n2), n2 / (n1 + n2));
+
+    // the ratios are rescaled by their median to stay in range
+    let l_star = quantile(l1, 0.5);
+    let e1 = l1.iter().map(|l| (l - l_star).exp()).collect::<Vec<_>>();
+    let e2 = l2.iter().map(|l| (l - l_star).exp()).collect::<Vec<_>>();
+
+    let mut r = 1.;
+    for _ in 0..MAX_ITERATIONS {
+        // e / (s1 e + s2 r) written to stay finite for a very large e
+        let numerator = mean(
+            &e2.iter()
+                .map(|e| 1. / (s1 + s2 * r / e))
+                .collect::<Vec<_>>(),
+        );
+        let denominator = mean(
+            &e1.iter()
+                .map(|e| 1. / (s1 * e + s2 * r))
+                .collect::<Vec<_>>(),
+        );
+
+        let next = numerator / denominator;
+        let converged = ((next - r) / next).abs() < TOLERANCE;
+        r = next;
+        if converged {
+            break;
+        }
+    }
+
+    r.ln() + l_star
+}
+
+/// A multivariate normal fitted on draws.
+struct NormalProposal {
+    mean: Vec<f64>,
+    /// Lower triangular Cholesky factor of the covariance
+    cholesky: Vec<Vec<f64>>,
+}
+
+impl NormalProposal {
+    /// Fits the mean and the covariance of the draws.
+    fn fit(draws: &[Vec<f64>]) -> Self {
+        let dim = draws[0].len();
+        let n = draws.len() as f64;
+
+        let mean = (0..dim)
+            .map(|i| draws.iter().map(|draw| draw[i]).sum::<f64>() / n)
+            .collect::<Vec<_>>();
+
+        let covariance = (0..dim)
+            .map(|i| {
+                (0..dim)
+                    .map(|j| {
+                        draws
+                            .iter()
+                            .map(|draw| (draw[i] - mean[i]) * (draw[j] - mean[j]))
+                            .sum::<f64>()
+                            / (n - 1.)
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        Self {
+            mean,
+            cholesky: cholesky(&covariance),
+        }
+    }
+
+    /// Log density at `u`.
+    fn log_pdf(&self, u: &[f64]) -> f64 {
+        let dim = self.mean.len();
+
+        // z = L⁻¹ (u - mean) by forward substitution
+        let mut z = vec![0.; dim];
+        for i in 0..dim {
+            let dot = (0..i).map(|j| self.cholesky[i][j] * z[j]).sum::<f64>();
+            z[i] = (u[i] - self.mean[i] - dot) / self.cholesky[i][i];
+        }
+
+        let log_det = (0..dim).map(|i| self.cholesky[i][i].ln()).sum::<f64>();
+
+        -0.5 * dim as f64 * (2. * std::f64::consts::PI).ln()
+            - log_det
+            - 0.5 * z.iter().map(|z| z * z).sum::<f64>()
+    }
+
+    /// Draws a position: `mean + L z` with `z` standard normal.
+    fn sample(&self, rng: &mut StdRng) -> Vec<f64> {
+        let z = (0..self.mean.len())
+            .map(|_| StandardNormal.sample(rng))
+            .collect::<Vec<f64>>();
+
+        self.mean
+            .iter()
+            .enumerate()
+            .map(|(i, m)| m + (0..=i).map(|j| self.cholesky[i][j] * z[j]).sum::<f64>())
+            .collect()
+    }
+}
+
+/// Cholesky factor of a symmetric positive semi-definite matrix.
+///
+/// A (nearly) singular matrix - e.g. a constant parameter - gets a tiny pivot
+/// instead of failing.
+fn cholesky(a: &[Vec<f64>]) -> Vec<Vec<f64>> {
+    let dim = a.len();
+    let mut l = vec![vec![0.; dim]; dim];
+
+    for i in 0..dim {
+        for j in 0..=i {
+            let dot = (0..j).map(|k| l[i][k] * l[j][k]).sum::<f64>();
+            if i == j {
+                l[i][i] = (a[i][i] - dot)
+                    .max(1e-12 * a[i][i].abs())
+                    .max(1e-300)
+                    .sqrt();
+            } else {
+                l[i][j] = (a[i][j] - dot) / l[j][j];
+            }
+        }
+    }
+
+    l
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::numerics::log_pdf_normal;
+
+    #[test]
+    fn test_cholesky() {
+        let a = vec![vec![4., 2.], vec![2., 5.]];
+        let l = cholesky(&a);
+        assert_eq!(l, vec![vec![2., 0.], vec![1., 2.]]);
+    }
+
+    #[test]
+    fn test_log_marginal_likelihood() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let log_z = 3.;
+
+        // an unnormalized normal posterior of known normalizing constant
+        let draws = (0..2000)
+            .map(|_| {
+                let z1: f64 = StandardNormal.sample(&mut rng);
+                let z2: f64 = StandardNormal.sample(&mut rng);
+                vec![1. + 2. * z1, (0.5 * z2).exp()]
+            })
+            .collect::<Vec<_>>();
+        let supports = [Support::Real, Support::Positive];
+
+        // x2 is lognormal: the density of log(x2) is normal
+        let log_joint = |x: &[f64]| {
+            Some(
+                log_z + log_pdf_normal(x[0], 1., 2.) + log_pdf_normal(x[1].ln(), 0., 0.5)
+                    - x[1].ln(),
+            )
+        };
+
+        let estimate = log_marginal_likelihood(&draws, &supports, log_joint, 7).unwrap();
+        assert!((estimate - log_z).abs() < 0.01, "{}", estimate);
+
+        assert!(matches!(
+            log_marginal_likelihood(&draws, &supports, |_| None, 7),
+            Err(MyError::ImproperPrior)
+        ));
+        assert!(matches!(
+            log_marginal_likelihood(&draws[..5], &supports, log_joint, 7),
+            Err(MyError::NotEnoughDraws {
+                draws: 5,
+                needed: 12
+            })
+        ));
+    }
+}
@@ -1,17 +1,213 @@
 //! Logic to run the sampling of one [`ChainRun`]or multiple chains [`Chains`].
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 use nuts_rs::CpuLogpFunc;
+use plotters::coord::Shift;
 use plotters::prelude::*;
-use plotters_canvas::CanvasBackend;
+use rand::rngs::StdRng;
 use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use rand_distr::{Distribution, StandardNormal};
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use wasm_bindgen::prelude::*;
 
 use crate::{
-    log,
-    sampler::{be_nuts, MyDivergenceInfo},
+    bridge, log,
+    plot::{canvas_area, LegendPosition},
+    sampler::{be_nuts, be_nuts_stepper, DrawStats, MyDivergenceInfo, SamplerState, Stepper},
+    stats::{
+        ess, hdi, integrated_autocorr_time, mean, posterior_mode, quantile, running_mean, sd,
+        split_r_hat,
+    },
+    utils::{now_millis, variant_from_index},
+    MyError,
 };
 
+/// How the posterior histograms are drawn.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "u32")]
+pub enum HistogramMode {
+    /// One histogram per chain - to diagnose convergence.
+    #[default]
+    PerChain,
+    /// A single histogram of the draws of all the chains.
+    Pooled,
+    /// A single histogram of the draws of all the chains with a fitted normal density.
+    PooledWithNormal,
+}
+
+/// Which panels are drawn for each parameter.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "u32")]
+pub enum PlotPanels {
+    /// The histogram of the posterior and the trace, side by side.
+    #[default]
+    Both,
+    /// Only the histogram of the posterior.
+    Histogram,
+    /// Only the trace.
+    Trace,
+}
+
+impl TryFrom<u32> for HistogramMode {
+    type Error = String;
+
+    fn try_from(index: u32) -> Result<Self, Self::Error> {
+        use HistogramMode::*;
+        variant_from_index(&[PerChain, Pooled, PooledWithNormal], index)
+    }
+}
+
+impl TryFrom<u32> for PlotPanels {
+    type Error = String;
+
+    fn try_from(index: u32) -> Result<Self, Self::Error> {
+        use PlotPanels::*;
+        variant_from_index(&[Both, Histogram, Trace], index)
+    }
+}
+
+impl PlotPanels {
+    /// Number of panels per parameter.
+    fn count(self) -> usize {
+        match self {
+            PlotPanels::Both => 2,
+            PlotPanels::Histogram | PlotPanels::Trace => 1,
+        }
+    }
+
+    /// Whether the histogram of the posterior is drawn.
+    fn has_histogram(self) -> bool {
+        self != PlotPanels::Trace
+    }
+
+    /// Whether the trace is drawn.
+    fn has_trace(self) -> bool {
+        self != PlotPanels::Histogram
+    }
+}
+
+/// Arrangement of the posterior plots.
+///
+/// The parameters are laid out in a grid with `columns` parameters per row -
+/// one parameter per row by default.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PlotLayout {
+    /// Panels drawn for each parameter
+    pub panels: PlotPanels,
+    /// Number of parameters per row
+    pub columns: u32,
+    /// Position of the legends of the panels
+    pub legend: LegendPosition,
+    /// Overlay the running mean of each chain on its trace - it flattens as
+    /// the chain converges
+    pub running_mean: bool,
+}
+
+impl Default for PlotLayout {
+    fn default() -> Self {
+        Self {
+            panels: PlotPanels::Both,
+            columns: 1,
+            legend: LegendPosition::default(),
+            running_mean: false,
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl PlotLayout {
+    /// Create the default layout: histogram and trace of one parameter per row
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl PlotLayout {
+    /// Returns the (rows, columns) of panels to plot `dim` parameters.
+    fn grid(&self, dim: usize) -> (usize, usize) {
+        let per_row = (self.columns as usize).clamp(1, dim.max(1));
+        let rows = dim.div_ceil(per_row);
+
+        (rows, per_row * self.panels.count())
+    }
+}
+
+/// The support of a parameter - the values it can take.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) enum Support {
+    /// Any real value
+    #[default]
+    Real,
+    /// Positive values - e.g. a standard deviation
+    Positive,
+    /// Values in (0, 1) - e.g. a probability
+    #[allow(dead_code)] // none of the current models has such a parameter
+    UnitInterval,
+}
+
+impl Support {
+    /// Name of the support in the outputs.
+    fn name(self) -> &'static str {
+        match self {
+            Support::Real => "real",
+            Support::Positive => "positive",
+            Support::UnitInterval => "unit-interval",
+        }
+    }
+
+    /// Whether `x` is in the support.
+    pub(crate) fn contains(self, x: f64) -> bool {
+        match self {
+            Support::Real => x.is_finite(),
+            Support::Positive => x.is_finite() && x > 0.,
+            Support::UnitInterval => x > 0. && x < 1.,
+        }
+    }
+
+    /// Maps a value of the support to the real line: log for positive values,
+    /// logit for the unit interval.
+    ///
+    /// The posteriors of constrained parameters are closer to symmetric there -
+    /// this is where they are binned and where their HDI is computed.
+    pub(crate) fn unconstrain(self, x: f64) -> f64 {
+        match self {
+            Support::Real => x,
+            Support::Positive => x.ln(),
+            Support::UnitInterval => (x / (1. - x)).ln(),
+        }
+    }
+
+    /// Inverse of [`Support::unconstrain`].
+    pub(crate) fn constrain(self, y: f64) -> f64 {
+        match self {
+            Support::Real => y,
+            Support::Positive => y.exp(),
+            Support::UnitInterval => 1. / (1. + (-y).exp()),
+        }
+    }
+
+    /// Log of the derivative of [`Support::constrain`] at `y` - the term to
+    /// add to a log density of the parameter to get the log density of `y`.
+    pub(crate) fn log_jacobian(self, y: f64) -> f64 {
+        match self {
+            Support::Real => 0.,
+            Support::Positive => y,
+            Support::UnitInterval => {
+                let p = self.constrain(y);
+                p.ln() + (1. - p).ln()
+            }
+        }
+    }
+}
+
 #[derive(Default)]
 pub struct Run {}
 
@@ -19,6 +215,25 @@ pub struct Run {}
 pub(crate) trait Model: CpuLogpFunc {
     /// Return the names of the parameters
     fn parameters(&self) -> Vec<String>;
+
+    /// Return the support of each parameter - unbounded by default.
+    fn supports(&self) -> Vec<Support> {
+        vec![Support::Real; self.dim()]
+    }
+
+    /// Return a reasonable starting point for the chains - the origin by default.
+    fn suggested_initial(&self) -> Vec<f64> {
+        vec![0.; self.dim()]
+    }
+
+    /// Return the normalized log prior plus the normalized log likelihood at
+    /// `position` - the integrand of the marginal likelihood.
+    ///
+    /// `None` if a prior is improper (e.g. flat): the marginal likelihood is
+    /// then undefined. This is the default.
+    fn log_joint(&self, _position: &[f64]) -> Option<f64> {
+        None
+    }
 }
 
 impl Run {
@@ -26,20 +241,55 @@ impl Run {
         &self,
         model: impl Model,
         seed: u64,
-        tuning: u64,
-        samples: u64,
+        settings: &RunSettings,
         initial_position: Vec<f64>,
-    ) -> ChainRun {
-        let (trace, stats) = be_nuts(model, tuning, samples, &initial_position, seed);
+        initial_step: Option<f64>,
+        max_millis: Option<f64>,
+    ) -> (ChainRun, Option<String>) {
+        let draws = be_nuts(
+            model,
+            settings.tuning,
+            settings.samples,
+            &initial_position,
+            seed,
+            max_millis,
+            settings.target_accept,
+            initial_step,
+            settings.maxdepth,
+            settings.max_energy_error,
+        );
+
+        if max_millis.is_none() && draws.failure.is_none() {
+            debug_assert_eq!(
+                draws.trace.len() as u64,
+                settings.samples,
+                "the trace must contain exactly the post-warmup draws"
+            );
+        }
+
+        let chain = ChainRun {
+            step_size: draws.step_size(),
+            trace: draws.trace,
+            stats: draws.divergences,
+            warmup: draws.warmup,
+            sampling: draws.sampling,
+        };
 
-        ChainRun { trace, stats }
+        (chain, draws.failure)
     }
 }
 
 /// A single chain run.
 struct ChainRun {
+    /// The post-warmup draws - the warmup draws are discarded.
     trace: Vec<Box<[f64]>>,
     stats: Vec<MyDivergenceInfo>,
+    /// The step size adapted during the warmup.
+    step_size: f64,
+    /// Acceptance, step size and tree depth of each warmup draw
+    warmup: Vec<DrawStats>,
+    /// Acceptance, step size and tree depth of each post-warmup draw
+    sampling: Vec<DrawStats>,
 }
 
 impl ChainRun {
@@ -52,46 +302,767 @@ impl ChainRun {
     }
 
     /// Return the stats for divergences.
-    #[allow(dead_code)]
     pub fn stats(&self) -> &Vec<MyDivergenceInfo> {
         &self.stats
     }
+
+    /// Keeps the first `len` post-warmup draws - and their divergences.
+    fn truncate(&mut self, len: usize) {
+        self.trace.truncate(len);
+        self.sampling.truncate(len);
+        self.stats.retain(|divergence| divergence.draw < len);
+    }
 }
 
 /// A collection of chains
+///
+/// Each chain holds exactly `samples` draws: the `tuning` warmup draws used to
+/// adapt the sampler are discarded. Every statistic computed from the traces
+/// (summary, ESS, R-hat, ...) is therefore about post-warmup draws only.
+///
+/// With a time budget, the chains may hold fewer draws - but always the same
+/// number for all the chains (see [`Chains::samples`]).
 pub(crate) struct Chains {
     chains: Vec<ChainRun>,
     dim: usize,
     pub(crate) parameters: Vec<String>,
+    supports: Vec<Support>,
+    /// Why the chains that stopped early failed - they are not in `chains`
+    failures: Vec<String>,
+    /// Names shown for the parameters - see [`Chains::set_display_names`]
+    display_names: Option<Vec<String>>,
 }
 
-impl Chains {
-    /// Runs a collection of chains - sequentially.
-    pub fn run(
-        seed: u64,
-        model: impl Model + Clone,
-        chain_count: u64,
-        tuning: u64,
-        samples: u64,
-        initial_position: Vec<f64>,
-    ) -> Self {
-        let chains = (0..chain_count)
+/// Central 95% interval of a parameter
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub(crate) struct Interval {
+    /// 2.5% quantile
+    pub low: f64,
+    /// 97.5% quantile
+    pub high: f64,
+}
+
+/// Convergence diagnostics of a parameter
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub(crate) struct Diagnostics {
+    /// Effective sample size - see [`Chains::effective_sample_size`]
+    pub ess: f64,
+    /// Monte Carlo standard error of the mean - see [`Chains::mcse_mean`]
+    pub mcse_mean: f64,
+    /// Fraction of repeated draws - see [`Chains::repeat_fraction`]
+    pub repeat_fraction: f64,
+}
+
+/// Result of a run - serialized for JS
+///
+/// The vectors hold one entry per parameter, in the order of `parameters`.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub(crate) struct FitResult {
+    /// Names of the sampled parameters
+    pub parameters: Vec<String>,
+    /// Posterior mean of each parameter
+    pub means: Vec<f64>,
+    /// Central 95% interval of each parameter
+    pub intervals: Vec<Interval>,
+    /// Convergence diagnostics of each parameter
+    pub diagnostics: Vec<Diagnostics>,
+    /// Warnings raised during the run - e.g. chains that disagree
+    pub warnings: Vec<String>,
+}
+
+/// The draws of a run - serialized for JS
+///
+/// The draws are positions: one entry per parameter, in the order of
+/// `parameters`.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub(crate) struct RunDraws {
+    /// Names of the sampled parameters
+    pub parameters: Vec<String>,
+    /// Post-warmup draws of each chain
+    pub chains: Vec<Vec<Vec<f64>>>,
+    /// Draws picked at random across the chains - see [`Chains::sample_posterior`]
+    pub posterior_samples: Vec<Vec<f64>>,
+    /// Split R-hat of each parameter - by name
+    pub r_hat: BTreeMap<String, f64>,
+}
+
+/// A window of the warmup of a chain
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub(crate) struct WarmupPhase {
+    /// Number of draws in the window
+    pub draws: usize,
+    /// Mean acceptance rate of the draws
+    pub accept: f64,
+    /// Step size at the end of the window
+    pub step_size: f64,
+}
+
+/// How the adaptation of a chain went - serialized for JS
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub(crate) struct Adaptation {
+    /// Consecutive windows of the warmup
+    pub warmup: Vec<WarmupPhase>,
+    /// Step size adapted during the warmup
+    pub step_size: f64,
+    /// Mean acceptance rate of the post-warmup draws
+    pub sampling_accept: f64,
+}
+
+/// Chains run a few draws at a time - to watch them converge.
+///
+/// The chains are interleaved: each step makes the same number of draws in
+/// every chain. As in [`Chains::run`], the warmup draws are discarded - the
+/// partial chains hold the post-warmup draws made so far - and a chain stopped
+/// by an unrecoverable error of the sampler is left out.
+pub(crate) struct StreamingRun {
+    /// The id and the stepper of each chain still running - in the order of
+    /// the chains of `chains`
+    steppers: Vec<(usize, Stepper)>,
+    chains: Chains,
+    /// Number of warmup draws per chain
+    tuning: u64,
+    /// Number of draws per chain - warmup included
+    total: u64,
+    /// Number of draws made by each chain so far - warmup included
+    drawn: u64,
+}
+
+impl StreamingRun {
+    /// Starts the chains of [`Chains::run`] - without drawing anything yet.
+    ///
+    /// The time budget of the settings is ignored: the caller decides when to
+    /// stop. Fails with [`MyError::SamplingFailed`] if no chain could start.
+    pub fn start(
+        model: impl Model + Clone + 'static,
+        settings: &RunSettings,
+    ) -> Result<Self, MyError> {
+        settings.check(model.dim())?;
+
+        let mut steppers = vec![];
+        let mut failures = vec![];
+        for (x, initial_position) in settings.initial_positions(&model)?.into_iter().enumerate() {
+            let stepper = be_nuts_stepper(
+                model.clone(),
+                settings.tuning,
+                &initial_position,
+                settings.chain_seed(x as u64),
+                settings.target_accept,
+                settings.initial_step(x),
+                settings.maxdepth,
+                settings.max_energy_error,
+            );
+            match stepper {
+                Ok(stepper) => steppers.push((x, stepper)),
+                Err(e) => failures.push(format!("chain {} stopped at the start: {}", x, e)),
+            }
+        }
+
+        if steppers.is_empty() && !failures.is_empty() {
+            return Err(MyError::SamplingFailed(failures.join("; ")));
+        }
+
+        let chains = Chains {
+            chains: steppers
+                .iter()
+                .map(|_| ChainRun {
+                    trace: vec![],
+                    stats: vec![],
+                    step_size: f64::NAN,
+                    warmup: vec![],
+                    sampling: vec![],
+                })
+                .collect(),
+            dim: model.dim(),
+            parameters: model.parameters(),
+            supports: model.supports(),
+            failures,
+            display_names: None,
+        };
+
+        Ok(Self {
+            steppers,
+            chains,
+            tuning: settings.tuning,
+            total: settings.tuning + settings.samples,
+            drawn: 0,
+        })
+    }
+
+    /// Makes up to `draws` more draws in each chain. Returns whether all the
+    /// draws are done.
+    ///
+    /// With `max_millis`, it also stops once the budget is exhausted - after a
+    /// draw in every chain, so a budget of 0 makes one draw per chain: the
+    /// caller can yield (e.g. to the event loop of the browser) and carry on
+    /// with the next call.
+    ///
+    /// A chain whose sampler fails is left out and its error kept (see
+    /// [`Chains::failures`]). Fails with [`MyError::SamplingFailed`] once all
+    /// the chains failed.
+    pub fn advance(&mut self, draws: u64, max_millis: Option<f64>) -> Result<bool, MyError> {
+        let draws = draws.min(self.total - self.drawn);
+        let deadline = max_millis.map(|budget| now_millis() + budget);
+
+        for _ in 0..draws {
+            let mut failed = vec![];
+            for (k, ((x, stepper), chain)) in self
+                .steppers
+                .iter_mut()
+                .zip(self.chains.chains.iter_mut())
+                .enumerate()
+            {
+                let (draw, divergence, stats) = match stepper() {
+                    Ok(step) => step,
+                    Err(e) => {
+                        let when = if self.drawn < self.tuning {
+                            String::from("during warmup")
+                        } else {
+                            format!("after {} draws", chain.trace.len())
+                        };
+                        failed.push((k, format!("chain {} stopped {}: {}", x, when, e)));
+                        continue;
+                    }
+                };
+                chain.step_size = stats.step_size;
+                // the warmup draws are not from the posterior
+                if self.drawn >= self.tuning {
+                    chain.trace.push(draw);
+                    chain.stats.extend(divergence);
+                    chain.sampling.push(stats);
+                } else {
+                    chain.warmup.push(stats);
+                }
+            }
+            self.drawn += 1;
+
+            for (k, failure) in failed.into_iter().rev() {
+                drop(self.steppers.remove(k));
+                self.chains.chains.remove(k);
+                self.chains.failures.push(failure);
+            }
+            if self.steppers.is_empty() {
+                self.chains.failures.sort();
+                return Err(MyError::SamplingFailed(self.chains.failures.join("; ")));
+            }
+            if deadline.is_some_and(|deadline| now_millis() >= deadline) {
+                break;
+            }
+        }
+
+        Ok(self.is_done())
+    }
+
+    /// Whether all the draws are done.
+    pub fn is_done(&self) -> bool {
+        self.drawn == self.total
+    }
+
+    /// Returns the partial chains.
+    pub fn chains(&self) -> &Chains {
+        &self.chains
+    }
+
+    /// Returns the chains - complete or not.
+    pub fn into_chains(self) -> Chains {
+        self.chains
+    }
+}
+
+/// Checks that the log density and its gradient are finite at `position` - the
+/// sampler cannot start from there otherwise.
+///
+/// The error names the parameter to blame: the first one outside of its
+/// support, or else the first one with a non-finite value or gradient.
+fn check_initial_position(model: &mut impl Model, position: &[f64]) -> Result<(), MyError> {
+    let mut grad = vec![0.; position.len()];
+    let finite = match model.logp(position, &mut grad) {
+        Ok(logp) => logp.is_finite() && grad.iter().all(|g| g.is_finite()),
+        Err(_) => false,
+    };
+    if finite {
+        return Ok(());
+    }
+
+    let parameters = model.parameters();
+    let culprit = position
+        .iter()
+        .zip(model.supports())
+        .position(|(x, support)| !support.contains(*x))
+        .or_else(|| {
+            position
+                .iter()
+                .zip(grad.iter())
+                .position(|(x, g)| !x.is_finite() || !g.is_finite())
+        });
+
+    Err(MyError::BadInitialPosition {
+        parameter: culprit.map(|i| parameters[i].clone()),
+        position: position.to_vec(),
+    })
+}
+
+/// Add Gaussian noise of standard deviation `scale` to each coordinate of the position.
+fn jitter(position: &[f64], scale: f64, seed: u64) -> Vec<f64> {
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    position
+        .iter()
+        .map(|x| {
+            let z: f64 = StandardNormal.sample(&mut rng);
+            x + scale * z
+        })
+        .collect()
+}
+
+/// Bounds of the axis of draws between `min_` and `max_` - floored and ceiled
+/// at the nearest 0.1.
+///
+/// The bounds are at least 0.1 apart so that a (nearly) constant parameter
+/// still gets a non-empty axis.
+pub(crate) fn axis_bounds(min_: f64, max_: f64) -> (f64, f64) {
+    let (min_, max_) = ((min_ * 10.).floor() / 10., (max_ * 10.).ceil() / 10.);
+
+    if max_ - min_ < 0.1 {
+        (min_ - 0.05, min_ + 0.05)
+    } else {
+        (min_, max_)
+    }
+}
+
+/// Returns the width of the bins of the histograms of draws between `min_` and
+/// `max_` - about 10 bins, closest power of 10 - and the height of the largest
+/// bin - at least 1 to keep the axis non-empty.
+pub(crate) fn histogram_bins(histograms: &[Vec<f64>], min_: f64, max_: f64) -> (f64, u32) {
+    let step = 10.0f64.powf((max_ - min_).log10().floor() - 1.);
+    let bins = (((max_ - min_) / step) as usize).max(1);
+
+    let max_height = histograms
+        .iter()
+        .map(|x| {
+            let mut counts = vec![0u32; bins];
+            for x in x.iter() {
+                let idx = usize::min(((x - min_) / step) as usize, counts.len() - 1);
+                counts[idx] += 1;
+            }
+            counts.iter().copied().max().unwrap()
+        })
+        .max()
+        .unwrap_or(0)
+        .max(1);
+
+    (step, max_height)
+}
+
+/// Settings of [`Chains::run`].
+#[derive(Clone, Debug, Default)]
+pub(crate) struct RunSettings {
+    /// Seed of the first chain - chain `i` is seeded with `seed + i`.
+    pub seed: u64,
+    /// Explicit seed of each chain - overrides `seed` when set.
+    pub seeds: Option<Vec<u64>>,
+    /// Number of chains
+    pub chain_count: u64,
+    /// Number of warmup draws per chain
+    pub tuning: u64,
+    /// Number of post-warmup draws per chain
+    pub samples: u64,
+    /// Starting point of the chains - [`Model::suggested_initial`] if not set
+    pub initial_position: Option<Vec<f64>>,
+    /// Standard deviation of the noise added to the starting point of each chain
+    pub jitter_scale: f64,
+    /// Time budget of the whole run in milliseconds - split evenly between
+    /// the chains. `samples` is then an upper bound.
+    pub max_millis: Option<f64>,
+    /// Memory budget of the traces in bytes - [`DEFAULT_MAX_TRACE_BYTES`] if
+    /// not set.
+    pub max_trace_bytes: Option<usize>,
+    /// Acceptance rate targeted by the adaptation of the step size - the
+    /// default of the sampler (0.8) if not set.
+    pub target_accept: Option<f64>,
+    /// Maximum depth of the trajectories: at most `2^maxdepth` steps - the
+    /// default of the sampler (10) if not set.
+    pub maxdepth: Option<u64>,
+    /// Energy error above which a draw is a divergence - the default of the
+    /// sampler (1000) if not set.
+    pub max_energy_error: Option<f64>,
+    /// Where the chains of a previous run stopped - overrides
+    /// `initial_position` and `jitter_scale`: each chain starts from the last
+    /// draw of a previous chain with its adapted step size.
+    pub sampler_state: Option<SamplerState>,
+}
+
+/// Default memory budget of the traces: 256 MiB.
+pub(crate) const DEFAULT_MAX_TRACE_BYTES: usize = 256 * 1024 * 1024;
+
+/// Largest maximum depth of the trajectories - up to a million steps per draw.
+const MAX_MAXDEPTH: u64 = 20;
+
+/// Fewest draws per chain - the split R-hat needs two draws per half-chain.
+pub(crate) const MIN_SAMPLES: u64 = 4;
+
+/// Split R-hat above which the chains have not converged.
+pub(crate) const R_HAT_THRESHOLD: f64 = 1.01;
+
+impl RunSettings {
+    /// Returns the seed of the given chain.
+    fn chain_seed(&self, chain: u64) -> u64 {
+        match &self.seeds {
+            Some(seeds) => seeds[chain as usize],
+            None => self.seed + chain,
+        }
+    }
+
+    /// Returns the step size the adaptation of the given chain starts from -
+    /// the one of the sampler state if any.
+    fn initial_step(&self, chain: usize) -> Option<f64> {
+        self.sampler_state
+            .as_ref()
+            .map(|state| state.step_sizes[chain])
+    }
+
+    /// Checks the seeds, the sampler state, the arguments of the sampler, the
+    /// number of draws and - before sampling anything - that the traces of a
+    /// model of dimension `dim` fit in the memory budget.
+    fn check(&self, dim: usize) -> Result<(), MyError> {
+        if let Some(seeds) = &self.seeds {
+            if seeds.len() as u64 != self.chain_count {
+                return Err(MyError::WrongSeedCount {
+                    expected: self.chain_count,
+                    actual: seeds.len(),
+                });
+            }
+        }
+
+        if let Some(state) = &self.sampler_state {
+            let chain_count = self.chain_count as usize;
+            if state.positions.len() != chain_count || state.step_sizes.len() != chain_count {
+                return Err(MyError::InvalidSamplerState(format!(
+                    "{} positions and {} step sizes for {} chains",
+                    state.positions.len(),
+                    state.step_sizes.len(),
+                    chain_count
+                )));
+            }
+            if let Some(position) = state.positions.iter().find(|p| p.len() != dim) {
+                return Err(MyError::InvalidSamplerState(format!(
+                    "a position has {} values for {} parameters",
+                    position.len(),
+                    dim
+                )));
+            }
+            if let Some(step_size) = state
+                .step_sizes
+                .iter()
+                .find(|s| !s.is_finite() || **s <= 0.)
+            {
+                return Err(MyError::InvalidSamplerState(format!(
+                    "the step size {} is not a positive number",
+                    step_size
+                )));
+            }
+        }
+
+        // the adaptation of nuts-rs panics without a single warmup draw
+        if self.tuning == 0 {
+            return Err(MyError::InvalidSamplerArgs(
+                "tuning = 0: at least one warmup draw is needed".to_string(),
+            ));
+        }
+        if let Some(target_accept) = self.target_accept {
+            if !(target_accept > 0. && target_accept < 1.) {
+                return Err(MyError::InvalidSamplerArgs(format!(
+                    "target_accept = {} is not in (0, 1)",
+                    target_accept
+                )));
+            }
+        }
+        if let Some(maxdepth) = self.maxdepth {
+            if !(1..=MAX_MAXDEPTH).contains(&maxdepth) {
+                return Err(MyError::InvalidSamplerArgs(format!(
+                    "maxdepth = {} is not in 1..={}",
+                    maxdepth, MAX_MAXDEPTH
+                )));
+            }
+        }
+        if let Some(max_energy_error) = self.max_energy_error {
+            if !max_energy_error.is_finite() || max_energy_error <= 0. {
+                return Err(MyError::InvalidSamplerArgs(format!(
+                    "max_energy_error = {} is not a positive number",
+                    max_energy_error
+                )));
+            }
+        }
+
+        if self.samples < MIN_SAMPLES {
+            return Err(MyError::NotEnoughDraws {
+                draws: self.samples as usize,
+                needed: MIN_SAMPLES as usize,
+            });
+        }
+
+        let bytes = self.trace_bytes(dim);
+        let budget = self.max_trace_bytes.unwrap_or(DEFAULT_MAX_TRACE_BYTES);
+        if bytes > budget {
+            return Err(MyError::TooManyDraws { bytes, budget });
+        }
+
+        Ok(())
+    }
+
+    /// Returns the starting point of each chain - jittered with a positive
+    /// `jitter_scale` - or the positions of the sampler state.
+    ///
+    /// Fails if the log density is not finite at one of them - see
+    /// [`check_initial_position`].
+    fn initial_positions(&self, model: &(impl Model + Clone)) -> Result<Vec<Vec<f64>>, MyError> {
+        if let Some(state) = &self.sampler_state {
+            let mut model = model.clone();
+            for position in &state.positions {
+                check_initial_position(&mut model, position)?;
+            }
+            return Ok(state.positions.clone());
+        }
+
+        let initial_position = self
+            .initial_position
+            .clone()
+            .unwrap_or_else(|| model.suggested_initial());
+
+        let positions = (0..self.chain_count)
             .map(|x| {
-                Run::default().run(
-                    model.clone(),
-                    seed + x,
-                    tuning,
-                    samples,
-                    initial_position.clone(),
-                )
+                if self.jitter_scale > 0. {
+                    jitter(&initial_position, self.jitter_scale, self.chain_seed(x))
+                } else {
+                    initial_position.clone()
+                }
             })
-            .collect();
+            .collect::<Vec<_>>();
+
+        let mut model = model.clone();
+        for position in &positions {
+            check_initial_position(&mut model, position)?;
+        }
+
+        Ok(positions)
+    }
+
+    /// Memory needed by the traces of a model of dimension `dim`:
+    /// `chain_count × samples × dim × 8` bytes (one f64 per parameter per draw).
+    fn trace_bytes(&self, dim: usize) -> usize {
+        [self.samples, dim as u64, std::mem::size_of::<f64>() as u64]
+            .iter()
+            .fold(self.chain_count, |acc, x| acc.saturating_mul(*x))
+            .min(usize::MAX as u64) as usize
+    }
+}
+
+impl Chains {
+    /// Runs a collection of chains - sequentially.
+    ///
+    /// Each chain is seeded with `seed + chain_id` or with its entry in
+    /// `seeds` - which must then have exactly one seed per chain.
+    ///
+    /// With a positive `jitter_scale`, each chain starts from `initial_position`
+    /// plus Gaussian noise of that standard deviation (seeded with the seed of
+    /// the chain). These overdispersed starts make R-hat trustworthy. The scale
+    /// must stay small compared to the support of constrained parameters (e.g.
+    /// sigma > 0).
+    ///
+    /// With `max_millis`, each chain stops drawing when its share of the budget
+    /// is exhausted and the chains are truncated to the shortest one.
+    ///
+    /// A chain stopped by an unrecoverable error of the sampler is left out -
+    /// the other chains go on - and its error is kept (see [`Chains::failures`]).
+    ///
+    /// Fails with [`MyError::TooManyDraws`] - before sampling anything - if the
+    /// traces would not fit in `max_trace_bytes`, or with
+    /// [`MyError::SamplingFailed`] if all the chains failed.
+    pub fn run(model: impl Model + Clone, settings: &RunSettings) -> Result<Self, MyError> {
+        settings.check(model.dim())?;
+
+        let max_millis = settings
+            .max_millis
+            .map(|budget| budget / settings.chain_count as f64);
+
+        let mut chains = vec![];
+        let mut failures = vec![];
+        for (x, initial_position) in settings.initial_positions(&model)?.into_iter().enumerate() {
+            let seed = settings.chain_seed(x as u64);
+            let (chain, failure) = Run::default().run(
+                model.clone(),
+                seed,
+                settings,
+                initial_position,
+                settings.initial_step(x),
+                max_millis,
+            );
+            match failure {
+                Some(failure) => failures.push(format!("chain {} stopped {}", x, failure)),
+                None => chains.push(chain),
+            }
+        }
+
+        if chains.is_empty() && !failures.is_empty() {
+            return Err(MyError::SamplingFailed(failures.join("; ")));
+        }
+
+        // the diagnostics need chains of the same length
+        let shortest = chains.iter().map(|c| c.trace.len()).min().unwrap_or(0);
+        for chain in chains.iter_mut() {
+            chain.truncate(shortest);
+        }
 
-        Chains {
+        Ok(Chains {
             chains,
             dim: model.dim(),
             parameters: model.parameters(),
+            supports: model.supports(),
+            failures,
+            display_names: None,
+        })
+    }
+
+    /// Runs the chains and - while more than `max_divergence_rate` of the draws
+    /// diverge - runs them again with a higher `target_accept` (halving the
+    /// distance to 1) and twice the tuning, up to `max_attempts` runs in total.
+    ///
+    /// This is the usual manual fix for divergences: smaller steps and a longer
+    /// adaptation. Returns the run with the fewest divergences and a note about
+    /// the retries.
+    pub fn run_with_retries(
+        model: impl Model + Clone,
+        settings: &RunSettings,
+        max_attempts: u32,
+        max_divergence_rate: f64,
+    ) -> Result<(Self, String), MyError> {
+        const DEFAULT_TARGET_ACCEPT: f64 = 0.8;
+
+        let mut settings = settings.clone();
+        let mut best = Chains::run(model.clone(), &settings)?;
+        let mut notes = vec![];
+
+        for attempt in 1..max_attempts.max(1) {
+            let divergences = best.divergence_count();
+            let draws = best.chains.len() * best.samples();
+            if divergences as f64 <= max_divergence_rate * draws as f64 {
+                break;
+            }
+
+            let target_accept = settings.target_accept.unwrap_or(DEFAULT_TARGET_ACCEPT);
+            settings.target_accept = Some(1. - (1. - target_accept) / 2.);
+            settings.tuning *= 2;
+            notes.push(format!(
+                "attempt {}: {} divergences - retrying with target_accept = {} and {} tuning draws",
+                attempt,
+                divergences,
+                settings.target_accept.unwrap(),
+                settings.tuning
+            ));
+
+            let chains = Chains::run(model.clone(), &settings)?;
+            if chains.divergence_count() < best.divergence_count() {
+                best = chains;
+            }
+        }
+
+        notes.push(format!(
+            "kept the run with {} divergences",
+            best.divergence_count()
+        ));
+
+        Ok((best, notes.join("\n")))
+    }
+
+    /// Returns the number of draws of each chain.
+    pub fn samples(&self) -> usize {
+        self.chains.first().map_or(0, |chain| chain.trace.len())
+    }
+
+    /// Returns why the chains left out of the run failed - one message per
+    /// failed chain.
+    pub fn failures(&self) -> &[String] {
+        &self.failures
+    }
+
+    /// Returns the chains that did not move: every parameter has a (near-)zero
+    /// variance along the trace - the chain is stuck at its initial position.
+    ///
+    /// Their draws are not from the posterior, and an ESS or an R-hat computed
+    /// with them is meaningless. A chain of a single draw is never stuck.
+    pub fn stuck_chains(&self) -> Vec<usize> {
+        // relative to the magnitude of the parameter
+        const TOLERANCE: f64 = 1e-12;
+
+        self.chains
+            .iter()
+            .enumerate()
+            .filter(|(_, chain)| {
+                chain.trace.len() > 1
+                    && (0..self.dim).all(|i| {
+                        let trace = chain.trace(i);
+                        sd(&trace) <= TOLERANCE * mean(&trace).abs().max(1.)
+                    })
+            })
+            .map(|(idx, _)| idx)
+            .collect()
+    }
+
+    /// Leaves out the chains that did not move (see [`Chains::stuck_chains`]):
+    /// they are reported along the [`Chains::failures`]. Returns how many
+    /// chains were left out.
+    ///
+    /// Fails with [`MyError::AllChainsStuck`] if no chain moved - the chains
+    /// are then left as they are.
+    pub fn drop_stuck_chains(&mut self) -> Result<usize, MyError> {
+        let stuck = self.stuck_chains();
+        if !stuck.is_empty() && stuck.len() == self.chains.len() {
+            return Err(MyError::AllChainsStuck);
         }
+
+        for idx in stuck.iter().rev() {
+            let chain = self.chains.remove(*idx);
+            self.failures.push(format!(
+                "a chain did not move from {:?}",
+                chain.trace.first().expect("A stuck chain has draws")
+            ));
+        }
+
+        Ok(stuck.len())
+    }
+
+    /// Shows the parameters under `names` - e.g. "warming_rate" for beta - in
+    /// the plots, the summaries and the exports. The internal names still
+    /// index the draws (see [`Chains::sample_posterior`]).
+    ///
+    /// Fails with [`MyError::WrongNameCount`] unless there is one name per
+    /// parameter.
+    pub fn set_display_names(&mut self, names: Vec<String>) -> Result<(), MyError> {
+        if names.len() != self.dim {
+            return Err(MyError::WrongNameCount {
+                expected: self.dim,
+                actual: names.len(),
+            });
+        }
+        self.display_names = Some(names);
+        Ok(())
+    }
+
+    /// Returns the name shown for each parameter - the internal name unless
+    /// set with [`Chains::set_display_names`].
+    pub fn display_names(&self) -> &[String] {
+        self.display_names.as_deref().unwrap_or(&self.parameters)
+    }
+
+    /// Returns the step size adapted during the warmup - for each chain.
+    ///
+    /// A very small step size is the sign of a difficult posterior geometry.
+    pub fn step_sizes(&self) -> Vec<f64> {
+        self.chains.iter().map(|chain| chain.step_size).collect()
+    }
+
+    /// Returns the number of divergent post-warmup draws - across all chains.
+    pub fn divergence_count(&self) -> usize {
+        self.chains.iter().map(|chain| chain.stats().len()).sum()
     }
 
     /// Returns the extrema for a given parameter - across all chains.
@@ -119,43 +1090,508 @@ impl Chains {
         self.chains.iter().map(|x| x.trace(i)).collect()
     }
 
-    /// Sample the posterior by picking a random sample from a random chain.
-    pub fn sample_posterior(&self, n: usize) -> HashMap<String, Vec<f64>> {
-        let mut rng = rand::thread_rng();
+    /// Returns the trace of a given parameter in a single chain.
+    ///
+    /// Fails if the chain or the parameter does not exist.
+    pub fn chain_trace(&self, chain_id: usize, parameter_idx: usize) -> Result<Vec<f64>, MyError> {
+        let chain = self.chains.get(chain_id).ok_or(MyError::UnknownChain {
+            chain_id,
+            chain_count: self.chains.len(),
+        })?;
 
-        let mut samples = HashMap::new();
+        if parameter_idx >= self.dim {
+            return Err(MyError::UnknownParameter {
+                parameter_idx,
+                dim: self.dim,
+            });
+        }
 
-        for _ in 0..n {
-            // pick a chain
-            let chain = self.chains.choose(&mut rng).unwrap();
+        Ok(chain.trace(parameter_idx))
+    }
 
-            // pick a sample
-            let sample = chain.trace.choose(&mut rng).unwrap();
+    /// Multiplies the draws of a given parameter by `factor` - e.g. to map a
+    /// coefficient sampled on a standardized scale back to the original one.
+    ///
+    /// The divergence locations are left on the sampling scale.
+    pub fn rescale(&mut self, parameter_idx: usize, factor: f64) {
+        for chain in self.chains.iter_mut() {
+            for draw in chain.trace.iter_mut() {
+                draw[parameter_idx] *= factor;
+            }
+        }
+    }
 
-            for (j, parameter) in self.parameters.iter().enumerate() {
-                samples
-                    .entry(parameter.clone())
-                    .or_insert_with(Vec::new)
-                    .push(sample[j]);
+    /// Adds `delta` to the draws of a given parameter - e.g. to move the
+    /// intercept of a regression with a fixed slope to another reference of
+    /// the predictor.
+    ///
+    /// The divergence locations are left on the sampling scale.
+    pub fn offset(&mut self, parameter_idx: usize, delta: f64) {
+        for chain in self.chains.iter_mut() {
+            for draw in chain.trace.iter_mut() {
+                draw[parameter_idx] += delta;
             }
         }
+    }
 
-        samples
+    /// Adds `factor` times the draws of parameter `by_idx` to the draws of
+    /// parameter `parameter_idx` - e.g. to move the intercept of a regression
+    /// to another reference of the predictor.
+    ///
+    /// The divergence locations are left on the sampling scale.
+    pub fn shift(&mut self, parameter_idx: usize, by_idx: usize, factor: f64) {
+        for chain in self.chains.iter_mut() {
+            for draw in chain.trace.iter_mut() {
+                draw[parameter_idx] += factor * draw[by_idx];
+            }
+        }
     }
 
-    /// Plot the traces and histograms for all parameters.
-    pub(crate) fn plot(&self, canvas_id: &str, chains: &Chains, samples: u64) {
-        let backend = CanvasBackend::new(canvas_id).expect("cannot find canvas");
-        let root = backend.into_drawing_area();
+    /// Returns the positions where the divergent trajectories started - across
+    /// all chains.
+    ///
+    /// Overlaid on a pair plot, they show where the geometry of the posterior
+    /// is hard for the sampler (e.g. the neck of a funnel).
+    pub fn divergence_locations(&self) -> Vec<Vec<f64>> {
+        self.chains
+            .iter()
+            .flat_map(|chain| chain.stats())
+            .filter_map(|info| info.start_location.as_ref())
+            .map(|location| location.to_vec())
+            .collect()
+    }
 
-        root.fill(&WHITE).unwrap();
+    /// Returns the mean of a given parameter - for each chain.
+    pub fn chain_means(&self, parameter_idx: usize) -> Vec<f64> {
+        self.traces(parameter_idx)
+            .iter()
+            .map(|trace| mean(trace))
+            .collect()
+    }
 
-        // split into DIMS horizontal subplots and 2 vertical subplots
-        let subplots = root.split_evenly((self.dim, 2));
+    /// Whether the chain means of a given parameter are spread by more than
+    /// `threshold` times the within-chain standard deviation.
+    ///
+    /// Chains exploring the same posterior have means much closer to each other
+    /// than their spread - a simple precursor to R-hat.
+    pub fn overdispersed(&self, parameter_idx: usize, threshold: f64) -> bool {
+        let means = self.chain_means(parameter_idx);
+        if means.len() < 2 {
+            return false;
+        }
+
+        let between_sd = sd(&means);
+        let within_sd = mean(
+            &self
+                .traces(parameter_idx)
+                .iter()
+                .map(|trace| sd(trace))
+                .collect::<Vec<_>>(),
+        );
+
+        between_sd > threshold * within_sd
+    }
+
+    /// Returns the fraction of the draws of a given parameter equal to the
+    /// previous draw of their chain - across all chains.
+    ///
+    /// A rejected trajectory repeats the current position: a high fraction
+    /// means a poor acceptance and spikes in the histograms.
+    pub fn repeat_fraction(&self, parameter_idx: usize) -> f64 {
+        let (repeats, pairs) = self
+            .traces(parameter_idx)
+            .iter()
+            .map(|trace| {
+                let repeats = trace.windows(2).filter(|w| w[0] == w[1]).count();
+                (repeats, trace.len().saturating_sub(1))
+            })
+            .fold((0, 0), |(r, p), (repeats, pairs)| (r + repeats, p + pairs));
+
+        if pairs == 0 {
+            0.
+        } else {
+            repeats as f64 / pairs as f64
+        }
+    }
+
+    /// Returns the effective sample size of a given parameter - across all chains:
+    /// the number of independent draws worth the draws of the chains.
+    ///
+    /// Works with a single chain - and on very short traces, where the draws
+    /// are counted as independent (see [`ess`]).
+    pub fn effective_sample_size(&self, parameter_idx: usize) -> f64 {
+        ess(&self.traces(parameter_idx))
+    }
+
+    /// Returns the integrated autocorrelation time of a given parameter -
+    /// across all chains: the number of draws worth one independent draw.
+    pub fn integrated_autocorr_time(&self, parameter_idx: usize) -> f64 {
+        integrated_autocorr_time(&self.traces(parameter_idx))
+    }
+
+    /// Returns the thinning interval that leaves roughly independent draws of
+    /// a given parameter - the integrated autocorrelation time rounded up.
+    pub fn suggested_thin(&self, parameter_idx: usize) -> usize {
+        let tau = self.integrated_autocorr_time(parameter_idx);
+        if tau.is_finite() {
+            (tau.ceil() as usize).max(1)
+        } else {
+            1
+        }
+    }
+
+    /// Returns the split R-hat of a given parameter - across all chains.
+    pub fn r_hat(&self, parameter_idx: usize) -> f64 {
+        split_r_hat(&self.traces(parameter_idx))
+    }
+
+    /// Split R-hat and effective sample size of each parameter as a CSV:
+    /// "parameter,R_HAT,ESS,converged".
+    ///
+    /// `converged` is false above [`R_HAT_THRESHOLD`].
+    pub fn convergence_csv(&self) -> String {
+        let mut csv = String::from("parameter,R_HAT,ESS,converged\n");
+
+        for (i, parameter) in self.display_names().iter().enumerate() {
+            let r_hat = self.r_hat(i);
+            csv.push_str(
+                format!(
+                    "{},{},{},{}\n",
+                    parameter,
+                    r_hat,
+                    self.effective_sample_size(i),
+                    r_hat <= R_HAT_THRESHOLD
+                )
+                .as_str(),
+            );
+        }
+
+        csv
+    }
+
+    /// Returns the Monte Carlo standard error of the posterior mean of a given
+    /// parameter: `sd / sqrt(ess)`.
+    ///
+    /// The posterior mean is only known within a few MCSE - quadrupling the
+    /// effective number of draws halves it.
+    pub fn mcse_mean(&self, parameter_idx: usize) -> f64 {
+        let draws = self.traces(parameter_idx).concat();
+
+        sd(&draws) / self.effective_sample_size(parameter_idx).sqrt()
+    }
+
+    /// Summary of the posterior of each parameter as a CSV with the header:
+    /// "parameter,mean,mcse_mean,sd,q2.5,q50,q97.5,hdi95_low,hdi95_high,mode,support"
+    ///
+    /// The quantiles give the equal-tailed 95% interval, the HDI the narrowest
+    /// 95% interval - they differ for skewed posteriors. The HDI of a
+    /// constrained parameter is computed on the unconstrained scale (see
+    /// [`Support::unconstrain`]) so that it stays within its support. The mode
+    /// is the peak of a kernel density estimate - it differs from the mean for
+    /// skewed posteriors such as sigma's.
+    pub fn summary(&self) -> String {
+        let mut summary = String::from(
+            "parameter,mean,mcse_mean,sd,q2.5,q50,q97.5,hdi95_low,hdi95_high,mode,support\n",
+        );
+
+        for (i, parameter) in self.display_names().iter().enumerate() {
+            let draws = self.traces(i).concat();
+            let support = self.supports[i];
+
+            let unconstrained = draws
+                .iter()
+                .map(|x| support.unconstrain(*x))
+                .collect::<Vec<_>>();
+            let (hdi_low, hdi_high) = hdi(&unconstrained, 0.95);
+            let (hdi_low, hdi_high) = (support.constrain(hdi_low), support.constrain(hdi_high));
+
+            summary.push_str(
+                format!(
+                    "{},{},{},{},{},{},{},{},{},{},{}\n",
+                    parameter,
+                    mean(&draws),
+                    self.mcse_mean(i),
+                    sd(&draws),
+                    quantile(&draws, 0.025),
+                    quantile(&draws, 0.5),
+                    quantile(&draws, 0.975),
+                    hdi_low,
+                    hdi_high,
+                    posterior_mode(&draws),
+                    support.name()
+                )
+                .as_str(),
+            );
+        }
+
+        summary
+    }
+
+    /// Diagnostics of each parameter as a CSV in the layout of Stan's summary:
+    /// "parameter,mean,se_mean,sd,2.5%,25%,50%,75%,97.5%,n_eff,Rhat"
+    ///
+    /// `se_mean` is the Monte Carlo standard error of the mean, `n_eff` the
+    /// effective sample size and `Rhat` the split R-hat.
+    pub fn diagnostics_csv(&self) -> String {
+        let mut csv = String::from("parameter,mean,se_mean,sd,2.5%,25%,50%,75%,97.5%,n_eff,Rhat\n");
+
+        for (i, parameter) in self.display_names().iter().enumerate() {
+            let draws = self.traces(i).concat();
+
+            let mut row = vec![
+                parameter.clone(),
+                mean(&draws).to_string(),
+                self.mcse_mean(i).to_string(),
+                sd(&draws).to_string(),
+            ];
+            row.extend(
+                [0.025, 0.25, 0.5, 0.75, 0.975]
+                    .iter()
+                    .map(|q| quantile(&draws, *q).to_string()),
+            );
+            row.push(self.effective_sample_size(i).to_string());
+            row.push(self.r_hat(i).to_string());
+
+            csv.push_str(row.join(",").as_str());
+            csv.push('\n');
+        }
+
+        csv
+    }
+
+    /// Returns the means, the 95% intervals (as in [`Chains::summary`]) and the
+    /// diagnostics of the parameters - with the `warnings` of the run.
+    pub(crate) fn fit_result(&self, warnings: Vec<String>) -> FitResult {
+        let draws = (0..self.parameters.len())
+            .map(|i| self.traces(i).concat())
+            .collect::<Vec<_>>();
+
+        FitResult {
+            parameters: self.display_names().to_vec(),
+            means: draws.iter().map(|draws| mean(draws)).collect(),
+            intervals: draws
+                .iter()
+                .map(|draws| Interval {
+                    low: quantile(draws, 0.025),
+                    high: quantile(draws, 0.975),
+                })
+                .collect(),
+            diagnostics: (0..self.parameters.len())
+                .map(|i| Diagnostics {
+                    ess: self.effective_sample_size(i),
+                    mcse_mean: self.mcse_mean(i),
+                    repeat_fraction: self.repeat_fraction(i),
+                })
+                .collect(),
+            warnings,
+        }
+    }
+
+    /// Returns the draws of the chains, `n` posterior draws picked with `seed`
+    /// (the ones of [`Chains::sample_posterior`]) and the split R-hat of each
+    /// parameter.
+    pub(crate) fn run_draws(&self, n: usize, seed: u64) -> RunDraws {
+        let posterior = self.sample_posterior(n, seed);
+
+        RunDraws {
+            parameters: self.display_names().to_vec(),
+            chains: self
+                .chains
+                .iter()
+                .map(|chain| chain.trace.iter().map(|draw| draw.to_vec()).collect())
+                .collect(),
+            posterior_samples: (0..n)
+                .map(|i| {
+                    self.parameters
+                        .iter()
+                        .map(|parameter| posterior[parameter][i])
+                        .collect()
+                })
+                .collect(),
+            r_hat: self
+                .display_names()
+                .iter()
+                .enumerate()
+                .map(|(i, name)| (name.clone(), self.r_hat(i)))
+                .collect(),
+        }
+    }
+
+    /// Returns where the chains stopped: the last draw and the adapted step
+    /// size of each chain - see [`RunSettings::sampler_state`].
+    pub(crate) fn sampler_state(&self) -> SamplerState {
+        SamplerState {
+            positions: self
+                .chains
+                .iter()
+                .filter_map(|chain| chain.trace.last())
+                .map(|draw| draw.to_vec())
+                .collect(),
+            step_sizes: self.chains.iter().map(|chain| chain.step_size).collect(),
+        }
+    }
+
+    /// Returns how the adaptation of each chain went: the warmup is split into
+    /// `phases` consecutive windows of (nearly) equal length, each with its
+    /// mean acceptance rate and its final step size, to compare with the
+    /// acceptance rate of the post-warmup draws.
+    ///
+    /// An acceptance rate of the sampling far from the target of the adaptation
+    /// (0.8 by default) or a step size still moving in the last window suggests
+    /// that the warmup was too short.
+    pub(crate) fn adaptation(&self, phases: usize) -> Vec<Adaptation> {
+        let accept =
+            |stats: &[DrawStats]| mean(&stats.iter().map(|stats| stats.accept).collect::<Vec<_>>());
+
+        self.chains
+            .iter()
+            .map(|chain| {
+                let window = chain.warmup.len().div_ceil(phases.max(1)).max(1);
+                Adaptation {
+                    warmup: chain
+                        .warmup
+                        .chunks(window)
+                        .map(|stats| WarmupPhase {
+                            draws: stats.len(),
+                            accept: accept(stats),
+                            step_size: stats[stats.len() - 1].step_size,
+                        })
+                        .collect(),
+                    step_size: chain.step_size,
+                    sampling_accept: accept(&chain.sampling),
+                }
+            })
+            .collect()
+    }
+
+    /// Export the draws as JSON in the shape expected by ArviZ's `from_dict`.
+    ///
+    /// Each parameter maps to an array of dimensions (chain, draw):
+    /// `{"posterior": {"alpha": [[...], [...]], ...}}`
+    ///
+    /// In Python: `az.from_dict(**json.loads(s))`.
+    pub fn to_arviz_json(&self) -> String {
+        let posterior = self
+            .display_names()
+            .iter()
+            .enumerate()
+            .map(|(i, parameter)| (parameter.clone(), serde_json::json!(self.traces(i))))
+            .collect::<serde_json::Map<_, _>>();
+
+        serde_json::json!({ "posterior": posterior }).to_string()
+    }
+
+    /// Returns all the draws in a flat array of dimensions (chain, draw, parameter).
+    ///
+    /// The value of parameter `p` in draw `d` of chain `c` is at index
+    /// `(c * n_draws + d) * dim + p` - see [`Chains::shape`].
+    pub fn draws_flat(&self) -> Vec<f64> {
+        self.chains
+            .iter()
+            .flat_map(|chain| chain.trace.iter())
+            .flat_map(|draw| draw.iter().copied())
+            .collect()
+    }
+
+    /// Estimates the log marginal likelihood of the model by bridge sampling -
+    /// see [`bridge::log_marginal_likelihood`]. The draws of all the chains are
+    /// pooled.
+    ///
+    /// `log_joint` is the normalized log prior plus log likelihood of the model
+    /// (see [`Model::log_joint`]).
+    pub fn log_marginal_likelihood(
+        &self,
+        log_joint: impl Fn(&[f64]) -> Option<f64>,
+        seed: u64,
+    ) -> Result<f64, MyError> {
+        let draws = self
+            .chains
+            .iter()
+            .flat_map(|chain| chain.trace.iter())
+            .map(|draw| draw.to_vec())
+            .collect::<Vec<_>>();
+
+        bridge::log_marginal_likelihood(&draws, &self.supports, log_joint, seed)
+    }
+
+    /// Returns the (chains, draws, parameters) dimensions of [`Chains::draws_flat`].
+    pub fn shape(&self) -> (usize, usize, usize) {
+        (self.chains.len(), self.samples(), self.dim)
+    }
+
+    /// Sample the posterior by picking a random sample from a random chain.
+    ///
+    /// The picks are drawn with `seed`: the same seed picks the same draws.
+    pub fn sample_posterior(&self, n: usize, seed: u64) -> HashMap<String, Vec<f64>> {
+        let mut rng = StdRng::seed_from_u64(seed);
+
+        let mut samples = HashMap::new();
+
+        for _ in 0..n {
+            // pick a chain
+            let chain = self.chains.choose(&mut rng).unwrap();
+
+            // pick a sample
+            let sample = chain.trace.choose(&mut rng).unwrap();
+
+            for (j, parameter) in self.parameters.iter().enumerate() {
+                samples
+                    .entry(parameter.clone())
+                    .or_insert_with(Vec::new)
+                    .push(sample[j]);
+            }
+        }
+
+        samples
+    }
+
+    /// Plot the traces and histograms for all parameters.
+    ///
+    /// The histograms are drawn per chain or pooled across chains depending on `histogram_mode`.
+    /// The panels and their arrangement are set by `layout`. The captions are
+    /// prefixed with `title_prefix`.
+    ///
+    /// Fails if the canvas is missing or has no 2D context.
+    pub(crate) fn plot(
+        &self,
+        canvas_id: &str,
+        chains: &Chains,
+        samples: u64,
+        histogram_mode: HistogramMode,
+        layout: PlotLayout,
+        title_prefix: &str,
+    ) -> Result<(), MyError> {
+        self.draw(
+            &canvas_area(canvas_id)?,
+            chains,
+            samples,
+            histogram_mode,
+            layout,
+            title_prefix,
+        )
+    }
+
+    /// Draw the traces and histograms on `root` - see [`Self::plot`].
+    pub(crate) fn draw<DB: DrawingBackend>(
+        &self,
+        root: &DrawingArea<DB, Shift>,
+        chains: &Chains,
+        samples: u64,
+        histogram_mode: HistogramMode,
+        layout: PlotLayout,
+        title_prefix: &str,
+    ) -> Result<(), MyError> {
+        root.fill(&WHITE).unwrap();
+
+        // one row per group of `columns` parameters, each parameter spans its
+        // panels - (DIMS, 2) by default
+        let (rows, columns) = layout.grid(self.dim);
+        let subplots = root.split_evenly((rows, columns));
+        let panel_count = layout.panels.count();
 
         let colors = [RED, GREEN, BLUE, MAGENTA, CYAN, YELLOW];
 
-        let parameters = self.parameters.clone();
+        let parameters = self.display_names().to_vec();
 
         // plot the histogram and traces
         for parameter_idx in 0..self.dim {
@@ -164,117 +1600,1152 @@ impl Chains {
 
             let param_traces = chains.traces(parameter_idx);
 
-            // ceil and floor at the nearest 0.1
-            let (min_, max_) = ((min_ * 10.).floor() / 10., (max_ * 10.).ceil() / 10.);
-            // let (min_, max_) = (min_.floor(), max_.ceil());
+            // the panels of this parameter - consecutive in the grid
+            let mut panels =
+                subplots[panel_count * parameter_idx..panel_count * (parameter_idx + 1)].iter();
+
+            let (min_, max_) = axis_bounds(min_, max_);
 
             log(format!(
                 "parameter {}: min_ = {}, max_ = {}",
                 parameter_idx, min_, max_
             )
             .as_str());
-            // step size - about 10 bins between min_ and max_ - closest power of 10
-            let step = 10.0f64.powf((max_ - min_).log10().floor() - 1.);
+            if layout.panels.has_histogram() {
+                // constrained parameters are binned on their unconstrained scale -
+                // log-spaced bins for sigma
+                let support = self.supports[parameter_idx];
+                let binned_traces = param_traces
+                    .iter()
+                    .map(|trace| {
+                        trace
+                            .iter()
+                            .map(|x| support.unconstrain(*x))
+                            .collect::<Vec<_>>()
+                    })
+                    .collect::<Vec<_>>();
 
-            // compute the height of the largest bin in the histogram
-            let max_height = param_traces
-                .iter()
-                .map(|x| {
-                    let mut counts = vec![0u32; ((max_ - min_) / step) as usize];
-                    for x in x.iter() {
-                        let idx = usize::min(((x - min_) / step) as usize, counts.len() - 1);
-                        counts[idx] += 1;
+                let (min_, max_) = if support == Support::Real {
+                    (min_, max_)
+                } else {
+                    let draws = binned_traces.concat();
+                    let min_ = draws.iter().copied().fold(f64::INFINITY, f64::min);
+                    let max_ = draws.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+                    axis_bounds(min_, max_)
+                };
+
+                // the draws to histogram - one series per chain or all the chains pooled together
+                let histograms = match histogram_mode {
+                    HistogramMode::PerChain => binned_traces,
+                    HistogramMode::Pooled | HistogramMode::PooledWithNormal => {
+                        vec![binned_traces.concat()]
                     }
-                    counts.iter().copied().max().unwrap()
-                })
-                .max()
-                .unwrap();
-
-            // plot the histogram
-            let root = &subplots[2 * parameter_idx];
-
-            root.fill(&WHITE).unwrap();
-
-            let mut chart = ChartBuilder::on(root)
-                .margin(5)
-                .caption(format!("{parameter} (posterior)"), ("sans-serif", 30))
-                .set_label_area_size(LabelAreaPosition::Left, 70)
-                .set_label_area_size(LabelAreaPosition::Bottom, 30)
-                // .set_label_area_size(LabelAreaPosition::Right, 60)
-                .build_cartesian_2d((min_..max_).step(step).use_round(), 0..max_height)
-                .unwrap();
-
-            chart
-                .configure_mesh()
-                .disable_x_mesh()
-                .disable_y_mesh()
-                .y_desc("Count")
-                .y_label_style(TextStyle::from(("sans-serif", 20)).color(&BLACK))
-                .x_label_style(TextStyle::from(("sans-serif", 20)).color(&BLACK))
-                .draw()
-                .unwrap();
-
-            for (chain, param_trace) in param_traces.iter().enumerate() {
-                let color = colors[chain % colors.len()];
-                let style = color.mix(0.2).filled();
-
-                let actual = Histogram::vertical(&chart)
-                    .style(style)
-                    .data(param_trace.iter().map(|x| (*x, 1)));
+                };
 
-                chart
-                    .draw_series(actual)
-                    .unwrap()
-                    .label(format!("Chain {chain}"))
-                    .legend(move |(x, y)| Rectangle::new([(x, y - 5), (x + 10, y + 5)], style));
+                let (step, max_height) = histogram_bins(&histograms, min_, max_);
+
+                // plot the histogram
+                let root = panels.next().unwrap();
+
+                root.fill(&WHITE).unwrap();
+
+                let mut chart = ChartBuilder::on(root)
+                    .margin(5)
+                    .caption(
+                        match support {
+                            Support::Real => format!("{title_prefix}{parameter} (posterior)"),
+                            Support::Positive => {
+                                format!("{title_prefix}{parameter} (posterior, log scale)")
+                            }
+                            Support::UnitInterval => {
+                                format!("{title_prefix}{parameter} (posterior, logit scale)")
+                            }
+                        },
+                        ("sans-serif", 30),
+                    )
+                    .set_label_area_size(LabelAreaPosition::Left, 70)
+                    .set_label_area_size(LabelAreaPosition::Bottom, 30)
+                    // .set_label_area_size(LabelAreaPosition::Right, 60)
+                    .build_cartesian_2d(
+                        (min_..max_).step(step).use_round(),
+                        0f64..max_height as f64,
+                    )
+                    .unwrap();
+
+                // label the unconstrained axis with the values of the parameter
+                let constrained_label = |x: &f64| format!("{:.3}", support.constrain(*x));
+
+                let mut mesh = chart.configure_mesh();
+                mesh.disable_x_mesh()
+                    .disable_y_mesh()
+                    .y_desc("Count")
+                    .y_label_style(TextStyle::from(("sans-serif", 20)).color(&BLACK))
+                    .x_label_style(TextStyle::from(("sans-serif", 20)).color(&BLACK));
+                if support != Support::Real {
+                    mesh.x_label_formatter(&constrained_label);
+                }
+                mesh.draw().unwrap();
+
+                for (chain, param_trace) in histograms.iter().enumerate() {
+                    let color = colors[chain % colors.len()];
+                    let style = color.mix(0.2).filled();
+
+                    let actual = Histogram::vertical(&chart)
+                        .style(style)
+                        .data(param_trace.iter().map(|x| (*x, 1.)));
+
+                    let label = match histogram_mode {
+                        HistogramMode::PerChain => format!("Chain {chain}"),
+                        HistogramMode::Pooled | HistogramMode::PooledWithNormal => {
+                            String::from("All chains")
+                        }
+                    };
+
+                    chart
+                        .draw_series(actual)
+                        .unwrap()
+                        .label(label)
+                        .legend(move |(x, y)| Rectangle::new([(x, y - 5), (x + 10, y + 5)], style));
+                }
+
+                if histogram_mode == HistogramMode::PooledWithNormal {
+                    let draws = &histograms[0];
+                    let n = draws.len() as f64;
+                    let (mean, sd) = (mean(draws), sd(draws));
+
+                    // expected count in a bin of width `step` around x
+                    let expected_count = |x: f64| {
+                        n * step * (-0.5 * ((x - mean) / sd).powi(2)).exp()
+                            / (sd * (2. * std::f64::consts::PI).sqrt())
+                    };
+
+                    const POINTS: usize = 100;
+                    chart
+                        .draw_series(LineSeries::new(
+                            (0..=POINTS).map(|i| {
+                                let x = min_ + (max_ - min_) * i as f64 / POINTS as f64;
+                                (x, expected_count(x))
+                            }),
+                            Into::<ShapeStyle>::into(BLACK).stroke_width(2),
+                        ))
+                        .unwrap()
+                        .label(format!("N({mean:.3}, {sd:.3})"))
+                        .legend(move |(x, y)| {
+                            Rectangle::new([(x, y - 1), (x + 10, y + 1)], BLACK.filled())
+                        });
+                }
+                if let Some(position) = layout.legend.position() {
+                    chart
+                        .configure_series_labels()
+                        .position(position)
+                        .draw()
+                        .unwrap();
+                }
             }
-            chart.configure_series_labels().draw().unwrap();
-
-            // plot the trace
-            let mut chart = ChartBuilder::on(&subplots[2 * parameter_idx + 1])
-                .margin(5)
-                .caption(format!("{parameter} (trace)"), ("sans-serif", 30))
-                .x_label_area_size(30)
-                .y_label_area_size(30)
-                .set_label_area_size(LabelAreaPosition::Right, 70)
-                .set_label_area_size(LabelAreaPosition::Bottom, 30)
-                .build_cartesian_2d(0f64..samples as f64, min_..max_)
-                .unwrap();
-
-            chart
-                .configure_mesh()
-                .x_labels(3)
-                .y_labels(3)
-                .x_label_style(TextStyle::from(("sans-serif", 20)).color(&BLACK))
-                .y_label_style(TextStyle::from(("sans-serif", 20)).color(&BLACK))
-                .draw()
-                .unwrap();
-
-            for (chain, param_trace) in param_traces.iter().enumerate() {
-                let color = colors[chain % colors.len()];
+
+            if layout.panels.has_trace() {
+                // plot the trace
+                let mut chart = ChartBuilder::on(panels.next().unwrap())
+                    .margin(5)
+                    .caption(
+                        format!("{title_prefix}{parameter} (trace)"),
+                        ("sans-serif", 30),
+                    )
+                    .x_label_area_size(30)
+                    .y_label_area_size(30)
+                    .set_label_area_size(LabelAreaPosition::Right, 70)
+                    .set_label_area_size(LabelAreaPosition::Bottom, 30)
+                    .build_cartesian_2d(0f64..samples as f64, min_..max_)
+                    .unwrap();
 
                 chart
-                    .draw_series(LineSeries::new(
-                        (0..samples)
-                            .zip(param_trace.iter())
-                            .map(|(i, x)| (i as f64, *x)),
-                        Into::<ShapeStyle>::into(color).stroke_width(1),
-                    ))
-                    .unwrap()
-                    .label(format!("Chain {chain}"))
-                    .legend(move |(x, y)| {
-                        Rectangle::new([(x, y - 5), (x + 10, y + 5)], color.filled())
-                    });
-            }
+                    .configure_mesh()
+                    .x_labels(3)
+                    .y_labels(3)
+                    .x_label_style(TextStyle::from(("sans-serif", 20)).color(&BLACK))
+                    .y_label_style(TextStyle::from(("sans-serif", 20)).color(&BLACK))
+                    .draw()
+                    .unwrap();
+
+                for (chain, param_trace) in param_traces.iter().enumerate() {
+                    let color = colors[chain % colors.len()];
+
+                    chart
+                        .draw_series(LineSeries::new(
+                            (0..samples)
+                                .zip(param_trace.iter())
+                                .map(|(i, x)| (i as f64, *x)),
+                            Into::<ShapeStyle>::into(color).stroke_width(1),
+                        ))
+                        .unwrap()
+                        .label(format!("Chain {chain}"))
+                        .legend(move |(x, y)| {
+                            Rectangle::new([(x, y - 5), (x + 10, y + 5)], color.filled())
+                        });
+                }
+
+                if layout.running_mean {
+                    // bold, atop the raw traces
+                    for (chain, param_trace) in param_traces.iter().enumerate() {
+                        let color = colors[chain % colors.len()];
 
-            chart
-                .configure_series_labels()
-                .background_style(WHITE.mix(0.8))
-                .border_style(BLACK)
-                .draw()
-                .unwrap();
+                        let c = chart
+                            .draw_series(LineSeries::new(
+                                running_mean(param_trace)
+                                    .into_iter()
+                                    .enumerate()
+                                    .map(|(i, x)| (i as f64, x)),
+                                Into::<ShapeStyle>::into(color.mix(0.9)).stroke_width(3),
+                            ))
+                            .unwrap();
+                        if chain == 0 {
+                            c.label("Running mean").legend(move |(x, y)| {
+                                PathElement::new(
+                                    vec![(x, y), (x + 10, y)],
+                                    Into::<ShapeStyle>::into(BLACK).stroke_width(3),
+                                )
+                            });
+                        }
+                    }
+                }
+
+                if let Some(position) = layout.legend.position() {
+                    chart
+                        .configure_series_labels()
+                        .position(position)
+                        .background_style(WHITE.mix(0.8))
+                        .border_style(BLACK)
+                        .draw()
+                        .unwrap();
+                }
+            }
         }
 
         root.present().unwrap();
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chain_run(trace: Vec<Box<[f64]>>, stats: Vec<MyDivergenceInfo>) -> ChainRun {
+        ChainRun {
+            trace,
+            stats,
+            step_size: 0.1,
+            warmup: vec![],
+            sampling: vec![],
+        }
+    }
+
+    /// A standard normal whose log density fails - unrecoverably - once its
+    /// clones have been evaluated `budget` times in total.
+    #[derive(Clone)]
+    struct FailingModel {
+        calls: std::sync::Arc<std::sync::atomic::AtomicUsize>,
+        budget: usize,
+    }
+
+    #[derive(Debug)]
+    struct Exhausted;
+
+    impl std::fmt::Display for Exhausted {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            write!(f, "exhausted")
+        }
+    }
+
+    impl std::error::Error for Exhausted {}
+
+    impl nuts_rs::LogpError for Exhausted {
+        fn is_recoverable(&self) -> bool {
+            false
+        }
+    }
+
+    impl CpuLogpFunc for FailingModel {
+        type Err = Exhausted;
+
+        fn dim(&self) -> usize {
+            1
+        }
+
+        fn logp(&mut self, position: &[f64], grad: &mut [f64]) -> Result<f64, Self::Err> {
+            let calls = self
+                .calls
+                .fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+                + 1;
+            if calls > self.budget {
+                return Err(Exhausted);
+            }
+            grad[0] = -position[0];
+            Ok(-0.5 * position[0] * position[0])
+        }
+    }
+
+    impl Model for FailingModel {
+        fn parameters(&self) -> Vec<String> {
+            vec![String::from("x")]
+        }
+    }
+
+    /// A standard normal with a pit around 10: the log density is only
+    /// defined at 10 itself - a chain starting there cannot move.
+    #[derive(Clone)]
+    struct PitModel;
+
+    #[derive(Debug)]
+    struct InPit;
+
+    impl std::fmt::Display for InPit {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            write!(f, "in the pit")
+        }
+    }
+
+    impl std::error::Error for InPit {}
+
+    impl nuts_rs::LogpError for InPit {
+        fn is_recoverable(&self) -> bool {
+            true
+        }
+    }
+
+    impl CpuLogpFunc for PitModel {
+        type Err = InPit;
+
+        fn dim(&self) -> usize {
+            1
+        }
+
+        fn logp(&mut self, position: &[f64], grad: &mut [f64]) -> Result<f64, Self::Err> {
+            let x = position[0];
+            if (x - 10.).abs() < 5. && x != 10. {
+                return Err(InPit);
+            }
+            grad[0] = -x;
+            Ok(-0.5 * x * x)
+        }
+    }
+
+    impl Model for PitModel {
+        fn parameters(&self) -> Vec<String> {
+            vec![String::from("x")]
+        }
+    }
+
+    #[test]
+    fn test_stuck_chains() {
+        let settings = RunSettings {
+            chain_count: 2,
+            tuning: 50,
+            samples: 50,
+            // the first chain starts in the pit - the second one off the mode:
+            // nuts-rs rejects a zero gradient at the start
+            sampler_state: Some(SamplerState {
+                positions: vec![vec![10.], vec![1.]],
+                step_sizes: vec![0.5, 0.5],
+            }),
+            ..Default::default()
+        };
+        let mut chains = Chains::run(PitModel, &settings).unwrap();
+        assert_eq!(chains.stuck_chains(), vec![0]);
+
+        assert_eq!(chains.drop_stuck_chains().unwrap(), 1);
+        assert_eq!(chains.chains.len(), 1);
+        assert!(chains.stuck_chains().is_empty());
+        assert_eq!(chains.failures(), ["a chain did not move from [10.0]"]);
+
+        // nothing to pool
+        let settings = RunSettings {
+            sampler_state: Some(SamplerState {
+                positions: vec![vec![10.], vec![10.]],
+                step_sizes: vec![0.5, 0.5],
+            }),
+            ..settings
+        };
+        let mut chains = Chains::run(PitModel, &settings).unwrap();
+        assert!(matches!(
+            chains.drop_stuck_chains(),
+            Err(MyError::AllChainsStuck)
+        ));
+        assert_eq!(chains.chains.len(), 2);
+    }
+
+    #[test]
+    fn test_failed_chain() {
+        let model = |budget| FailingModel {
+            calls: Default::default(),
+            budget,
+        };
+        let settings = RunSettings {
+            seed: 3,
+            chain_count: 1,
+            tuning: 100,
+            samples: 100,
+            // off the mode: nuts-rs rejects a zero gradient at the start
+            initial_position: Some(vec![1.]),
+            ..Default::default()
+        };
+
+        // the evaluations of a complete chain
+        let complete = model(usize::MAX);
+        Chains::run(complete.clone(), &settings).unwrap();
+        let calls = complete.calls.load(std::sync::atomic::Ordering::Relaxed);
+
+        // the 2nd chain runs out of evaluations during its warmup: it is left out
+        let settings = RunSettings {
+            chain_count: 2,
+            ..settings
+        };
+        let chains = Chains::run(model(calls + 50), &settings).unwrap();
+        assert_eq!(chains.traces(0).len(), 1);
+        assert_eq!(chains.samples(), 100);
+        assert_eq!(chains.failures().len(), 1);
+        assert!(
+            chains.failures()[0].starts_with("chain 1 stopped during warmup"),
+            "{}",
+            chains.failures()[0]
+        );
+
+        // the draws made before an error after the warmup are returned
+        let draws = |model| be_nuts(model, 100, 100, &[1.], 3, None, None, None, None, None);
+        let complete = model(usize::MAX);
+        assert_eq!(draws(complete.clone()).trace.len(), 100);
+        let calls = complete.calls.load(std::sync::atomic::Ordering::Relaxed);
+        let partial = draws(model(calls - 20));
+        assert!(!partial.trace.is_empty() && partial.trace.len() < 100);
+        let failure = partial.failure.unwrap();
+        assert!(
+            failure.starts_with(&format!("after {} draws", partial.trace.len())),
+            "{}",
+            failure
+        );
+        assert!(failure.contains("exhausted"), "{}", failure);
+
+        // nothing left
+        assert!(matches!(
+            Chains::run(model(100), &settings),
+            Err(MyError::SamplingFailed(_))
+        ));
+        let mut run = StreamingRun::start(model(100), &settings).unwrap();
+        assert!(matches!(
+            run.advance(200, None),
+            Err(MyError::SamplingFailed(failure)) if failure.starts_with("chain 0 stopped during warmup")
+        ));
+    }
+
+    #[test]
+    fn test_streaming_run_time_budget() {
+        let model = FailingModel {
+            calls: Default::default(),
+            budget: usize::MAX,
+        };
+        let settings = RunSettings {
+            seed: 7,
+            chain_count: 2,
+            tuning: 5,
+            samples: 10,
+            initial_position: Some(vec![1.]),
+            ..Default::default()
+        };
+
+        // an exhausted budget: a draw in each chain per step
+        let mut run = StreamingRun::start(model, &settings).unwrap();
+        for step in 1..=15usize {
+            assert_eq!(run.advance(u64::MAX, Some(0.)).unwrap(), step == 15);
+            assert_eq!(run.chains().samples(), step.saturating_sub(5));
+        }
+    }
+
+    #[test]
+    fn test_jitter() {
+        let position = vec![1., 2., 3.];
+
+        assert_eq!(jitter(&position, 0.1, 42), jitter(&position, 0.1, 42));
+        assert_ne!(jitter(&position, 0.1, 42), jitter(&position, 0.1, 43));
+
+        let jittered = jitter(&position, 0.1, 42);
+        for (x, y) in position.iter().zip(jittered.iter()) {
+            assert!((x - y).abs() < 1.);
+        }
+    }
+
+    #[test]
+    fn test_layout_grid() {
+        assert_eq!(PlotLayout::default().grid(3), (3, 2));
+
+        let grid = PlotLayout {
+            panels: PlotPanels::Histogram,
+            columns: 3,
+            ..PlotLayout::default()
+        };
+        assert_eq!(grid.grid(7), (3, 3));
+        assert_eq!(grid.grid(2), (1, 2));
+
+        let traces = PlotLayout {
+            panels: PlotPanels::Trace,
+            columns: 0,
+            ..PlotLayout::default()
+        };
+        assert_eq!(traces.grid(4), (4, 1));
+    }
+
+    #[test]
+    fn test_histogram_bins_constant() {
+        // a parameter that barely moves
+        let draws = vec![vec![20.; 100], vec![20. + 1e-12; 100]];
+        let (min_, max_) = axis_bounds(20., 20. + 1e-12);
+        assert!(max_ - min_ >= 0.1 - 1e-12);
+        assert!(min_ <= 20. && max_ >= 20.);
+
+        let (step, max_height) = histogram_bins(&draws, min_, max_);
+        assert!(step > 0. && step <= (max_ - min_) / 10. + 1e-12);
+        assert_eq!(max_height, 100);
+
+        // no draws at all
+        let (step, max_height) = histogram_bins(&[vec![]], min_, max_);
+        assert!(step > 0.);
+        assert_eq!(max_height, 1);
+    }
+
+    #[test]
+    fn test_divergence_locations() {
+        let divergence = |start_location: Option<Vec<f64>>| MyDivergenceInfo {
+            start_location: start_location.map(Into::into),
+            draw: 0,
+        };
+
+        let chains = Chains {
+            chains: vec![
+                chain_run(
+                    vec![vec![1., 10.].into()],
+                    vec![divergence(Some(vec![0.5, 5.])), divergence(None)],
+                ),
+                chain_run(
+                    vec![vec![3., 30.].into()],
+                    vec![divergence(Some(vec![2.5, 25.]))],
+                ),
+            ],
+            dim: 2,
+            parameters: vec![String::from("alpha"), String::from("beta")],
+            supports: vec![Support::Real; 2],
+            failures: vec![],
+            display_names: None,
+        };
+
+        assert_eq!(
+            chains.divergence_locations(),
+            vec![vec![0.5, 5.], vec![2.5, 25.]]
+        );
+    }
+
+    #[test]
+    fn test_truncate() {
+        let divergence = |draw| MyDivergenceInfo {
+            start_location: None,
+            draw,
+        };
+
+        let mut chain = chain_run(
+            vec![vec![1.].into(), vec![2.].into(), vec![3.].into()],
+            vec![divergence(0), divergence(2)],
+        );
+        chain.truncate(2);
+        assert_eq!(chain.trace(0), vec![1., 2.]);
+        // the divergence of the dropped draw goes with it
+        assert_eq!(
+            chain.stats().iter().map(|d| d.draw).collect::<Vec<_>>(),
+            vec![0]
+        );
+    }
+
+    #[test]
+    fn test_repeat_fraction() {
+        let chains = Chains {
+            chains: vec![
+                chain_run(
+                    vec![
+                        vec![1., 5.].into(),
+                        vec![1., 6.].into(),
+                        vec![2., 6.].into(),
+                    ],
+                    vec![],
+                ),
+                chain_run(vec![vec![2., 7.].into(), vec![2., 8.].into()], vec![]),
+                chain_run(vec![vec![3., 9.].into()], vec![]),
+            ],
+            dim: 2,
+            parameters: vec![String::from("alpha"), String::from("beta")],
+            supports: vec![Support::Real; 2],
+            failures: vec![],
+            display_names: None,
+        };
+
+        // across chains: 1 -> 1 and 2 -> 2 - not 2 (chain 0) -> 2 (chain 1)
+        assert_eq!(chains.repeat_fraction(0), 2. / 3.);
+        assert_eq!(chains.repeat_fraction(1), 1. / 3.);
+    }
+
+    #[test]
+    fn test_overdispersed() {
+        let trace = |offset: f64| {
+            (0..100)
+                .map(|i| vec![offset + (i % 10) as f64 / 10.].into())
+                .collect::<Vec<_>>()
+        };
+
+        let chains = |offsets: &[f64]| Chains {
+            chains: offsets
+                .iter()
+                .map(|o| chain_run(trace(*o), vec![]))
+                .collect(),
+            dim: 1,
+            parameters: vec![String::from("alpha")],
+            supports: vec![Support::Real],
+            failures: vec![],
+            display_names: None,
+        };
+
+        let mixed = chains(&[0., 0.01, -0.01]);
+        assert!((mixed.chain_means(0)[1] - 0.46).abs() < 1e-12);
+        assert!(!mixed.overdispersed(0, 0.5));
+
+        let stuck = chains(&[0., 5., 0.]);
+        assert!(stuck.overdispersed(0, 0.5));
+    }
+
+    #[test]
+    fn test_summary_supports() {
+        // log-normal draws of sigma
+        let trace = (0..1000)
+            .map(|i| vec![0., (i as f64 / 250. - 2.).powi(3).exp()].into())
+            .collect::<Vec<_>>();
+
+        let chains = Chains {
+            chains: vec![chain_run(trace, vec![])],
+            dim: 2,
+            parameters: vec![String::from("alpha"), String::from("sigma")],
+            supports: vec![Support::Real, Support::Positive],
+            failures: vec![],
+            display_names: None,
+        };
+
+        let summary = chains.summary();
+        let sigma = summary
+            .lines()
+            .nth(2)
+            .unwrap()
+            .split(',')
+            .collect::<Vec<_>>();
+        assert_eq!(sigma[0], "sigma");
+        assert_eq!(sigma[10], "positive");
+        assert_eq!(
+            summary.lines().nth(1).unwrap().split(',').nth(10),
+            Some("real")
+        );
+
+        // the HDI is the one of log(sigma)
+        let log_draws = chains.traces(1)[0]
+            .iter()
+            .map(|x| x.ln())
+            .collect::<Vec<_>>();
+        let (low, high) = hdi(&log_draws, 0.95);
+        assert!((sigma[7].parse::<f64>().unwrap() - low.exp()).abs() < 1e-12);
+        assert!((sigma[8].parse::<f64>().unwrap() - high.exp()).abs() < 1e-12);
+
+        for x in [0.2, 1., 3.] {
+            let unit = Support::UnitInterval;
+            assert!(
+                (Support::Positive.constrain(Support::Positive.unconstrain(x)) - x).abs() < 1e-12
+            );
+            assert!((unit.constrain(unit.unconstrain(x / 4.)) - x / 4.).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_chain_trace() {
+        let mut chains = Chains {
+            chains: vec![
+                chain_run(vec![vec![1., 10.].into(), vec![2., 20.].into()], vec![]),
+                chain_run(vec![vec![3., 30.].into(), vec![4., 40.].into()], vec![]),
+            ],
+            dim: 2,
+            parameters: vec![String::from("alpha"), String::from("beta")],
+            supports: vec![Support::Real; 2],
+            failures: vec![],
+            display_names: None,
+        };
+
+        assert_eq!(chains.chain_trace(1, 0).unwrap(), vec![3., 4.]);
+        assert_eq!(chains.chain_trace(0, 1).unwrap(), vec![10., 20.]);
+        assert!(matches!(
+            chains.chain_trace(2, 0),
+            Err(MyError::UnknownChain {
+                chain_id: 2,
+                chain_count: 2
+            })
+        ));
+        assert!(matches!(
+            chains.chain_trace(0, 2),
+            Err(MyError::UnknownParameter {
+                parameter_idx: 2,
+                dim: 2
+            })
+        ));
+
+        chains.rescale(1, 0.5);
+        assert_eq!(chains.chain_trace(1, 1).unwrap(), vec![15., 20.]);
+        assert_eq!(chains.chain_trace(1, 0).unwrap(), vec![3., 4.]);
+
+        chains.shift(0, 1, 0.1);
+        assert_eq!(chains.chain_trace(1, 0).unwrap(), vec![4.5, 6.]);
+        assert_eq!(chains.chain_trace(1, 1).unwrap(), vec![15., 20.]);
+
+        chains.offset(0, -0.5);
+        assert_eq!(chains.chain_trace(1, 0).unwrap(), vec![4., 5.5]);
+        assert_eq!(chains.chain_trace(1, 1).unwrap(), vec![15., 20.]);
+    }
+
+    #[test]
+    fn test_draws_flat() {
+        let chains = Chains {
+            chains: vec![
+                chain_run(vec![vec![1., 10.].into(), vec![2., 20.].into()], vec![]),
+                chain_run(vec![vec![3., 30.].into(), vec![4., 40.].into()], vec![]),
+            ],
+            dim: 2,
+            parameters: vec![String::from("alpha"), String::from("beta")],
+            supports: vec![Support::Real; 2],
+            failures: vec![],
+            display_names: None,
+        };
+
+        assert_eq!(chains.shape(), (2, 2, 2));
+        assert_eq!(
+            chains.draws_flat(),
+            vec![1., 10., 2., 20., 3., 30., 4., 40.]
+        );
+    }
+
+    #[test]
+    fn test_arviz_json() {
+        let chains = Chains {
+            chains: vec![
+                chain_run(vec![vec![1., 10.].into(), vec![2., 20.].into()], vec![]),
+                chain_run(vec![vec![3., 30.].into(), vec![4., 40.].into()], vec![]),
+            ],
+            dim: 2,
+            parameters: vec![String::from("alpha"), String::from("beta")],
+            supports: vec![Support::Real; 2],
+            failures: vec![],
+            display_names: None,
+        };
+
+        let json: serde_json::Value = serde_json::from_str(&chains.to_arviz_json()).unwrap();
+
+        assert_eq!(
+            json["posterior"]["alpha"],
+            serde_json::json!([[1., 2.], [3., 4.]])
+        );
+        assert_eq!(
+            json["posterior"]["beta"],
+            serde_json::json!([[10., 20.], [30., 40.]])
+        );
+    }
+
+    #[test]
+    fn test_r_hat() {
+        // two chains that do not overlap: one around 0, the other around 10
+        let trace = |offset: f64| {
+            (0..50)
+                .map(|i| vec![offset + (i % 5) as f64 / 10.].into())
+                .collect::<Vec<_>>()
+        };
+        let chains = Chains {
+            chains: vec![chain_run(trace(0.), vec![]), chain_run(trace(10.), vec![])],
+            dim: 1,
+            parameters: vec![String::from("alpha")],
+            supports: vec![Support::Real],
+            failures: vec![],
+            display_names: None,
+        };
+
+        let r_hat = chains.r_hat(0);
+        assert!(r_hat > 10., "r_hat = {}", r_hat);
+        assert_eq!(
+            chains.convergence_csv(),
+            format!(
+                "parameter,R_HAT,ESS,converged\nalpha,{},{},false\n",
+                r_hat,
+                chains.effective_sample_size(0)
+            )
+        );
+
+        // the same chain twice
+        let chains = Chains {
+            chains: vec![chain_run(trace(0.), vec![]), chain_run(trace(0.), vec![])],
+            ..chains
+        };
+        assert!(chains.r_hat(0) <= R_HAT_THRESHOLD);
+    }
+
+    #[test]
+    fn test_diagnostics_csv() {
+        let trace = |offset: f64| {
+            (0..100)
+                .map(|i| vec![offset + (i % 7) as f64, 10. + (i % 3) as f64].into())
+                .collect::<Vec<_>>()
+        };
+        let chains = Chains {
+            chains: vec![chain_run(trace(0.), vec![]), chain_run(trace(0.5), vec![])],
+            dim: 2,
+            parameters: vec![String::from("alpha"), String::from("beta")],
+            supports: vec![Support::Real; 2],
+            failures: vec![],
+            display_names: None,
+        };
+
+        let csv = chains.diagnostics_csv();
+        let lines = csv.lines().collect::<Vec<_>>();
+        assert_eq!(
+            lines[0],
+            "parameter,mean,se_mean,sd,2.5%,25%,50%,75%,97.5%,n_eff,Rhat"
+        );
+        // one row per parameter - as wide as the header
+        assert_eq!(lines.len(), 1 + chains.dim);
+        for (line, parameter) in lines[1..].iter().zip(chains.parameters.iter()) {
+            let fields = line.split(',').collect::<Vec<_>>();
+            assert_eq!(fields.len(), 11);
+            assert_eq!(fields[0], parameter);
+        }
+        assert_eq!(
+            lines[2].split(',').nth(10).unwrap(),
+            chains.r_hat(1).to_string()
+        );
+    }
+
+    #[test]
+    fn test_fit_result() {
+        let chains = Chains {
+            chains: vec![
+                chain_run(vec![vec![1., 10.].into(), vec![2., 20.].into()], vec![]),
+                chain_run(vec![vec![3., 30.].into(), vec![4., 40.].into()], vec![]),
+            ],
+            dim: 2,
+            parameters: vec![String::from("alpha"), String::from("beta")],
+            supports: vec![Support::Real; 2],
+            failures: vec![],
+            display_names: None,
+        };
+
+        let fit = chains.fit_result(vec![String::from("the chains disagree")]);
+        assert_eq!(fit.parameters, chains.parameters);
+        assert_eq!(fit.means, vec![2.5, 25.]);
+        assert_eq!(
+            fit.intervals[0],
+            Interval {
+                low: quantile(&[1., 2., 3., 4.], 0.025),
+                high: quantile(&[1., 2., 3., 4.], 0.975)
+            }
+        );
+        assert_eq!(fit.diagnostics[1].repeat_fraction, 0.);
+
+        // the shape JS receives
+        let json = serde_json::to_value(&fit).unwrap();
+        assert_eq!(json["means"], serde_json::json!([2.5, 25.]));
+        assert_eq!(json["intervals"][1]["high"], fit.intervals[1].high);
+        assert!(json["diagnostics"][0].get("ess").is_some());
+        assert_eq!(json["warnings"], serde_json::json!(["the chains disagree"]));
+    }
+
+    #[test]
+    fn test_run_draws() {
+        let trace = |offset: f64| {
+            (0..8)
+                .map(|i| vec![offset + i as f64, 10. + (i % 3) as f64].into())
+                .collect::<Vec<_>>()
+        };
+        let chains = Chains {
+            chains: vec![chain_run(trace(0.), vec![]), chain_run(trace(0.5), vec![])],
+            dim: 2,
+            parameters: vec![String::from("alpha"), String::from("beta")],
+            supports: vec![Support::Real; 2],
+            failures: vec![],
+            display_names: Some(vec![String::from("intercept"), String::from("slope")]),
+        };
+
+        let draws = chains.run_draws(5, 3);
+        assert_eq!(draws.parameters, vec!["intercept", "slope"]);
+        assert_eq!(draws.chains.len(), 2);
+        assert_eq!(draws.chains[1][2], vec![2.5, 12.]);
+        // the picks of sample_posterior with the same seed
+        let posterior = chains.sample_posterior(5, 3);
+        assert_eq!(draws.posterior_samples.len(), 5);
+        for (i, sample) in draws.posterior_samples.iter().enumerate() {
+            assert_eq!(sample, &vec![posterior["alpha"][i], posterior["beta"][i]]);
+        }
+        assert_eq!(draws.r_hat["slope"], chains.r_hat(1));
+
+        // the shape JS receives
+        let json = serde_json::to_value(&draws).unwrap();
+        assert_eq!(json["chains"][0][1], serde_json::json!([1., 11.]));
+        assert_eq!(json["r_hat"]["intercept"], chains.r_hat(0));
+    }
+
+    #[test]
+    fn test_adaptation() {
+        let stats = |accept: &[f64], step_size: f64| {
+            accept
+                .iter()
+                .map(|accept| DrawStats {
+                    accept: *accept,
+                    step_size,
+                    depth: 1,
+                })
+                .collect::<Vec<_>>()
+        };
+        let mut chain = chain_run(vec![vec![1.].into(), vec![2.].into()], vec![]);
+        chain.warmup = stats(&[0.25, 0.5, 0.75, 0.5, 1.], 0.5);
+        chain.warmup[4].step_size = 0.3;
+        chain.sampling = stats(&[0.8, 0.9], 0.3);
+        let chains = Chains {
+            chains: vec![chain],
+            dim: 1,
+            parameters: vec![String::from("x")],
+            supports: vec![Support::Real],
+            failures: vec![],
+            display_names: None,
+        };
+
+        let adaptation = &chains.adaptation(2)[0];
+        assert_eq!(
+            adaptation.warmup,
+            vec![
+                WarmupPhase {
+                    draws: 3,
+                    accept: 0.5,
+                    step_size: 0.5
+                },
+                WarmupPhase {
+                    draws: 2,
+                    accept: 0.75,
+                    step_size: 0.3
+                }
+            ]
+        );
+        assert_eq!(adaptation.step_size, 0.1);
+        assert!((adaptation.sampling_accept - 0.85).abs() < 1e-12);
+
+        // a single window by default - and never more windows than draws
+        assert_eq!(chains.adaptation(1)[0].warmup.len(), 1);
+        assert_eq!(chains.adaptation(10)[0].warmup.len(), 5);
+
+        // the warmup draws of a run are kept
+        let model = FailingModel {
+            calls: Default::default(),
+            budget: usize::MAX,
+        };
+        let settings = RunSettings {
+            seed: 3,
+            chain_count: 2,
+            tuning: 40,
+            samples: 10,
+            // off the mode: nuts-rs rejects a zero gradient at the start
+            initial_position: Some(vec![1.]),
+            ..Default::default()
+        };
+        let adaptation = Chains::run(model, &settings).unwrap().adaptation(4);
+        assert_eq!(adaptation.len(), 2);
+        assert_eq!(
+            adaptation[0]
+                .warmup
+                .iter()
+                .map(|phase| phase.draws)
+                .collect::<Vec<_>>(),
+            vec![10; 4]
+        );
+        assert!(adaptation[1].sampling_accept >= 0. && adaptation[1].sampling_accept <= 1.);
+    }
+
+    #[test]
+    fn test_display_names() {
+        let mut chains = Chains {
+            chains: vec![
+                chain_run((0..8).map(|i| vec![i as f64, 1.].into()).collect(), vec![]),
+                chain_run((0..8).map(|i| vec![1., i as f64].into()).collect(), vec![]),
+            ],
+            dim: 2,
+            parameters: vec![String::from("alpha"), String::from("beta")],
+            supports: vec![Support::Real, Support::Real],
+            failures: vec![],
+            display_names: None,
+        };
+        assert_eq!(chains.display_names(), chains.parameters.as_slice());
+
+        assert!(matches!(
+            chains.set_display_names(vec![String::from("intercept")]),
+            Err(MyError::WrongNameCount {
+                expected: 2,
+                actual: 1
+            })
+        ));
+
+        let names = vec![String::from("intercept"), String::from("warming_rate")];
+        chains.set_display_names(names.clone()).unwrap();
+        assert_eq!(chains.display_names(), names.as_slice());
+        assert!(chains.summary().contains("\nwarming_rate,"));
+        assert!(chains.diagnostics_csv().contains("\nintercept,"));
+        assert_eq!(chains.fit_result(vec![]).parameters, names);
+        assert!(chains.to_arviz_json().contains("\"warming_rate\""));
+
+        // the internal names still index the draws
+        assert_eq!(chains.parameters, vec!["alpha", "beta"]);
+        assert!(chains.sample_posterior(3, 1).contains_key("beta"));
+    }
+
+    #[test]
+    fn test_sampler_args() {
+        let x = (0..50).map(|i| i as f64 - 25.).collect::<Vec<_>>();
+        let y = x
+            .iter()
+            .map(|x| 10. + 0.02 * x + (x * 7.).sin())
+            .collect::<Vec<_>>();
+        let model = crate::model::regression::Regression::new(x, y);
+        let settings = RunSettings {
+            seed: 5,
+            chain_count: 2,
+            tuning: 100,
+            samples: 200,
+            ..Default::default()
+        };
+
+        let divergences = |settings: RunSettings| {
+            Chains::run(model.clone(), &settings)
+                .unwrap()
+                .divergence_count()
+        };
+        let default = divergences(settings.clone());
+        // a lower energy threshold flags more draws as divergent
+        let strict = divergences(RunSettings {
+            max_energy_error: Some(0.1),
+            ..settings.clone()
+        });
+        assert!(strict > default, "{} vs {}", strict, default);
+
+        // the trees are never deeper than maxdepth - the default one is
+        // deeper on this posterior
+        let max_depth = |maxdepth| {
+            let chains = Chains::run(
+                model.clone(),
+                &RunSettings {
+                    maxdepth,
+                    ..settings.clone()
+                },
+            )
+            .unwrap();
+            chains
+                .chains
+                .iter()
+                .flat_map(|chain| chain.warmup.iter().chain(chain.sampling.iter()))
+                .map(|stats| stats.depth)
+                .max()
+                .unwrap()
+        };
+        assert!(max_depth(None) > 2);
+        assert!(max_depth(Some(2)) <= 2);
+
+        let invalid = |settings: RunSettings| {
+            matches!(
+                Chains::run(model.clone(), &settings),
+                Err(MyError::InvalidSamplerArgs(_))
+            )
+        };
+        assert!(invalid(RunSettings {
+            target_accept: Some(1.),
+            ..settings.clone()
+        }));
+        assert!(invalid(RunSettings {
+            maxdepth: Some(64),
+            ..settings.clone()
+        }));
+        assert!(invalid(RunSettings {
+            max_energy_error: Some(-1.),
+            ..settings
+        }));
+    }
+
+    #[test]
+    fn test_sampler_state() {
+        let model = || FailingModel {
+            calls: Default::default(),
+            budget: usize::MAX,
+        };
+        let settings = RunSettings {
+            seed: 3,
+            chain_count: 2,
+            tuning: 40,
+            samples: 10,
+            // off the mode: nuts-rs rejects a zero gradient at the start
+            initial_position: Some(vec![1.]),
+            ..Default::default()
+        };
+        let state = Chains::run(model(), &settings).unwrap().sampler_state();
+        assert_eq!(state.positions.len(), 2);
+        assert_eq!(state.step_sizes.len(), 2);
+
+        // the adaptation needs a warmup draw
+        assert!(matches!(
+            Chains::run(
+                model(),
+                &RunSettings {
+                    tuning: 0,
+                    sampler_state: Some(state.clone()),
+                    ..settings.clone()
+                }
+            ),
+            Err(MyError::InvalidSamplerArgs(_))
+        ));
+
+        // the warmup starts from the adapted step sizes
+        let settings = RunSettings {
+            tuning: 1,
+            sampler_state: Some(state.clone()),
+            ..settings
+        };
+        assert_eq!(
+            settings.initial_positions(&model()).unwrap(),
+            state.positions
+        );
+        let chains = Chains::run(model(), &settings).unwrap();
+        for (chain, step_size) in chains.chains.iter().zip(state.step_sizes.iter()) {
+            assert_eq!(chain.warmup.len(), 1);
+            assert_eq!(chain.warmup[0].step_size, *step_size);
+        }
+
+        let run = |state: SamplerState| {
+            Chains::run(
+                model(),
+                &RunSettings {
+                    sampler_state: Some(state),
+                    ..settings.clone()
+                },
+            )
+        };
+        // one position and one step size per chain
+        let mut wrong = state.clone();
+        wrong.step_sizes.pop();
+        assert!(matches!(run(wrong), Err(MyError::InvalidSamplerState(_))));
+        // one value per parameter
+        let mut wrong = state.clone();
+        wrong.positions[1].push(0.);
+        assert!(matches!(run(wrong), Err(MyError::InvalidSamplerState(_))));
+        // positive step sizes
+        let mut wrong = state;
+        wrong.step_sizes[0] = f64::NAN;
+        assert!(matches!(run(wrong), Err(MyError::InvalidSamplerState(_))));
     }
 }
@@ -1,20 +1,176 @@
 //! # Bayesian regression in WebAssembly
+mod bridge;
 mod chain;
 mod model;
+mod numerics;
+mod optimize;
 
 mod plot;
+mod predictive;
 mod sampler;
+mod stats;
 mod utils;
 
 use core::fmt;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::Read;
 
-use model::regression::Regression;
+use chrono::{Datelike, NaiveDate};
+use flate2::read::GzDecoder;
 
-use utils::set_panic_hook;
+use chain::{HistogramMode, Model, PlotLayout};
+use model::changepoint::Changepoint;
+use model::heteroscedastic::{season_feature, Heteroscedastic};
+use model::logistic::Logistic;
+use model::mv::MultivariateNormalModel;
+use model::prior::{PriorSpec, ScalePrior};
+use model::regression::{Centering, Regression};
+use nuts_rs::CpuLogpFunc;
+use optimize::MapEstimate;
+use plot::{PlotStyle, PlotViews};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use sampler::SamplerState;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use stats::QuantileMethod;
+
+use utils::{set_panic_hook, timed, Timings};
 use wasm_bindgen::prelude::*;
 // #[global_allocator]
 // static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
 
+/// Normalized log prior plus log likelihood of a position - see
+/// [`Model::log_joint`].
+type LogJoint = Box<dyn Fn(&[f64]) -> Option<f64>>;
+
+#[wasm_bindgen(typescript_custom_section)]
+const FIT_RESULT: &str = r#"
+/** The result of run_with - one entry per parameter in each array */
+export interface FitResult {
+    parameters: string[];
+    means: number[];
+    /** central 95% intervals */
+    intervals: { low: number; high: number }[];
+    diagnostics: { ess: number; mcse_mean: number; repeat_fraction: number }[];
+    warnings: string[];
+}
+"#;
+
+#[wasm_bindgen(typescript_custom_section)]
+const RUN_DRAWS: &str = r#"
+/** The draws of run_json - each draw has one entry per parameter */
+export interface RunDraws {
+    parameters: string[];
+    /** post-warmup draws of each chain */
+    chains: number[][][];
+    /** draws picked at random across the chains */
+    posterior_samples: number[][];
+    /** split R-hat of each parameter */
+    r_hat: Record<string, number>;
+}
+"#;
+
+#[wasm_bindgen(typescript_custom_section)]
+const SAMPLER_STATE: &str = r#"
+/** Where the chains of a run stopped - see export_sampler_state */
+export interface SamplerState {
+    /** last draw of each chain */
+    positions: number[][];
+    /** adapted step size of each chain */
+    step_sizes: number[];
+}
+"#;
+
+#[wasm_bindgen(typescript_custom_section)]
+const RUN_OPTIONS: &str = r#"
+/** The optional arguments of run_with and run_json - a missing field keeps the default */
+export interface RunOptions {
+    fixed_sigma?: number;
+    histogram_mode?: HistogramMode;
+    initial_position?: number[];
+    jitter_scale?: number;
+    slope_laplace_scale?: number;
+    /** the fields of a PlotLayout - as a plain object */
+    layout?: { panels?: PlotPanels; columns?: number; legend?: LegendPosition; running_mean?: boolean };
+    seeds?: number[];
+    max_millis?: number;
+    max_memory_mb?: number;
+    log_response?: boolean;
+    max_attempts?: number;
+    standardize?: boolean;
+    /** the fields of a PlotViews - as a plain object */
+    views?: { data?: string; residuals?: string };
+    downweight_outliers?: boolean;
+    title_prefix?: string;
+    sigma_prior?: ScalePrior;
+    sigma_prior_scale?: number;
+    centering?: Centering;
+    centering_date?: number;
+    x_column?: number;
+    y_column?: number;
+    predictor_columns?: number[];
+    sampler_state?: SamplerState;
+    fixed_beta?: number;
+    parameter_names?: string[];
+    drop_stuck_chains?: boolean;
+    subsample_n?: number;
+    alpha_prior_mean?: number;
+    alpha_prior_sd?: number;
+    beta_prior_mean?: number;
+    beta_prior_sd?: number;
+    target_accept?: number;
+    maxdepth?: number;
+    max_energy_error?: number;
+    r_hat_id?: string;
+}
+"#;
+
+#[wasm_bindgen(typescript_custom_section)]
+const LOGISTIC_OPTIONS: &str = r#"
+/** The optional arguments of run_logistic_with - a missing field keeps the default */
+export interface LogisticOptions {
+    x_column?: number;
+    y_column?: number;
+    alpha_prior_mean?: number;
+    alpha_prior_sd?: number;
+    beta_prior_mean?: number;
+    beta_prior_sd?: number;
+    title_prefix?: string;
+}
+"#;
+
+#[wasm_bindgen(typescript_custom_section)]
+const ADAPTATION: &str = r#"
+/** How the adaptation of a chain went - see adaptation_summary */
+export interface Adaptation {
+    /** consecutive windows of the warmup */
+    warmup: { draws: number; accept: number; step_size: number }[];
+    step_size: number;
+    sampling_accept: number;
+}
+"#;
+
+thread_local! {
+    /// The chains of the last run - kept for the export functions.
+    static LAST_RUN: RefCell<Option<chain::Chains>> = const { RefCell::new(None) };
+    /// The log joint density of the model of the last run - on the scale of
+    /// the draws kept in [`LAST_RUN`].
+    static LAST_LOG_JOINT: RefCell<Option<LogJoint>> = const { RefCell::new(None) };
+    /// The run started by [`start_streaming_run`].
+    static STREAMING_RUN: RefCell<Option<StreamingSession>> = const { RefCell::new(None) };
+}
+
+/// A run started by [`start_streaming_run`] - see [`step_streaming_run`].
+struct StreamingSession {
+    run: chain::StreamingRun,
+    /// The regression of the run - for the outputs once it is done
+    regression: RegressionRun,
+    /// Textarea of the posterior draws - if any
+    posteriod_id: Option<String>,
+}
+
 /// Error type for this crate
 #[derive(Debug)]
 pub enum MyError {
@@ -22,6 +178,176 @@ pub enum MyError {
     UnexpectedRawDataHeader,
     /// Invalid date format
     InvalidDateFormat,
+    /// A predictor is (nearly) constant - its coefficient cannot be identified
+    ZeroVariancePredictor(String),
+    /// No sampling has been run yet
+    NoRun,
+    /// A required column is missing from a CSV
+    MissingColumn(String),
+    /// The log density could not be evaluated during an optimization
+    OptimizationFailed(String),
+    /// The canvas with the given id is missing or has no 2D context
+    CanvasUnavailable(String),
+    /// The element with the given id is missing from the page
+    ElementUnavailable(String),
+    /// The response cannot be log-transformed: it has a non-positive value
+    NonPositiveResponse(f64),
+    /// No chain with this id in the last run
+    UnknownChain {
+        /// Requested chain
+        chain_id: usize,
+        /// Number of chains
+        chain_count: usize,
+    },
+    /// No parameter with this index in the last run
+    UnknownParameter {
+        /// Requested parameter
+        parameter_idx: usize,
+        /// Number of parameters
+        dim: usize,
+    },
+    /// The traces would not fit in the memory budget
+    TooManyDraws {
+        /// Memory needed by the traces in bytes
+        bytes: usize,
+        /// Memory budget in bytes
+        budget: usize,
+    },
+    /// Unknown temperature units
+    UnknownUnits(String),
+    /// Not a temperature element of the NOAA GHCN daily data
+    UnsupportedElement(String),
+    /// The number of explicit seeds does not match the number of chains
+    WrongSeedCount {
+        /// Number of chains
+        expected: u64,
+        /// Number of seeds given
+        actual: usize,
+    },
+    /// The input is not a valid gzip stream of UTF-8 text
+    InvalidGzip(String),
+    /// A row of the regression CSV has too few values
+    RegressionRowTooShort {
+        /// Index of the row (after the header)
+        row: usize,
+        /// Number of values in the row
+        width: usize,
+        /// Number of values expected
+        expected: usize,
+    },
+    /// A prior of the model is improper - the marginal likelihood is undefined
+    ImproperPrior,
+    /// Too few draws for the estimate
+    NotEnoughDraws {
+        /// Number of draws
+        draws: usize,
+        /// Minimum number of draws
+        needed: usize,
+    },
+    /// The input has no observation - e.g. a filter removed all the rows
+    NoData,
+    /// The log density is not finite at an initial position of the chains
+    BadInitialPosition {
+        /// The parameter to blame - if one could be found
+        parameter: Option<String>,
+        /// The initial position
+        position: Vec<f64>,
+    },
+    /// A column index is beyond the width of the rows
+    ColumnOutOfRange {
+        /// Requested column
+        index: usize,
+        /// Number of columns
+        width: usize,
+    },
+    /// The sampler failed in every chain
+    SamplingFailed(String),
+    /// The sampler state does not fit the run
+    InvalidSamplerState(String),
+    /// The options of a run are not a `RunOptions` object
+    InvalidRunOptions(String),
+    /// No observation in the baseline period of the anomalies
+    EmptyBaseline {
+        /// First year of the period
+        first_year: i32,
+        /// Last year of the period
+        last_year: i32,
+    },
+    /// The baseline is not a period `[first_year, last_year]`
+    InvalidBaseline(Vec<i32>),
+    /// The posterior mean has fewer values than alpha and beta
+    PosteriorMeanTooShort(usize),
+    /// The columns of the data are not DATE and a value that can be plotted
+    UnexpectedColumns(Vec<String>),
+    /// A parameter of a prior is out of its range - e.g. a scale that is not
+    /// positive
+    InvalidPrior(String),
+    /// The known standard deviation of the noise is not positive
+    InvalidFixedSigma(f64),
+    /// A position does not have one value per sampled parameter
+    WrongPositionLength {
+        /// Number of sampled parameters
+        expected: usize,
+        /// Number of values given
+        actual: usize,
+    },
+    /// The dates and the values of a series have different lengths
+    LengthMismatch {
+        /// Number of dates
+        dates: usize,
+        /// Number of values
+        values: usize,
+    },
+    /// The number of display names does not match the number of parameters
+    WrongNameCount {
+        /// Number of sampled parameters
+        expected: usize,
+        /// Number of names given
+        actual: usize,
+    },
+    /// A row of the input is malformed - e.g. a row of a generic time series
+    /// is not a date and a value, or a raw row misses a field
+    MalformedRow {
+        /// Line of the row - the header is line 1
+        line: usize,
+    },
+    /// Every chain stayed at its initial position
+    AllChainsStuck,
+    /// An argument of the sampler is out of its range
+    InvalidSamplerArgs(String),
+    /// A cell of a CSV is not a number
+    NonNumericCell {
+        /// Line of the cell
+        line: usize,
+        /// Column of the cell - its name in the header
+        column: String,
+    },
+    /// A row of a CSV does not have as many cells as the header
+    ColumnCountMismatch {
+        /// Line of the row
+        line: usize,
+        /// Number of columns in the header
+        expected: usize,
+        /// Number of cells in the row
+        found: usize,
+    },
+    /// The epoch year of the dates is out of the supported range of dates
+    InvalidEpochYear(i32),
+    /// An outcome of the logistic regression is neither 0 nor 1
+    NonBinaryOutcome(f64),
+    /// A fixed slope with several predictors - the number of predictors
+    FixedBetaNeedsOnePredictor(usize),
+    /// A column is selected more than once - its index
+    DuplicateColumn(usize),
+    /// The hypothesized slope is not a finite number
+    InvalidFixedBeta(f64),
+    /// A COUNT is not a positive number - it cannot weight its observation
+    InvalidWeight {
+        /// Index of the row (after the header)
+        row: usize,
+        /// The count
+        value: f64,
+    },
 }
 
 impl std::error::Error for MyError {}
@@ -31,6 +357,194 @@ impl fmt::Display for MyError {
         match self {
             MyError::UnexpectedRawDataHeader => write!(f, "Unexpected raw data header"),
             MyError::InvalidDateFormat => write!(f, "Invalid date format - expected YYYYMMDD"),
+            MyError::ZeroVariancePredictor(column) => write!(
+                f,
+                "Predictor {} has (near-)zero variance - its coefficient cannot be estimated",
+                column
+            ),
+            MyError::NoRun => write!(f, "No sampling run yet - call run_with first"),
+            MyError::MissingColumn(column) => write!(f, "Missing column {}", column),
+            MyError::OptimizationFailed(e) => write!(f, "Optimization failed: {}", e),
+            MyError::CanvasUnavailable(canvas_id) => {
+                write!(
+                    f,
+                    "Canvas {} is unavailable - nothing was plotted",
+                    canvas_id
+                )
+            }
+            MyError::ElementUnavailable(element_id) => write!(
+                f,
+                "Element {} is unavailable - nothing was written",
+                element_id
+            ),
+            MyError::NonPositiveResponse(value) => write!(
+                f,
+                "Cannot fit log(TMAX): {} is not positive - all the values must be",
+                value
+            ),
+            MyError::UnknownChain {
+                chain_id,
+                chain_count,
+            } => write!(
+                f,
+                "Unknown chain {} - there are {} chains",
+                chain_id, chain_count
+            ),
+            MyError::UnknownParameter { parameter_idx, dim } => write!(
+                f,
+                "Unknown parameter {} - there are {} parameters",
+                parameter_idx, dim
+            ),
+            MyError::TooManyDraws { bytes, budget } => write!(
+                f,
+                "The traces need {} bytes but the budget is {} bytes - reduce the number of chains or samples",
+                bytes, budget
+            ),
+            MyError::UnknownUnits(units) => {
+                write!(f, "Unknown units {} - expected C, F or K", units)
+            }
+            MyError::UnsupportedElement(element) => write!(
+                f,
+                "Unsupported element {} - expected TMAX, TMIN or TAVG",
+                element
+            ),
+            MyError::WrongSeedCount { expected, actual } => write!(
+                f,
+                "Expected one seed per chain ({}) but got {}",
+                expected, actual
+            ),
+            MyError::InvalidGzip(e) => write!(f, "Invalid gzip input: {}", e),
+            MyError::RegressionRowTooShort {
+                row,
+                width,
+                expected,
+            } => write!(
+                f,
+                "Row {} of the regression has {} values - expected {} (alpha, beta, sigma)",
+                row, width, expected
+            ),
+            MyError::ImproperPrior => write!(
+                f,
+                "The marginal likelihood needs proper priors - the model has a flat prior"
+            ),
+            MyError::NotEnoughDraws { draws, needed } => write!(
+                f,
+                "{} draws are not enough - at least {} are needed",
+                draws, needed
+            ),
+            MyError::NoData => write!(f, "No data - the input has no observation"),
+            MyError::BadInitialPosition {
+                parameter: Some(parameter),
+                position,
+            } => write!(
+                f,
+                "The log density is not finite at the initial position {:?} - check {}",
+                position, parameter
+            ),
+            MyError::BadInitialPosition {
+                parameter: None,
+                position,
+            } => write!(
+                f,
+                "The log density is not finite at the initial position {:?}",
+                position
+            ),
+            MyError::ColumnOutOfRange { index, width } => write!(
+                f,
+                "Column {} is out of range - the rows have {} columns",
+                index, width
+            ),
+            MyError::SamplingFailed(e) => write!(f, "Sampling failed in every chain: {}", e),
+            MyError::InvalidSamplerState(e) => write!(f, "Invalid sampler state: {}", e),
+            MyError::InvalidRunOptions(e) => write!(f, "Invalid run options: {}", e),
+            MyError::EmptyBaseline {
+                first_year,
+                last_year,
+            } => write!(
+                f,
+                "No observation in the baseline period {}-{} - the anomalies are undefined",
+                first_year, last_year
+            ),
+            MyError::WrongPositionLength { expected, actual } => write!(
+                f,
+                "Expected one value per sampled parameter ({}) but got {}",
+                expected, actual
+            ),
+            MyError::LengthMismatch { dates, values } => write!(
+                f,
+                "{} dates but {} values - one value per date is required",
+                dates, values
+            ),
+            MyError::InvalidPrior(e) => write!(f, "Invalid prior: {}", e),
+            MyError::InvalidFixedSigma(sigma) => write!(
+                f,
+                "fixed_sigma = {} is not a positive number",
+                sigma
+            ),
+            MyError::UnexpectedColumns(columns) => write!(
+                f,
+                "Unexpected columns {} - expected DATE and a temperature element or VALUE",
+                columns.join(",")
+            ),
+            MyError::PosteriorMeanTooShort(len) => write!(
+                f,
+                "The posterior mean has {} values - at least alpha and beta are needed",
+                len
+            ),
+            MyError::InvalidBaseline(baseline) => write!(
+                f,
+                "Invalid baseline {:?} - expected [first_year, last_year] with first_year <= last_year",
+                baseline
+            ),
+            MyError::WrongNameCount { expected, actual } => write!(
+                f,
+                "Expected one name per parameter ({}) but got {}",
+                expected, actual
+            ),
+            MyError::MalformedRow { line } => {
+                write!(
+                    f,
+                    "Line {} is malformed - wrong number of fields or a value that is not a number",
+                    line
+                )
+            }
+            MyError::AllChainsStuck => write!(
+                f,
+                "Every chain stayed at its initial position - try other initial values"
+            ),
+            MyError::InvalidSamplerArgs(e) => write!(f, "Invalid sampler argument: {}", e),
+            MyError::NonNumericCell { line, column } => {
+                write!(f, "Line {}: the {} cell is not a number", line, column)
+            }
+            MyError::ColumnCountMismatch {
+                line,
+                expected,
+                found,
+            } => write!(
+                f,
+                "Line {}: expected {} cells as in the header but found {}",
+                line, expected, found
+            ),
+            MyError::InvalidEpochYear(year) => write!(f, "Invalid epoch year {}", year),
+            MyError::NonBinaryOutcome(y) => {
+                write!(f, "Outcome {} is not binary - expected 0 or 1", y)
+            }
+            MyError::FixedBetaNeedsOnePredictor(k) => write!(
+                f,
+                "A fixed beta needs a single predictor - the data has {}",
+                k
+            ),
+            MyError::DuplicateColumn(index) => {
+                write!(f, "Column {} is selected more than once", index)
+            }
+            MyError::InvalidFixedBeta(beta) => {
+                write!(f, "fixed_beta = {} is not a finite number", beta)
+            }
+            MyError::InvalidWeight { row, value } => write!(
+                f,
+                "Row {}: the COUNT {} is not a positive number",
+                row, value
+            ),
         }
     }
 }
@@ -41,42 +555,274 @@ impl From<MyError> for JsValue {
     }
 }
 
-fn parse_csv(input_data: String) -> (Vec<Vec<f64>>, Vec<String>) {
-    let input_data = input_data.trim();
-    let lines: Vec<_> = input_data.split('\n').collect();
-    let headers = lines[0];
-    let parameters = headers
-        .split(',')
-        .map(|x| x.trim().to_string())
-        .collect::<Vec<_>>();
+/// Returns the meaningful lines of a CSV - blank lines and comment lines
+/// (starting with '#') are skipped - with their numbers in the input.
+fn csv_lines(data: &str) -> Vec<(usize, &str)> {
+    data.lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .collect()
+}
+
+/// Parses a CSV of numbers with a header - the rows and the names of the
+/// columns.
+///
+/// Fails with [`MyError::ColumnCountMismatch`] if a row does not have as many
+/// cells as the header, or with [`MyError::NonNumericCell`] if a cell is not a
+/// number. The lines are numbered in the input - the header is line 1 unless
+/// preceded by comments.
+fn parse_csv(input_data: String) -> Result<(Vec<Vec<f64>>, Vec<String>), MyError> {
+    let mut lines = csv_lines(&input_data).into_iter();
+
+    let parameters = match lines.next() {
+        Some((_, headers)) => headers
+            .split(',')
+            .map(|x| x.trim().to_string())
+            .collect::<Vec<_>>(),
+        None => return Ok((vec![], vec![])),
+    };
 
     let observed = lines
-        .iter()
-        .skip(1)
-        .map(|x| {
-            let x_ = x
-                .split(',')
-                .map(|x| x.trim().parse::<f64>().unwrap())
-                .collect::<Vec<_>>();
-            assert_eq!(x_.len(), parameters.len(), "Wrong number of columns");
-            x_
+        .map(|(line, row)| {
+            let cells = row.split(',').map(str::trim).collect::<Vec<_>>();
+            if cells.len() != parameters.len() {
+                return Err(MyError::ColumnCountMismatch {
+                    line,
+                    expected: parameters.len(),
+                    found: cells.len(),
+                });
+            }
+
+            cells
+                .iter()
+                .zip(parameters.iter())
+                .map(|(cell, column)| {
+                    cell.parse::<f64>().map_err(|_| MyError::NonNumericCell {
+                        line,
+                        column: column.clone(),
+                    })
+                })
+                .collect::<Result<Vec<_>, _>>()
         })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok((observed, parameters))
+}
+
+/// Returns the index of a column of a CSV.
+fn column_index(parameters: &[String], column: &str) -> Result<usize, MyError> {
+    parameters
+        .iter()
+        .position(|p| p == column)
+        .ok_or_else(|| MyError::MissingColumn(column.to_string()))
+}
+
+/// Returns the index of the value column of prepared data - the first column
+/// but DATE, DOY, PRCP and COUNT: TMAX, TMIN, TAVG, their anomalies (see
+/// [`prepare`]) or their detrended values (see [`detrend`]).
+fn value_column_index(parameters: &[String]) -> Result<usize, MyError> {
+    parameters
+        .iter()
+        .position(|p| !["DATE", "DOY", "PRCP", "COUNT"].contains(&p.as_str()))
+        .ok_or_else(|| MyError::MissingColumn(String::from("TMAX, TMIN or TAVG")))
+}
+
+/// Keeps `n` of the observations drawn at random with `seed` - in their
+/// original order. All of them are kept if `n` is 0 or not less than their
+/// number.
+fn subsample(observed: Vec<Vec<f64>>, n: usize, seed: u64) -> Vec<Vec<f64>> {
+    if n == 0 || n >= observed.len() {
+        return observed;
+    }
+
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut kept = rand::seq::index::sample(&mut rng, observed.len(), n).into_vec();
+    kept.sort_unstable();
+
+    kept.into_iter().map(|i| observed[i].clone()).collect()
+}
+
+/// Moves the `x_column`, the `y_column` and the `predictor_columns` of a CSV to
+/// the front - the layout the regression expects: the predictor, the response,
+/// the other predictors and then the other columns in order.
+///
+/// Fails with [`MyError::ColumnOutOfRange`] if an index is beyond the width of
+/// the rows and with [`MyError::DuplicateColumn`] if a column is selected
+/// twice.
+fn select_columns(
+    observed: Vec<Vec<f64>>,
+    parameters: Vec<String>,
+    x_column: usize,
+    y_column: usize,
+    predictor_columns: &[usize],
+) -> Result<(Vec<Vec<f64>>, Vec<String>), MyError> {
+    let width = parameters.len();
+    let selected = [x_column, y_column]
+        .iter()
+        .chain(predictor_columns)
+        .copied()
+        .collect::<Vec<_>>();
+    for (i, &index) in selected.iter().enumerate() {
+        if index >= width {
+            return Err(MyError::ColumnOutOfRange { index, width });
+        }
+        if selected[..i].contains(&index) {
+            return Err(MyError::DuplicateColumn(index));
+        }
+    }
+
+    let order = selected
+        .iter()
+        .copied()
+        .chain((0..width).filter(|i| !selected.contains(i)))
         .collect::<Vec<_>>();
 
-    (observed, parameters)
+    let observed = observed
+        .iter()
+        .map(|row| order.iter().map(|i| row[*i]).collect())
+        .collect();
+    let parameters = order.iter().map(|i| parameters[*i].clone()).collect();
+
+    Ok((observed, parameters))
+}
+
+/// Returns the columns of a CSV that are more predictors by default - all but
+/// the `x_column`, the `y_column`, COUNT (a weight) and ID.
+fn default_predictor_columns(
+    parameters: &[String],
+    x_column: usize,
+    y_column: usize,
+) -> Vec<usize> {
+    (0..parameters.len())
+        .filter(|i| *i != x_column && *i != y_column)
+        .filter(|i| !["COUNT", "ID"].contains(&parameters[*i].as_str()))
+        .collect()
+}
+
+/// Reject a predictor whose variance is (nearly) zero.
+///
+/// Centering such a predictor makes it vanish and its coefficient becomes
+/// unidentifiable, which leads to diverging runs. Fails with
+/// [`MyError::NoData`] if there is no observation.
+fn check_predictor_variance(column: &str, x: &[f64]) -> Result<(), MyError> {
+    if x.is_empty() {
+        return Err(MyError::NoData);
+    }
+
+    let n = x.len() as f64;
+    let mean = x.iter().sum::<f64>() / n;
+    let var = x.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / n;
+
+    // relative to the magnitude of the values
+    if var.sqrt() <= 1e-9 * mean.abs().max(1.) {
+        return Err(MyError::ZeroVariancePredictor(column.to_string()));
+    }
+
+    Ok(())
+}
+
+/// Reject the scale of a prior given as the argument `name` unless it is a
+/// positive number.
+fn check_prior_scale(name: &str, scale: Option<f64>) -> Result<(), MyError> {
+    match scale {
+        Some(scale) if !(scale.is_finite() && scale > 0.) => Err(MyError::InvalidPrior(format!(
+            "{} = {} is not a positive number",
+            name, scale
+        ))),
+        _ => Ok(()),
+    }
 }
 
 /// Returns the date as a float representing the time in years
 /// The input date is a string in the format YYYYMMDD.
 fn parse_date(date: &str) -> Result<f64, MyError> {
-    let year = date[0..4].parse::<i32>().unwrap();
-    let month = date[4..6].parse::<u32>().unwrap();
-    let day = date[6..8].parse::<u32>().unwrap();
+    parse_date_rel(date, 0)
+}
+
+/// Returns the date as a float representing the time in years since the
+/// start of `epoch_year` - e.g. 24.5 for mid-2024 with an epoch year of 2000.
+/// The input date is a string in the format YYYYMMDD.
+fn parse_date_rel(date: &str, epoch_year: i32) -> Result<f64, MyError> {
+    let date = parse_naive_date(date)?;
+
+    years_since_epoch(date, epoch_year)
+}
+
+/// Returns the date as a float representing the time in years since the
+/// start of `epoch_year`, the day of the year (1 to 366 - leap years
+/// included) and the year.
+/// The input date is a string in the format YYYYMMDD.
+fn parse_date_components(date: &str, epoch_year: i32) -> Result<(f64, u32, i32), MyError> {
+    let date = parse_naive_date(date)?;
+
+    Ok((
+        years_since_epoch(date, epoch_year)?,
+        date.ordinal(),
+        date.year(),
+    ))
+}
+
+/// Parse a date in the format YYYYMMDD.
+///
+/// Fails with [`MyError::InvalidDateFormat`] - rather than panicking - unless
+/// the string is exactly 8 digits of a valid date.
+fn parse_naive_date(date: &str) -> Result<NaiveDate, MyError> {
+    if date.len() != 8 || !date.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(MyError::InvalidDateFormat);
+    }
+
+    let year = date[0..4].parse::<i32>();
+    let month = date[4..6].parse::<u32>();
+    let day = date[6..8].parse::<u32>();
+
+    match (year, month, day) {
+        (Ok(year), Ok(month), Ok(day)) => {
+            NaiveDate::from_ymd_opt(year, month, day).ok_or(MyError::InvalidDateFormat)
+        }
+        _ => Err(MyError::InvalidDateFormat),
+    }
+}
+
+/// Format of the dates of a generic time series - see [`prepare_generic`]
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DateFormat {
+    /// Dates in (fractional) years - e.g. 2024.5
+    #[default]
+    YearFraction,
+    /// Dates as YYYYMMDD - e.g. 20240701 - converted to years as [`prepare`]
+    /// does
+    Yyyymmdd,
+}
 
-    let date =
-        chrono::NaiveDate::from_ymd_opt(year, month, day).ok_or(MyError::InvalidDateFormat)?;
+impl DateFormat {
+    /// Returns the date as a float representing the time in years.
+    fn parse(self, date: &str) -> Result<f64, MyError> {
+        match self {
+            DateFormat::YearFraction => date
+                .parse::<f64>()
+                .ok()
+                .filter(|date| date.is_finite())
+                .ok_or(MyError::InvalidDateFormat),
+            DateFormat::Yyyymmdd => {
+                if date.len() != 8 || !date.bytes().all(|b| b.is_ascii_digit()) {
+                    return Err(MyError::InvalidDateFormat);
+                }
+                parse_date(date)
+            }
+        }
+    }
+}
 
-    let epoch = chrono::NaiveDate::from_ymd_opt(0, 1, 1).unwrap();
+/// Returns the time in years since the start of `epoch_year` - 0000-01-01
+/// for an epoch year of 0.
+///
+/// Fails with [`MyError::InvalidEpochYear`] if the epoch year is out of the
+/// range of the dates.
+fn years_since_epoch(date: NaiveDate, epoch_year: i32) -> Result<f64, MyError> {
+    let epoch =
+        NaiveDate::from_ymd_opt(epoch_year, 1, 1).ok_or(MyError::InvalidEpochYear(epoch_year))?;
 
     let duration = date.signed_duration_since(epoch);
     let duration = duration.num_seconds() as f64;
@@ -84,183 +830,4015 @@ fn parse_date(date: &str) -> Result<f64, MyError> {
     Ok(duration / (365.25 * 24.0 * 60.0 * 60.0))
 }
 
+/// Temperature units of the prepared data
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum Units {
+    /// Degrees Celsius
+    #[default]
+    Celsius,
+    /// Degrees Fahrenheit
+    Fahrenheit,
+    /// Kelvin
+    Kelvin,
+}
+
+impl std::str::FromStr for Units {
+    type Err = MyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "C" => Ok(Units::Celsius),
+            "F" => Ok(Units::Fahrenheit),
+            "K" => Ok(Units::Kelvin),
+            _ => Err(MyError::UnknownUnits(s.to_string())),
+        }
+    }
+}
+
+impl Units {
+    /// Convert a temperature in tenths of degrees Celsius - as in the raw data.
+    fn convert_tenths_of_celsius(self, value: i32) -> f64 {
+        let celsius = value as f64 / 10.0;
+
+        match self {
+            Units::Celsius => celsius,
+            Units::Fahrenheit => celsius * 9. / 5. + 32.,
+            Units::Kelvin => celsius + 273.15,
+        }
+    }
+}
+
+/// Temperature element of the NOAA GHCN daily data
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Element {
+    /// Maximum temperature
+    Tmax,
+    /// Minimum temperature
+    Tmin,
+    /// Average temperature
+    Tavg,
+}
+
+impl std::str::FromStr for Element {
+    type Err = MyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "TMAX" => Ok(Element::Tmax),
+            "TMIN" => Ok(Element::Tmin),
+            "TAVG" => Ok(Element::Tavg),
+            _ => Err(MyError::UnsupportedElement(s.to_string())),
+        }
+    }
+}
+
+impl Element {
+    /// Name of the element in the raw data - and of its column in the
+    /// prepared data.
+    fn name(self) -> &'static str {
+        match self {
+            Element::Tmax => "TMAX",
+            Element::Tmin => "TMIN",
+            Element::Tavg => "TAVG",
+        }
+    }
+}
+
+/// Prepare a generic time series for the regression
+///
+/// The input data is a two-column CSV - a date and a value, e.g. "DATE,VALUE".
+/// The first line is the header, whatever the names of its columns. Unlike
+/// [`prepare`], the data need not come from NOAA: there is no ELEMENT, no
+/// flag and no unit conversion. The dates are in `date_format` - fractional
+/// years by default, or YYYYMMDD strings.
+///
+/// The output data is a CSV with the following header:
+/// "DATE,VALUE"
+///
+/// Blank lines and lines starting with '#' are skipped. Fails with
+/// [`MyError::MalformedRow`] if a row has not two columns or its value is not
+/// a number, with [`MyError::InvalidDateFormat`] if a date is not in
+/// `date_format` and with [`MyError::NoData`] if there is no row.
+#[wasm_bindgen]
+pub fn prepare_generic(
+    raw_data: String,
+    date_format: Option<DateFormat>,
+) -> Result<String, MyError> {
+    set_panic_hook();
+
+    let date_format = date_format.unwrap_or_default();
+
+    let mut data = String::from("DATE,VALUE\n");
+    let mut lines = csv_lines(&raw_data).into_iter();
+    // the header - whatever the names of the columns
+    lines.next();
+
+    let mut rows = 0;
+    for (line, row) in lines {
+        let fields = row.split(',').map(str::trim).collect::<Vec<_>>();
+        let (date, value) = match fields.as_slice() {
+            [date, value] => (date, value),
+            _ => return Err(MyError::MalformedRow { line }),
+        };
+        let date = date_format.parse(date)?;
+        let value = value
+            .parse::<f64>()
+            .map_err(|_| MyError::MalformedRow { line })?;
+
+        data.push_str(format!("{},{}\n", date, value).as_str());
+        rows += 1;
+    }
+
+    if rows == 0 {
+        return Err(MyError::NoData);
+    }
+
+    Ok(data)
+}
+
+/// Header of the NOAA GHCN daily data
+const RAW_DATA_HEADER: &str = "ID,DATE,ELEMENT,DATA_VALUE,M_FLAG,Q_FLAG,S_FLAG,OBS_TIME";
+
 /// Prepare the data for the regression
 /// The input data is a CSV with the following header:
 /// "ID,DATE,ELEMENT,DATA_VALUE,M_FLAG,Q_FLAG,S_FLAG,OBS_TIME"
 /// The output data is a CSV with the following header:
 /// "DATE,TMAX"
+///
+/// Only the observations of `element` are kept: "TMAX", "TMIN" or "TAVG" - the
+/// name of the output column (TMAX above). Fails with
+/// [`MyError::UnsupportedElement`] for any other element.
+///
+/// When `with_doy` is set, the day of the year (1 to 366) is emitted as a second
+/// feature for seasonal models and the header becomes:
+/// "DATE,DOY,TMAX"
+///
+/// The temperatures are in `units`: "C" (default), "F" or "K".
+///
+/// DATE is in years since the start of `epoch_year` - 0 (0000-01-01) by
+/// default: with an epoch year of 2000, mid-2024 is 24.5. Fails with
+/// [`MyError::InvalidEpochYear`] if the year is out of the range of the dates.
+///
+/// When `relative_dates` is set, DATE is in years since the earliest
+/// observation (starting at 0) instead of years since 0000-01-01 - smaller
+/// values that keep higher-order models well-conditioned. For a model on
+/// uncentered dates, the intercept is then TMAX at the start of the record
+/// (the regression centers the dates anyway).
+///
+/// With a `baseline` - `[first_year, last_year]`, e.g. `[1961, 1990]` for the
+/// 1961-1990 normal - the values are anomalies: TMAX minus its mean over the
+/// observations of these calendar years. The TMAX column is then named
+/// TMAX_ANOMALY (TMIN_ANOMALY for TMIN...) - which the plots caption
+/// accordingly. Fails with
+/// [`MyError::EmptyBaseline`] if no observation falls in the baseline period,
+/// and with [`MyError::InvalidBaseline`] if it is not two years in order.
+///
+/// Fails with [`MyError::MalformedRow`] if a row misses a field or the value of
+/// an observation of `element` is not an integer.
 #[wasm_bindgen]
-pub fn prepare(raw_data: String) -> Result<String, MyError> {
+pub fn prepare(
+    raw_data: String,
+    element: &str,
+    with_doy: Option<bool>,
+    units: Option<String>,
+    relative_dates: Option<bool>,
+    baseline: Option<Vec<i32>>,
+    epoch_year: Option<i32>,
+) -> Result<String, MyError> {
+    let element = element.parse::<Element>()?;
+    let epoch_year = epoch_year.unwrap_or(0);
+    let with_doy = with_doy.unwrap_or(false);
+    let relative_dates = relative_dates.unwrap_or(false);
+    let units = match units {
+        Some(units) => units.parse::<Units>()?,
+        None => Units::default(),
+    };
+
     // receive data as CSV with the following header:
     // ID,DATE,ELEMENT,DATA_VALUE,M_FLAG,Q_FLAG,S_FLAG,OBS_TIME
-    const EXPECTED_HEADER: &str = "ID,DATE,ELEMENT,DATA_VALUE,M_FLAG,Q_FLAG,S_FLAG,OBS_TIME";
 
-    let raw_data = raw_data.trim();
-    let lines: Vec<_> = raw_data.split('\n').collect();
-    let header = lines[0];
+    let lines = csv_lines(&raw_data);
+    let header = lines.first().map(|(_, header)| *header).unwrap_or_default();
 
-    if header != EXPECTED_HEADER {
+    if header != RAW_DATA_HEADER {
         return Err(MyError::UnexpectedRawDataHeader);
     }
 
+    let baseline = match baseline.as_deref() {
+        Some(&[first_year, last_year]) if first_year <= last_year => Some((first_year, last_year)),
+        Some(_) => return Err(MyError::InvalidBaseline(baseline.unwrap_or_default())),
+        None => None,
+    };
+    let value_column = if baseline.is_some() {
+        format!("{}_ANOMALY", element.name())
+    } else {
+        element.name().to_string()
+    };
+
     let mut output = String::new();
-    // the output header is: DATE,TMAX
-    output.push_str("DATE,TMAX\n");
+    // the output header is: DATE,<value column> or DATE,DOY,<value column>
+    if with_doy {
+        output.push_str(format!("DATE,DOY,{}\n", value_column).as_str());
+    } else {
+        output.push_str(format!("DATE,{}\n", value_column).as_str());
+    }
+
+    // (date, day of the year, value) of the observations of the element
+    let mut rows = vec![];
+    // the values of the baseline period
+    let mut baseline_values = vec![];
+    for &(line, row) in lines.iter().skip(1) {
+        let fields: Vec<_> = row.split(',').collect();
+        let field = |i: usize| fields.get(i).copied().ok_or(MyError::MalformedRow { line });
+        let date = field(1)?;
+        let data_value = field(3)?;
+        let q_flag = field(5)?;
+
+        if field(2)? == element.name() && q_flag.is_empty() {
+            let data_value = data_value
+                .parse::<i32>()
+                .map_err(|_| MyError::MalformedRow { line })?;
+            let data_value = units.convert_tenths_of_celsius(data_value);
+
+            // convert the date to years (float) since the epoch
+            let (date, doy, year) = parse_date_components(date, epoch_year)?;
+            rows.push((date, with_doy.then_some(doy), data_value));
+
+            if let Some((first_year, last_year)) = baseline {
+                if first_year <= year && year <= last_year {
+                    baseline_values.push(data_value);
+                }
+            }
+        }
+    }
 
-    for line in lines.iter().skip(1) {
-        let line = line.trim();
-        let fields: Vec<_> = line.split(',').collect();
-        let date = fields[1];
-        let element = fields[2];
-        let data_value = fields[3];
-        let q_flag = fields[5];
+    let offset = match baseline {
+        Some((first_year, last_year)) if baseline_values.is_empty() => {
+            return Err(MyError::EmptyBaseline {
+                first_year,
+                last_year,
+            });
+        }
+        Some(_) => stats::mean(&baseline_values),
+        None => 0.,
+    };
 
-        if element == "TMAX" && q_flag.is_empty() {
-            // convert the date to years (float) since EPOCH
-            let date = parse_date(date)?;
-            let data_value = data_value.parse::<i32>().unwrap() as f64 / 10.0;
+    let origin = if relative_dates {
+        rows.iter().map(|row| row.0).fold(f64::INFINITY, f64::min)
+    } else {
+        0.
+    };
 
-            output.push_str(format!("{},{}\n", date, data_value).as_str());
+    for (date, doy, data_value) in rows {
+        let date = date - origin;
+        let data_value = data_value - offset;
+        match doy {
+            Some(doy) => output.push_str(format!("{},{},{}\n", date, doy, data_value).as_str()),
+            None => output.push_str(format!("{},{}\n", date, data_value).as_str()),
         }
     }
 
     Ok(output)
 }
 
-/// Plot the data
+/// Prepare gzip-compressed data for the regression
 ///
-/// The input data is a CSV with the following header:
-/// "DATE,TMAX"
+/// Same as [`prepare`] on the decompressed `raw_data` - GHCN files are often
+/// distributed gzipped.
+#[wasm_bindgen]
+pub fn prepare_gz(
+    raw_data: &[u8],
+    element: &str,
+    with_doy: Option<bool>,
+    units: Option<String>,
+    relative_dates: Option<bool>,
+    baseline: Option<Vec<i32>>,
+    epoch_year: Option<i32>,
+) -> Result<String, MyError> {
+    prepare(
+        gunzip(raw_data)?,
+        element,
+        with_doy,
+        units,
+        relative_dates,
+        baseline,
+        epoch_year,
+    )
+}
+
+/// Default minimum number of observations for a station to be fitted
+const DEFAULT_MIN_OBS: usize = 30;
+
+/// Split raw data with several stations into one raw CSV per station
 ///
-/// The posterior is a CSV with the following header:
-/// "ALPHA,BETA,SIGMA"
+/// The input has the header of [`prepare`]. The output is JSON:
+/// `{"stations": {"<ID>": "<raw CSV>", ...}, "skipped": {"<ID>": count, ...}}`
+/// where each raw CSV keeps the input header and can go through [`prepare`].
 ///
-/// The output is a plot of the data in the canvas with the given id: `canvas_id`.
+/// The stations with fewer than `min_obs` (default 30) observations of
+/// `element` (default "TMAX") - the ones [`prepare`] keeps - are skipped and
+/// reported with their count: a slope fitted on a handful of points would
+/// dominate a comparison with noise. Fails with [`MyError::MalformedRow`] if a
+/// row misses a field, and with [`MyError::UnsupportedElement`] for an element
+/// other than "TMAX", "TMIN" or "TAVG".
 #[wasm_bindgen]
-pub fn plot_tmax(canvas_id: &str, regression_data: String, input_data: String) {
-    set_panic_hook();
+pub fn split_stations(
+    raw_data: String,
+    min_obs: Option<usize>,
+    element: Option<String>,
+) -> Result<String, MyError> {
+    let min_obs = min_obs.unwrap_or(DEFAULT_MIN_OBS);
+    let element = match element {
+        Some(element) => element.parse::<Element>()?,
+        None => Element::Tmax,
+    };
 
-    let (observed, parameters) = parse_csv(input_data);
+    let lines = csv_lines(&raw_data);
+    let header = lines.first().map(|(_, header)| *header).unwrap_or_default();
 
-    let regression = if regression_data.is_empty() {
-        None
-    } else {
-        let (regression, _parameters) = parse_csv(regression_data);
-        Some(regression)
-    };
+    if header != RAW_DATA_HEADER {
+        return Err(MyError::UnexpectedRawDataHeader);
+    }
 
-    let p = plot::TMaxPlot::new(observed, regression, parameters);
+    // (lines, observations of the element) of each station
+    let mut stations: HashMap<&str, (Vec<&str>, usize)> = HashMap::new();
+    for &(line, row) in lines.iter().skip(1) {
+        let fields: Vec<_> = row.split(',').collect();
+        let field = |i: usize| fields.get(i).copied().ok_or(MyError::MalformedRow { line });
+        let (lines, count) = stations.entry(field(0)?).or_default();
+        lines.push(row);
+        if field(2)? == element.name() && field(5)?.is_empty() {
+            *count += 1;
+        }
+    }
+
+    let mut kept = serde_json::Map::new();
+    let mut skipped = serde_json::Map::new();
+    for (id, (lines, count)) in stations {
+        if count < min_obs {
+            skipped.insert(id.to_string(), serde_json::json!(count));
+        } else {
+            let mut csv = format!("{}\n", header);
+            for line in lines {
+                csv.push_str(line);
+                csv.push('\n');
+            }
+            kept.insert(id.to_string(), serde_json::json!(csv));
+        }
+    }
 
-    p.plot(canvas_id);
+    Ok(serde_json::json!({ "stations": kept, "skipped": skipped }).to_string())
 }
 
-/// Run the regression
-///
-/// The input data is a CSV with the following header:
-/// "DATE,TMAX"
+/// Decompresses a gzip stream of UTF-8 text.
+fn gunzip(raw_data: &[u8]) -> Result<String, MyError> {
+    let mut data = String::new();
+    GzDecoder::new(raw_data)
+        .read_to_string(&mut data)
+        .map_err(|e| MyError::InvalidGzip(e.to_string()))?;
+    Ok(data)
+}
+
+/// Merge prepared CSVs on their DATE column
 ///
-/// The output is a plot of the data in the canvas with the given id: `canvas_id`.
-/// The posterior is also stored in the textarea with the given id: `posterior_id`.
+/// Each input is a CSV with a DATE column (e.g. "DATE,TMAX" and "DATE,PRCP" from
+/// the same station). The output is a CSV with the DATE followed by the other
+/// columns of each input, in order: "DATE,TMAX,PRCP".
 ///
-/// The regression is run with the following parameters:
-/// - `seed`: seed for the random number generator - each chain will be seeded with `seed + chain_id`
-/// - `input_data`: the input data
-/// - `chain_count`: number of chains to run
-/// - `tuning`: number of tuning steps
-/// - `samples`: number of samples to draw for each chain
+/// This is an inner join: the dates missing from any of the inputs are dropped.
+/// The rows are in the order of the first input. If a date is repeated within
+/// one of the other inputs, its last row is used.
 #[wasm_bindgen]
-pub fn run_with(
-    canvas_id: &str,
-    posteriod_id: &str,
-    seed: u64,
-    input_data: String,
-    chain_count: u64,
-    tuning: u64,
-    samples: u64,
-) {
+pub fn join_by_date(csvs: Vec<String>) -> Result<String, MyError> {
     set_panic_hook();
-    log("Running");
 
-    let (observed, _parameters) = parse_csv(input_data);
+    let mut tables = vec![];
+    for csv in csvs {
+        let (rows, parameters) = parse_csv(csv)?;
 
-    // let model = MultivariateNormalModel {
-    //     observed,
-    //     dims: parameters.len(),
-    //     parameters,
-    // };
-    // let initial_position = vec![0.0; model.dim()];
-    let x = observed.iter().map(|x| x[0]).collect::<Vec<_>>();
-    let y = observed.iter().map(|x| x[1]).collect::<Vec<_>>();
+        let date_idx = column_index(&parameters, "DATE")?;
 
-    if x.len() != y.len() {
-        panic!("x and y must have the same length");
+        tables.push((rows, parameters, date_idx));
     }
 
-    if x.is_empty() {
-        panic!("x and y must have at least one element");
+    let mut output = String::from("DATE");
+    for (_, parameters, date_idx) in &tables {
+        for (i, parameter) in parameters.iter().enumerate() {
+            if i != *date_idx {
+                output.push(',');
+                output.push_str(parameter);
+            }
+        }
     }
+    output.push('\n');
 
-    // Use the middle of the time period as reference
-    // to prevent strong correlations between alpha and beta
-    let x_m = x.iter().sum::<f64>() / x.len() as f64;
-
-    let x = x.iter().map(|x| x - x_m).collect::<Vec<_>>();
-
-    let model = Regression::new(x.clone(), y.clone());
-
-    // y = alpha + beta * x + noise
-    let guessed_beta = 0.;//y.iter().sum::<f64>() / x.iter().sum::<f64>();
-    let guessed_alpha = y.iter().sum::<f64>() / y.len() as f64;
-    let guessed_sigma = 1.;
-    let initial_position = vec![guessed_alpha, guessed_beta, guessed_sigma];
-    log(format!("initial_position = {:?}", initial_position).as_str());
-
-    let chains = chain::Chains::run(seed, model, chain_count, tuning, samples, initial_position);
-
-    log("Plotting");
+    let Some(((first, _, first_date_idx), others)) = tables.split_first() else {
+        return Ok(output);
+    };
 
-    chains.plot(canvas_id, &chains, samples);
+    // index the other tables by date - the bits of the parsed date are exact
+    // since all the files go through the same conversion
+    let others = others
+        .iter()
+        .map(|(rows, _, date_idx)| {
+            let by_date = rows
+                .iter()
+                .map(|row| (row[*date_idx].to_bits(), row))
+                .collect::<HashMap<_, _>>();
+            (by_date, *date_idx)
+        })
+        .collect::<Vec<_>>();
 
-    log("Sampling posterior");
-    const POSTERIOR_SAMPLES: usize = 10;
-    let posterior = chains.sample_posterior(POSTERIOR_SAMPLES);
-    let text_area = web_sys::window()
-        .unwrap()
-        .document()
-        .unwrap()
-        .get_element_by_id(posteriod_id)
-        .unwrap();
+    'rows: for row in first {
+        let date = row[*first_date_idx];
 
-    let mut posterior_str = String::new();
-    // store the posterior in the textarea as a CSV
-    // the header is: ALPHA,BETA,SIGMA (same as the model parameters)
-    posterior_str.push_str(chains.parameters.join(",").as_str());
-    posterior_str.push('\n');
+        let mut line = date.to_string();
+        let mut push_values = |row: &[f64], date_idx: usize| {
+            for (i, value) in row.iter().enumerate() {
+                if i != date_idx {
+                    line.push_str(format!(",{}", value).as_str());
+                }
+            }
+        };
 
-    for i in 0..POSTERIOR_SAMPLES {
-        let mut line = vec![];
-        for parameter in chains.parameters.iter() {
-            line.push(format!("{}", posterior.get(parameter).unwrap()[i]));
+        push_values(row, *first_date_idx);
+        for (by_date, date_idx) in &others {
+            match by_date.get(&date.to_bits()) {
+                Some(other) => push_values(other, *date_idx),
+                None => continue 'rows,
+            }
         }
 
-        posterior_str.push_str(line.join(",").as_str());
-        posterior_str.push('\n');
+        output.push_str(&line);
+        output.push('\n');
     }
-    text_area.set_text_content(Some(posterior_str.as_str()));
 
-    log("Done");
+    Ok(output)
+}
+
+/// Plot the data
+///
+/// The input data is a CSV with the following header:
+/// "DATE,TMAX"
+///
+/// The posterior is a CSV with the following header:
+/// "ALPHA,BETA,SIGMA"
+///
+/// The output is a plot of the data in the canvas with the given id: `canvas_id`.
+/// The optional `style` controls the colors, markers and lines - red markers and
+/// faint blue regression lines by default. The observations are sorted by date
+/// unless `style.sort_by_date` is disabled. A centered moving average of TMAX -
+/// a quick descriptive trend - is drawn with a positive
+/// `style.moving_average_window`. Above `style.max_points` observations (10000
+/// by default), the markers give the mean and the range of TMAX in bins of
+/// dates instead - the regression lines still use every observation.
+///
+/// With `log_response`, the posterior is the one of a regression of `log(TMAX)`
+/// (see [`run_with`]): the regression lines are back-transformed to the mean of
+/// the lognormal, `exp(alpha + beta * (date - mean(date)) + sigma² / 2)`.
+///
+/// Fails with [`MyError::CanvasUnavailable`] if the canvas cannot be drawn on,
+/// with [`MyError::RegressionRowTooShort`] if a row of the posterior lacks
+/// one of ALPHA, BETA or SIGMA, with [`MyError::NoData`] if the input data
+/// has no observation, with [`MyError::UnexpectedColumns`] if its columns are
+/// not DATE and a temperature element or VALUE, or with
+/// [`MyError::NonNumericCell`] or [`MyError::ColumnCountMismatch`] if a CSV is
+/// malformed.
+///
+/// The caption is `title` - "TMax (C)" by default, or "TMax anomaly (C)" for
+/// the anomalies of [`prepare`] (a "DATE,TMAX_ANOMALY" input).
+#[wasm_bindgen]
+pub fn plot_tmax(
+    canvas_id: &str,
+    regression_data: String,
+    input_data: String,
+    style: Option<PlotStyle>,
+    log_response: Option<bool>,
+    title: Option<String>,
+) -> Result<(), MyError> {
+    set_panic_hook();
+
+    tmax_plot(regression_data, input_data, style, log_response, title)?.plot(canvas_id)
 }
 
+/// Plot the data as an SVG
+///
+/// This is [`plot_tmax`] rendered to an SVG document of `width` x `height`
+/// pixels (1200 x 800 by default) instead of a canvas - to download the plot
+/// as a vector graphic. Returns the SVG document.
+///
+/// Fails as [`plot_tmax`] does - but for the canvas.
 #[wasm_bindgen]
-extern "C" {
+pub fn plot_tmax_svg(
+    regression_data: String,
+    input_data: String,
+    style: Option<PlotStyle>,
+    log_response: Option<bool>,
+    title: Option<String>,
+    width: Option<u32>,
+    height: Option<u32>,
+) -> Result<String, MyError> {
+    set_panic_hook();
 
-    #[wasm_bindgen(js_namespace = console)]
-    fn log(s: &str);
+    let p = tmax_plot(regression_data, input_data, style, log_response, title)?;
+    plot::render_svg(svg_size(width, height), |root| p.draw(root))
 }
 
-// Download the data from the given URL
-// #[wasm_bindgen]
-// pub async fn get_data(url: String) -> String {
-//     let data = download(url).await;
-//     data.unwrap().as_string().unwrap()
-// }
+/// The plot of [`plot_tmax`].
+fn tmax_plot(
+    regression_data: String,
+    input_data: String,
+    style: Option<PlotStyle>,
+    log_response: Option<bool>,
+    title: Option<String>,
+) -> Result<plot::TMaxPlot, MyError> {
+    let (observed, parameters) = parse_csv(input_data)?;
+    if observed.is_empty() {
+        return Err(MyError::NoData);
+    }
+
+    let regression = if regression_data.is_empty() {
+        None
+    } else {
+        let (regression, _parameters) = parse_csv(regression_data)?;
+        Some(regression)
+    };
+
+    let mut p = plot::TMaxPlot::new(observed, regression, parameters, style.unwrap_or_default())?;
+    if log_response.unwrap_or(false) {
+        p = p.with_log_response();
+    }
+    if let Some(title) = title {
+        p = p.with_title(title);
+    }
+
+    Ok(p)
+}
+
+/// The size of an SVG plot - [`plot::DEFAULT_SVG_SIZE`] for the missing
+/// dimensions.
+fn svg_size(width: Option<u32>, height: Option<u32>) -> (u32, u32) {
+    let (default_width, default_height) = plot::DEFAULT_SVG_SIZE;
+    (
+        width.unwrap_or(default_width),
+        height.unwrap_or(default_height),
+    )
+}
+
+/// Returns the extent of the data
+///
+/// The input data is a CSV with the following header:
+/// "DATE,TMAX"
+///
+/// The output is `[date_min, date_max, tmax_min, tmax_max]` - the ranges of the
+/// axes of [`plot_tmax`]: a single date or a constant TMAX is widened by 1 on
+/// each side. Fails with [`MyError::NoData`] if the input data has no
+/// observation.
+#[wasm_bindgen]
+pub fn data_bounds(input_data: String) -> Result<Vec<f64>, MyError> {
+    set_panic_hook();
+
+    let (observed, _parameters) = parse_csv(input_data)?;
+    if observed.is_empty() {
+        return Err(MyError::NoData);
+    }
+
+    let ((date_min, date_max), (tmax_min, tmax_max)) = plot::data_bounds(&observed);
+
+    Ok(vec![date_min, date_max, tmax_min, tmax_max])
+}
+
+/// Plot the residuals of the regression
+///
+/// The input data is a CSV with the following header:
+/// "DATE,TMAX"
+///
+/// The posterior mean is the mean of the regression parameters: `[alpha, beta, sigma]`.
+///
+/// The output is a plot of the residuals (observed - fitted) against the date and
+/// against the fitted value in the canvas with the given id: `canvas_id`.
+/// Any structure in the residuals (trend, U-shape, funnel) indicates the linear
+/// model is inadequate.
+///
+/// The captions of the panels are prefixed with `title_prefix` if any.
+///
+/// Fails with [`MyError::UnexpectedColumns`] if the columns are not a DATE and a
+/// value, with [`MyError::PosteriorMeanTooShort`] if the posterior mean misses
+/// alpha or beta, or with [`MyError::CanvasUnavailable`] if the canvas cannot
+/// be drawn on.
+#[wasm_bindgen]
+pub fn plot_residuals(
+    canvas_id: &str,
+    input_data: String,
+    posterior_mean: Vec<f64>,
+    title_prefix: Option<String>,
+) -> Result<(), MyError> {
+    set_panic_hook();
+
+    let (observed, parameters) = parse_csv(input_data)?;
+
+    let mut p = plot::ResidualPlot::new(observed, posterior_mean, parameters)?;
+    if let Some(title_prefix) = title_prefix {
+        p = p.with_title_prefix(title_prefix);
+    }
+
+    p.plot(canvas_id)
+}
+
+/// Remove the linear trend from the observations
+///
+/// The input data is a CSV with the following header:
+/// "DATE,TMAX" or "DATE,DOY,TMAX" - or TMIN, TAVG or their anomalies in place
+/// of TMAX (see [`prepare`]).
+///
+/// The posterior mean is the mean of the regression parameters: `[alpha, beta, ...]`.
+///
+/// The output is a CSV of the observations minus the posterior-mean line, with
+/// the header "DATE,TMAX_DETRENDED" - or "DATE,DOY,TMAX_DETRENDED" when the input
+/// has the day of the year (TMIN_DETRENDED for TMIN...). What remains is the seasonal signal (and noise) -
+/// see [`plot_seasonal`]. Fails with [`MyError::PosteriorMeanTooShort`] if the
+/// posterior mean misses alpha or beta.
+#[wasm_bindgen]
+pub fn detrend(input_data: String, posterior_mean: Vec<f64>) -> Result<String, MyError> {
+    set_panic_hook();
+
+    if posterior_mean.len() < 2 {
+        return Err(MyError::PosteriorMeanTooShort(posterior_mean.len()));
+    }
+
+    let (observed, parameters) = parse_csv(input_data)?;
+
+    let date_idx = column_index(&parameters, "DATE")?;
+    let value_idx = value_column_index(&parameters)?;
+    let doy_idx = column_index(&parameters, "DOY").ok();
+
+    let (alpha, beta) = (posterior_mean[0], posterior_mean[1]);
+
+    // same reference as the regression - the middle of the time period
+    let x_m = observed.iter().map(|x| x[date_idx]).sum::<f64>() / observed.len() as f64;
+
+    let value_column = &parameters[value_idx];
+    let mut output = match doy_idx {
+        Some(_) => format!("DATE,DOY,{}_DETRENDED\n", value_column),
+        None => format!("DATE,{}_DETRENDED\n", value_column),
+    };
+
+    for row in &observed {
+        let date = row[date_idx];
+        let detrended = row[value_idx] - (alpha + beta * (date - x_m));
+
+        match doy_idx {
+            Some(doy_idx) => {
+                output.push_str(format!("{},{},{}\n", date, row[doy_idx], detrended).as_str())
+            }
+            None => output.push_str(format!("{},{}\n", date, detrended).as_str()),
+        }
+    }
+
+    Ok(output)
+}
+
+/// Plot the detrended observations against the day of the year
+///
+/// The input data is a CSV with the following header:
+/// "DATE,DOY,TMAX_DETRENDED" - as returned by [`detrend`] for data prepared with
+/// the day of the year (TMIN_DETRENDED for TMIN...).
+///
+/// The output is a plot of the annual cycle in the canvas with the given id: `canvas_id`.
+/// The caption is `title` - "Detrended TMax vs day of year" by default.
+#[wasm_bindgen]
+pub fn plot_seasonal(
+    canvas_id: &str,
+    detrended_data: String,
+    title: Option<String>,
+) -> Result<(), MyError> {
+    set_panic_hook();
+
+    let (observed, parameters) = parse_csv(detrended_data)?;
+
+    let doy_idx = column_index(&parameters, "DOY")?;
+    let value_idx = value_column_index(&parameters)?;
+
+    let points = observed
+        .iter()
+        .map(|row| (row[doy_idx], row[value_idx]))
+        .collect();
+
+    let mut p = plot::SeasonalPlot::new(points);
+    if let Some(title) = title {
+        p = p.with_title(title);
+    }
+
+    p.plot(canvas_id)
+}
+
+/// Plot TMAX and PRCP together
+///
+/// The input data is a CSV with the following header:
+/// "DATE,TMAX,PRCP" - as returned by [`join_by_date`] for the TMAX and the PRCP
+/// of a station. TMIN, TAVG or their anomalies can take the place of TMAX.
+///
+/// The output is a plot in the canvas with the given id: `canvas_id` - TMAX as
+/// a line on the left axis and PRCP as bars on the right axis, each on its own
+/// scale. The caption is `title` - "TMax (C) and PRCP (mm)" by default.
+///
+/// Fails with [`MyError::MissingColumn`] if a column is missing, with
+/// [`MyError::NoData`] if the input data has no observation, or with
+/// [`MyError::CanvasUnavailable`] if the canvas cannot be drawn on.
+#[wasm_bindgen]
+pub fn plot_tmax_prcp(
+    canvas_id: &str,
+    input_data: String,
+    title: Option<String>,
+) -> Result<(), MyError> {
+    set_panic_hook();
+
+    let (observed, parameters) = parse_csv(input_data)?;
+
+    let date_idx = column_index(&parameters, "DATE")?;
+    let value_idx = value_column_index(&parameters)?;
+    let prcp_idx = column_index(&parameters, "PRCP")?;
+
+    if observed.is_empty() {
+        return Err(MyError::NoData);
+    }
+
+    let observed = observed
+        .iter()
+        .map(|row| (row[date_idx], row[value_idx], row[prcp_idx]))
+        .collect();
+
+    let mut p = plot::DualAxisPlot::new(observed);
+    if let Some(title) = title {
+        p = p.with_title(title);
+    }
+
+    p.plot(canvas_id)
+}
+
+/// Simulate new datasets from the posterior
+///
+/// The input data is a CSV with the following header:
+/// "DATE,TMAX"
+///
+/// The posterior is a CSV with the following header:
+/// "ALPHA,BETA,SIGMA"
+///
+/// For each posterior draw, a new TMAX is simulated at each DATE of the input
+/// data: `y ~ Normal(alpha + beta * (date - mean(date)), sigma)`. Comparing
+/// these to the observed data is a posterior predictive check.
+///
+/// Returns the simulated datasets flattened draw by draw: the value for the
+/// i-th date of the d-th draw is at index `d * n_dates + i`.
+#[wasm_bindgen]
+pub fn simulate_posterior_predictive(
+    regression_data: String,
+    input_data: String,
+    seed: u64,
+) -> Result<Vec<f64>, MyError> {
+    set_panic_hook();
+
+    let (observed, _parameters) = parse_csv(input_data)?;
+    let (draws, _parameters) = parse_csv(regression_data)?;
+
+    let xs = observed.iter().map(|x| x[0]).collect::<Vec<_>>();
+    let x0 = stats::mean(&xs);
+
+    Ok(predictive::simulate_posterior_predictive(&draws, &xs, x0, seed).concat())
+}
+
+/// Predict TMAX at arbitrary dates - e.g. in 2050
+///
+/// The posterior is a CSV with the following header:
+/// "ALPHA,BETA,SIGMA"
+///
+/// `query_years` are dates in years - as the DATE column of the prepared data -
+/// and `x0` is the mean DATE of the data the regression was run on (the
+/// reference of the intercept).
+///
+/// For each query date, a TMAX is simulated for each posterior draw:
+/// `y ~ Normal(alpha + beta * (date - x0), sigma)`. The intervals of these
+/// draws include both the uncertainty of the trend and the noise.
+///
+/// With `log_response`, the posterior is the one of a regression of `log(TMAX)`
+/// (see [`run_with`]) and the draws are back-transformed with `exp`.
+///
+/// Returns the draws flattened query by query: the draw `d` for the q-th query
+/// date is at index `q * n_draws + d`.
+#[wasm_bindgen]
+pub fn predict(
+    regression_data: String,
+    query_years: Vec<f64>,
+    x0: f64,
+    seed: u64,
+    log_response: Option<bool>,
+) -> Result<Vec<f64>, MyError> {
+    set_panic_hook();
+
+    let (draws, _parameters) = parse_csv(regression_data)?;
+
+    let predictions = predictive::predict(&draws, &query_years, x0, seed).concat();
+    if log_response.unwrap_or(false) {
+        Ok(predictions.iter().map(|y| y.exp()).collect())
+    } else {
+        Ok(predictions)
+    }
+}
+
+/// Credible intervals of the mean TMAX at given dates
+///
+/// The posterior is a CSV with the following header:
+/// "ALPHA,BETA,SIGMA"
+///
+/// `query_years` and `x0` are as in [`predict`]. Unlike [`predict`], the
+/// intervals are the ones of the fitted line `alpha + beta * (date - x0)` -
+/// without the noise: "the mean TMAX in 1990 was 14.2 (13.9 - 14.5)".
+///
+/// The interval is the central `prob` one - 95% by default. Its bounds are
+/// quantiles of the draws computed with `method` - linear interpolation by
+/// default, as NumPy and pandas do.
+///
+/// Returns a JSON object keyed by query date:
+/// `{"1990": {"mean": 14.2, "low": 13.9, "high": 14.5}, ...}`
+#[wasm_bindgen]
+pub fn line_intervals(
+    regression_data: String,
+    query_years: Vec<f64>,
+    x0: f64,
+    prob: Option<f64>,
+    method: Option<QuantileMethod>,
+) -> Result<String, MyError> {
+    set_panic_hook();
+
+    let prob = prob.unwrap_or(0.95);
+    let method = method.unwrap_or_default();
+    let (draws, _parameters) = parse_csv(regression_data)?;
+
+    let intervals = query_years
+        .iter()
+        .map(|year| {
+            let (mean, low, high) = predictive::line_interval(&draws, *year, x0, prob, method);
+            (
+                year.to_string(),
+                serde_json::json!({ "mean": mean, "low": low, "high": high }),
+            )
+        })
+        .collect::<serde_json::Map<_, _>>();
+
+    Ok(serde_json::Value::Object(intervals).to_string())
+}
+
+/// Plot the posterior of the mean TMAX at a given date
+///
+/// The posterior is a CSV with the following header:
+/// "ALPHA,BETA,SIGMA"
+///
+/// The fitted line `alpha + beta * (query_year - x0)` is computed for each
+/// draw - `x0` as in [`predict`] - and its histogram is plotted in the canvas
+/// with the given id: `canvas_id`. This is the distribution behind
+/// [`line_intervals`]. The caption is `title` - "Mean TMAX in <query_year>
+/// (posterior)" by default.
+#[wasm_bindgen]
+pub fn plot_derived(
+    canvas_id: &str,
+    regression_data: String,
+    query_year: f64,
+    x0: f64,
+    title: Option<String>,
+) -> Result<(), MyError> {
+    set_panic_hook();
+
+    let (draws, _parameters) = parse_csv(regression_data)?;
+
+    plot::DerivedPlot::new(
+        &draws,
+        |draw| draw[0] + draw[1] * (query_year - x0),
+        title.unwrap_or_else(|| format!("Mean TMAX in {} (posterior)", query_year)),
+    )
+    .plot(canvas_id)
+}
+
+/// Check the calibration of the regression against the data
+///
+/// The input data is a CSV with the following header:
+/// "DATE,TMAX"
+///
+/// The posterior is a CSV with the following header:
+/// "ALPHA,BETA,SIGMA"
+///
+/// Returns the fraction of the observations within the 90% posterior
+/// predictive interval. A coverage far from 0.9 means the noise model is wrong.
+///
+/// The Bayesian p-value of the variance of the data is logged too.
+#[wasm_bindgen]
+pub fn posterior_predictive_coverage(
+    regression_data: String,
+    input_data: String,
+    seed: u64,
+) -> Result<f64, MyError> {
+    set_panic_hook();
+
+    let (observed, _parameters) = parse_csv(input_data)?;
+    let (draws, _parameters) = parse_csv(regression_data)?;
+
+    let xs = observed.iter().map(|x| x[0]).collect::<Vec<_>>();
+    let ys = observed.iter().map(|x| x[1]).collect::<Vec<_>>();
+    let x0 = stats::mean(&xs);
+
+    let simulated = predictive::simulate_posterior_predictive(&draws, &xs, x0, seed);
+
+    let p_value = predictive::variance_p_value(&ys, &simulated);
+    log(format!("variance p-value = {}", p_value).as_str());
+
+    Ok(predictive::coverage(&ys, &simulated, 0.9))
+}
+
+/// Check the calibration of the regression against the data at several levels
+///
+/// The input data is a CSV with the following header:
+/// "DATE,TMAX"
+///
+/// The posterior is a CSV with the following header:
+/// "ALPHA,BETA,SIGMA"
+///
+/// For each nominal level of `levels` - 10%, 20%, ..., 90% by default - the
+/// fraction of the observations within the central posterior predictive
+/// interval of that level is computed, as [`posterior_predictive_coverage`]
+/// does for the 90% one. A well-calibrated model lies on the diagonal: an
+/// empirical coverage below the nominal level means too narrow intervals.
+///
+/// Returns a JSON array of the pairs to plot:
+/// `[{"nominal": 0.1, "empirical": 0.12}, ...]`
+#[wasm_bindgen]
+pub fn posterior_predictive_calibration(
+    regression_data: String,
+    input_data: String,
+    seed: u64,
+    levels: Option<Vec<f64>>,
+) -> Result<String, MyError> {
+    set_panic_hook();
+
+    let (observed, _parameters) = parse_csv(input_data)?;
+    let (draws, _parameters) = parse_csv(regression_data)?;
+
+    let xs = observed.iter().map(|x| x[0]).collect::<Vec<_>>();
+    let ys = observed.iter().map(|x| x[1]).collect::<Vec<_>>();
+    let x0 = stats::mean(&xs);
+
+    let simulated = predictive::simulate_posterior_predictive(&draws, &xs, x0, seed);
+
+    let levels = levels.unwrap_or_else(|| predictive::CALIBRATION_LEVELS.to_vec());
+    let curve = predictive::calibration_curve(&ys, &simulated, &levels)
+        .into_iter()
+        .map(|(nominal, empirical)| serde_json::json!({ "nominal": nominal, "empirical": empirical }))
+        .collect::<Vec<_>>();
+
+    Ok(serde_json::Value::Array(curve).to_string())
+}
+
+/// Build the regression model of the 2nd column - or of its log with
+/// `log_response` - against the (centered) 1st one - weighted by the COUNT
+/// column if any.
+///
+/// Fails with [`MyError::InvalidWeight`] if a COUNT is not a positive number.
+///
+/// The 1st column is centered on `center` - its mean by default. The columns
+/// of `predictors` are more predictors, each centered on its mean: the model
+/// then has a slope per predictor, `beta_1` (of the 1st column) to `beta_k`,
+/// and alpha is at the mean of the other predictors. The other columns but
+/// COUNT are ignored.
+///
+/// With `standardize`, the predictor is also divided by its standard
+/// deviation. Returns the model and the scale of the predictor - its standard
+/// deviation, or 1 if not standardized: the slope of the model times this
+/// scale is the slope on the original scale.
+#[allow(clippy::too_many_arguments)]
+fn regression_model(
+    observed: &[Vec<f64>],
+    parameters: &[String],
+    predictors: &[usize],
+    fixed_sigma: Option<f64>,
+    slope_laplace_scale: Option<f64>,
+    log_response: bool,
+    standardize: bool,
+    center: Option<f64>,
+) -> Result<(Regression, f64), MyError> {
+    if observed.is_empty() {
+        return Err(MyError::NoData);
+    }
+
+    let x = observed.iter().map(|x| x[0]).collect::<Vec<_>>();
+    let mut y = observed.iter().map(|x| x[1]).collect::<Vec<_>>();
+
+    if log_response {
+        if let Some(value) = y.iter().find(|y| **y <= 0.) {
+            return Err(MyError::NonPositiveResponse(*value));
+        }
+        y = y.iter().map(|y| y.ln()).collect();
+    }
+
+    check_predictor_variance(&parameters[0], &x)?;
+
+    // Use the middle of the time period as reference
+    // to prevent strong correlations between alpha and beta
+    let x_m = center.unwrap_or_else(|| stats::mean(&x));
+
+    // a slope per standard deviation of the predictor is on a comparable
+    // scale whatever the units of the predictor
+    let x_scale = if standardize { stats::sd(&x) } else { 1. };
+
+    let x = x.iter().map(|x| (x - x_m) / x_scale).collect::<Vec<_>>();
+
+    let mut model = if predictors.is_empty() {
+        Regression::new(x, y)
+    } else {
+        let mut columns = vec![x];
+        for &i in predictors {
+            let covariate = observed.iter().map(|row| row[i]).collect::<Vec<_>>();
+            check_predictor_variance(&parameters[i], &covariate)?;
+            let covariate_mean = stats::mean(&covariate);
+            columns.push(covariate.iter().map(|c| c - covariate_mean).collect());
+        }
+        let rows = (0..y.len())
+            .map(|row| columns.iter().map(|column| column[row]).collect())
+            .collect();
+        Regression::new_multiple(rows, y)?
+    };
+    if let Some(sigma) = fixed_sigma {
+        if !(sigma.is_finite() && sigma > 0.) {
+            return Err(MyError::InvalidFixedSigma(sigma));
+        }
+        model = model.with_fixed_sigma(sigma);
+    }
+    let mut priors = Regression::DEFAULT_PRIORS;
+    if let Some(b) = slope_laplace_scale {
+        priors[1] = PriorSpec::Laplace { b };
+    }
+    model = model.with_priors(priors);
+    // aggregated observations are weighted by the number of values averaged
+    if let Ok(count_idx) = column_index(parameters, "COUNT") {
+        let counts = observed.iter().map(|x| x[count_idx]).collect::<Vec<_>>();
+        if let Some((row, value)) = counts
+            .iter()
+            .enumerate()
+            .find(|(_, c)| !(c.is_finite() && **c > 0.))
+        {
+            return Err(MyError::InvalidWeight { row, value: *value });
+        }
+        model = model.with_weights(counts);
+    }
+
+    Ok((model, x_scale))
+}
+
+/// Find the maximum a posteriori estimate of the regression
+///
+/// The input data is a CSV with the following header:
+/// "DATE,TMAX" (or "DATE,TMAX,COUNT" - see [`run_with`])
+///
+/// The log density is maximized by gradient ascent from the least squares fit.
+/// This is a fast deterministic point estimate - and a good `initial_position`
+/// for [`run_with`]. The estimate tells whether the optimization converged.
+#[wasm_bindgen]
+pub fn find_map(input_data: String, fixed_sigma: Option<f64>) -> Result<MapEstimate, MyError> {
+    set_panic_hook();
+
+    let (observed, parameters) = parse_csv(input_data)?;
+    let (mut model, _) = regression_model(
+        &observed,
+        &parameters,
+        &[],
+        fixed_sigma,
+        None,
+        false,
+        false,
+        None,
+    )?;
+
+    const MAX_ITERATIONS: u32 = 10_000;
+    let initial = model.suggested_initial();
+    let map = optimize::maximize(&mut model, &initial, MAX_ITERATIONS)
+        .map_err(|e| MyError::OptimizationFailed(e.to_string()))?;
+
+    log(format!(
+        "MAP = {:?} after {} iterations (converged: {})",
+        map.position, map.iterations, map.converged
+    )
+    .as_str());
+
+    Ok(map)
+}
+
+/// Run the regression
+///
+/// The input data is a CSV with the following header:
+/// "DATE,TMAX"
+///
+/// For aggregated data (e.g. yearly means), a COUNT column with the number of
+/// values averaged can be appended: "DATE,TMAX,COUNT". Each observation is then
+/// weighted by its count - its variance is `sigma² / COUNT`. A COUNT that is not a
+/// positive number fails with [`MyError::InvalidWeight`].
+///
+/// Any other column but ID is one more predictor - a multiple linear
+/// regression: with "DATE,TMAX,PRCP", `TMAX = alpha + beta_1 * DATE + beta_2 *
+/// PRCP + noise`. The number of slopes `k` is the number of columns beyond the
+/// response but COUNT and ID - `predictor_columns` selects others. The slopes
+/// are named `beta_1` (of DATE) to `beta_k` and alpha is at the mean of the
+/// other predictors. The plots and the posterior textarea show the trend along
+/// DATE - `beta_1` - and the residuals view is skipped. Fails with
+/// [`MyError::FixedBetaNeedsOnePredictor`] with a `fixed_beta` - unless
+/// `predictor_columns` is `[]`.
+///
+/// The output is a plot of the data in the canvas with the given id: `canvas_id`.
+/// Draws of the posterior are also stored as a CSV in the textarea with the
+/// given id: `posterior_id` - if any. If the canvas is unavailable, the plot is
+/// skipped (with a log) - the result is still returned.
+///
+/// The regression is run with the following parameters:
+/// - `seed`: seed for the random number generator - each chain will be seeded with `seed + chain_id`
+/// - `input_data`: the input data
+/// - `chain_count`: number of chains to run
+/// - `tuning`: number of tuning (warmup) steps - these draws are discarded - at least 1, 0
+///   fails with [`MyError::InvalidSamplerArgs`] before sampling
+/// - `samples`: number of post-warmup samples to draw for each chain - at least 4, fewer fail
+///   with [`MyError::NotEnoughDraws`] before sampling
+/// - `options`: optional `RunOptions` object (see its TypeScript definition) - each of its
+///   fields is optional and a missing one keeps the default. Fails with
+///   [`MyError::InvalidRunOptions`] if it is not such an object - e.g. a misspelled field
+///
+/// The fields of `options` are:
+/// - `fixed_sigma`: optional known standard deviation of the noise - when set, sigma is not sampled
+/// - `histogram_mode`: optional way to draw the posterior histograms - per chain by default
+/// - `initial_position`: optional starting point of all the chains - e.g. the posterior mean
+///   (`means`) returned by a previous run. The least squares fit by default. Fails with
+///   [`MyError::WrongPositionLength`] without one value per sampled parameter
+/// - `jitter_scale`: optional standard deviation of the Gaussian noise added to the initial
+///   position of each chain - 0 (no jitter) by default
+/// - `slope_laplace_scale`: optional scale of a Laplace prior on the slope - shrinks it towards 0.
+///   A normal prior is used by default
+/// - `layout`: optional panels and grid of the posterior plots - histogram and trace of one
+///   parameter per row by default
+/// - `seeds`: optional explicit seed of each chain (one per chain) - overrides `seed`
+/// - `max_millis`: optional time budget of the sampling in milliseconds - the chains stop
+///   drawing when it is exhausted and `samples` becomes an upper bound. See
+///   [`samples_collected`] for the number of draws actually collected
+/// - `max_memory_mb`: optional memory budget of the traces in MiB - `chain_count × samples ×
+///   dim × 8` bytes. The run fails upfront if it is exceeded. 256 MiB by default
+/// - `log_response`: optional - fit `log(TMAX)` instead of TMAX, for positive data with a
+///   multiplicative noise. Fails if a value is not positive. The posterior is then on the log
+///   scale - see the `log_response` option of [`plot_tmax`] and [`predict`]
+/// - `max_attempts`: optional - with more than one attempt, the chains are run again with a
+///   higher `target_accept` and twice the tuning while more than 1% of the draws diverge. The
+///   run with the fewest divergences is kept and the retries are logged. A single attempt by
+///   default
+/// - `views`: optional canvases of more views of the same run: the data with the regression
+///   lines of the posterior draws (as [`plot_tmax`]) and the residuals at the posterior mean
+///   (as [`plot_residuals`]). The views without a canvas are skipped - all of them by default
+/// - `title_prefix`: optional prefix of the captions of the posterior plots - e.g. the name of
+///   the station. The captions are the parameter names by default
+/// - `downweight_outliers`: optional - a robust alternative: the chains are first run on the
+///   data, the observations more than 3 sigma away from the posterior mean fit are down-weighted
+///   (their residual is brought back to 3 sigma) and the chains are run again - up to 3 times
+///   while new outliers show up. The down-weighted observations are logged. Disabled by default
+/// - `standardize`: optional - sample the slope per standard deviation of DATE: `TMAX = alpha +
+///   beta_std * (DATE - mean(DATE)) / sd(DATE)`. This conditions the problem and makes the prior
+///   of the slope independent of the units of DATE. The summary of the standardized
+///   coefficients is logged with the back-transformation `beta = beta_std / sd(DATE)` - alpha
+///   (at the mean DATE) and sigma are unchanged. Everything else (plots, posterior,
+///   `initial_position`, exports) is on the original scale
+/// - `sigma_prior`: optional family of the prior of sigma - flat by default. A half-normal or a
+///   half-Cauchy prior is of scale `sigma_prior_scale` if given, and otherwise the standard
+///   deviation of the residuals of the least squares fit: a data-appropriate scale of the noise,
+///   whatever the units of TMAX. Ignored with `fixed_sigma`
+/// - `sigma_prior_scale`: optional scale of the prior of sigma - see `sigma_prior`
+/// - `centering`: optional reference DATE of alpha during the sampling - the mean DATE by
+///   default. The median DATE is robust to clustered or duplicated dates, `Reference` uses
+///   `centering_date` and `None` does not center. A reference within the bulk of the dates
+///   decorrelates alpha and beta. Whatever the centering, alpha is then moved to the mean DATE
+///   (`alpha + beta * (mean - reference)`) for the outputs - as the other functions expect
+/// - `centering_date`: optional reference DATE of the `Reference` centering - required by it:
+///   fails with [`MyError::InvalidRunOptions`] without
+/// - `x_column`: optional index of the predictor column - 0 (DATE) by default. With
+///   `y_column`, this selects the columns of a CSV whose names are not the expected ones. The
+///   first line is still read as a header. Fails with [`MyError::ColumnOutOfRange`] if the rows
+///   have no such column
+/// - `y_column`: optional index of the response column - 1 (TMAX) by default
+/// - `predictor_columns`: optional indices of more predictor columns - all the columns but
+///   `x_column`, `y_column`, COUNT and ID by default, none with `[]`. Each has its own slope, with the default normal prior: `slope_laplace_scale`, `standardize` and
+///   the prior of beta are those of the slope of `x_column` only. Fails with
+///   [`MyError::DuplicateColumn`] if a column is selected twice and with
+///   [`MyError::ZeroVariancePredictor`] if a predictor is constant
+/// - `sampler_state`: optional `SamplerState` of a previous run (see [`export_sampler_state`]) -
+///   each chain starts from the last draw of a previous chain with its adapted step size - a
+///   short warmup is then enough (nuts-rs needs at least one draw). Overrides
+///   `initial_position` and `jitter_scale`. Fails with [`MyError::InvalidSamplerState`] if it
+///   does not have one position and one step size per chain. See [`export_sampler_state`] for the caveats
+/// - `fixed_beta`: optional hypothesized slope (per unit of DATE) - when set, beta is not
+///   sampled: only alpha and sigma are. Comparing the fit (e.g. [`log_marginal_likelihood`] or
+///   the residuals) with the one of the free slope tells whether the data are consistent with
+///   the hypothesis. `initial_position` and `sampler_state` then have no beta either. Fails
+///   with [`MyError::InvalidFixedBeta`] unless it is a finite number
+/// - `parameter_names`: optional display names of the sampled parameters, in order - e.g.
+///   `["intercept", "warming_rate", "noise"]`. They replace alpha, beta and sigma in the plot
+///   captions, the logs, the posterior CSV, the `FitResult` and the exports of the run. Fails
+///   with [`MyError::WrongNameCount`] - before sampling - unless there is one name per sampled
+///   parameter
+/// - `drop_stuck_chains`: whether to leave out the chains that did not move from their initial
+///   position (false by default). Such chains are always reported: their constant draws make the
+///   ESS and the R-hat look fine while they are not from the posterior. When left out, fails with
+///   [`MyError::AllChainsStuck`] if no chain moved
+/// - `subsample_n`: optional number of observations to fit - drawn at random with `seed` - for
+///   a fast approximate fit of a large dataset. All of them by default (or with 0). This is an
+///   approximation: the posterior is the one of the subsample, its intervals are wider than with
+///   all the observations. The plots and the marginal likelihood are those of the subsample too
+/// - `alpha_prior_mean`, `alpha_prior_sd`: optional mean and standard deviation of the normal
+///   prior of alpha - 0 and 10 by default (a precision of 0.01). The prior is on alpha at the
+///   centering DATE (see `centering`), on the scale of the response (its log with
+///   `log_response`): data far from 0 - e.g. temperatures in Kelvin - need a prior around
+///   their level
+/// - `beta_prior_mean`, `beta_prior_sd`: optional mean and standard deviation of the normal
+///   prior of beta - per unit of DATE, whatever `standardize` - 0 and 10 by default. Given,
+///   they replace the Laplace prior of `slope_laplace_scale`. Ignored with `fixed_beta`
+/// - `target_accept`: optional acceptance rate targeted by the adaptation of the step size - in
+///   (0, 1), 0.8 by default. A higher target means smaller steps: fewer divergences on a stiff
+///   posterior, but longer trajectories. The starting point of the retries of `max_attempts`
+/// - `maxdepth`: optional maximum depth of the trajectories - at most `2^maxdepth` steps per
+///   draw, in 1..=20, 10 by default. A poorly-scaled posterior may need deeper trees
+/// - `max_energy_error`: optional energy error above which a draw is a divergence - positive,
+///   1000 by default. For these three arguments, 0 keeps the default and a value out of range
+///   fails with [`MyError::InvalidSamplerArgs`] before sampling
+/// - `r_hat_id`: optional id of a textarea to write the split R-hat and the effective sample
+///   size of each parameter in - as "parameter,R_HAT,ESS,converged" rows. Above an R-hat of
+///   1.01, the chains have not converged: a warning is also returned. Run longer (or check the
+///   model) before trusting the posterior. The ESS is the number of independent draws the draws
+///   of all the chains are worth
+///
+/// Everything random - the jitter of the initial positions, the chains and the
+/// posterior draws written in the textarea - is drawn from `seed` (or `seeds`):
+/// the same inputs give the same outputs.
+///
+/// The scales of the priors (`slope_laplace_scale`, `sigma_prior_scale`,
+/// `alpha_prior_sd` and `beta_prior_sd`) and `fixed_sigma` must be positive:
+/// other values fail with [`MyError::InvalidPrior`] and
+/// [`MyError::InvalidFixedSigma`] before sampling.
+///
+/// Returns a `FitResult` object (see its TypeScript definition) with the names,
+/// the posterior means, the central 95% intervals and the diagnostics of the
+/// sampled parameters - on the original scale - and the warnings of the run.
+/// The posterior means (`means`) can be passed back as `initial_position` to
+/// warm restart a subsequent run (e.g. after tweaking the priors). Starting
+/// from a previous posterior mean biases the warmup slightly but converges much
+/// faster - this is fine for interactive exploration.
+///
+/// Fails with [`MyError::NonNumericCell`] or [`MyError::ColumnCountMismatch`]
+/// if the input data is not a CSV of numbers - e.g. pasted data with a typo.
+#[wasm_bindgen]
+#[allow(clippy::too_many_arguments)]
+pub fn run_with(
+    canvas_id: &str,
+    posteriod_id: Option<String>,
+    seed: u64,
+    input_data: String,
+    chain_count: u64,
+    tuning: u64,
+    samples: u64,
+    options: Option<JsValue>,
+) -> Result<JsValue, MyError> {
+    run_regression(
+        canvas_id,
+        posteriod_id,
+        seed,
+        input_data,
+        chain_count,
+        tuning,
+        samples,
+        parse_options(options)?,
+    )
+}
+
+/// Deserializes the optional `options` object of a run - the defaults if
+/// there is none.
+///
+/// Fails with [`MyError::InvalidRunOptions`] if it is not such an object.
+fn parse_options<T: DeserializeOwned + Default>(options: Option<JsValue>) -> Result<T, MyError> {
+    options
+        .map(|options| {
+            serde_wasm_bindgen::from_value::<T>(options)
+                .map_err(|e| MyError::InvalidRunOptions(e.to_string()))
+        })
+        .transpose()
+        .map(Option::unwrap_or_default)
+}
+
+/// The optional arguments of [`run_with`], [`run_json`] and
+/// [`start_streaming_run`] - see the documentation of `run_with`.
+///
+/// A missing field keeps the default.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RunOptions {
+    fixed_sigma: Option<f64>,
+    histogram_mode: Option<HistogramMode>,
+    initial_position: Option<Vec<f64>>,
+    jitter_scale: Option<f64>,
+    slope_laplace_scale: Option<f64>,
+    layout: Option<PlotLayout>,
+    seeds: Option<Vec<u64>>,
+    max_millis: Option<f64>,
+    max_memory_mb: Option<f64>,
+    log_response: Option<bool>,
+    max_attempts: Option<u32>,
+    standardize: Option<bool>,
+    views: Option<PlotViews>,
+    downweight_outliers: Option<bool>,
+    title_prefix: Option<String>,
+    sigma_prior: Option<ScalePrior>,
+    sigma_prior_scale: Option<f64>,
+    centering: Option<Centering>,
+    centering_date: Option<f64>,
+    x_column: Option<usize>,
+    y_column: Option<usize>,
+    predictor_columns: Option<Vec<usize>>,
+    sampler_state: Option<SamplerState>,
+    fixed_beta: Option<f64>,
+    parameter_names: Option<Vec<String>>,
+    drop_stuck_chains: Option<bool>,
+    subsample_n: Option<usize>,
+    alpha_prior_mean: Option<f64>,
+    alpha_prior_sd: Option<f64>,
+    beta_prior_mean: Option<f64>,
+    beta_prior_sd: Option<f64>,
+    target_accept: Option<f64>,
+    maxdepth: Option<u64>,
+    max_energy_error: Option<f64>,
+    r_hat_id: Option<String>,
+}
+
+/// A regression run of [`run_with`] set up from its options: the model, the
+/// settings of the chains and what the outputs need once they are drawn.
+struct RegressionRun {
+    model: Regression,
+    settings: chain::RunSettings,
+    /// The selected columns: the predictor, the response and the others
+    observed: Vec<Vec<f64>>,
+    parameters: Vec<String>,
+    /// Standard deviation of the predictor if standardized - 1 otherwise
+    x_scale: f64,
+    /// Reference of the predictor during the sampling
+    center: f64,
+    /// Mean of the predictor minus `center`
+    shift: f64,
+    standardize: bool,
+    log_response: bool,
+    fixed_beta: Option<f64>,
+    fixed_sigma: Option<f64>,
+    max_attempts: Option<u32>,
+    downweight_outliers: bool,
+    drop_stuck_chains: bool,
+    parameter_names: Option<Vec<String>>,
+    histogram_mode: Option<HistogramMode>,
+    layout: Option<PlotLayout>,
+    title_prefix: Option<String>,
+    views: Option<PlotViews>,
+    r_hat_id: Option<String>,
+}
+
+// indices of the intercept and the slope in the position of the regression
+const ALPHA_IDX: usize = 0;
+const BETA_IDX: usize = 1;
+
+/// Run the regression with the given options - see [`run_with`].
+#[allow(clippy::too_many_arguments)]
+fn run_regression(
+    canvas_id: &str,
+    posteriod_id: Option<String>,
+    seed: u64,
+    input_data: String,
+    chain_count: u64,
+    tuning: u64,
+    samples: u64,
+    options: RunOptions,
+) -> Result<JsValue, MyError> {
+    set_panic_hook();
+    log("Running");
+
+    let mut run = setup_regression(seed, input_data, chain_count, tuning, samples, options)?;
+
+    if run.downweight_outliers {
+        // standardized residuals beyond this are outliers
+        const OUTLIER_THRESHOLD: f64 = 3.;
+        // down-weighting moves the fit - which may reveal other outliers
+        const MAX_PASSES: usize = 3;
+        for _ in 0..MAX_PASSES {
+            let chains = chain::Chains::run(run.model.clone(), &run.settings)?;
+            let posterior_mean = (0..chains.parameters.len())
+                .map(|i| stats::mean(&chains.traces(i).concat()))
+                .collect::<Vec<_>>();
+
+            let (reweighted, outliers) = run
+                .model
+                .downweight_outliers(&posterior_mean, OUTLIER_THRESHOLD);
+            run.model = reweighted;
+            if outliers.is_empty() {
+                break;
+            }
+            for (i, z) in outliers {
+                log(format!(
+                    "down-weighted the observation at {} = {}: standardized residual {:.2}",
+                    run.parameters[0], run.observed[i][0], z
+                )
+                .as_str());
+            }
+        }
+    }
+
+    let chains = match run.max_attempts {
+        Some(max_attempts) if max_attempts > 1 => {
+            // retry while more than 1% of the draws diverge
+            const MAX_DIVERGENCE_RATE: f64 = 0.01;
+            let (chains, note) = chain::Chains::run_with_retries(
+                run.model.clone(),
+                &run.settings,
+                max_attempts,
+                MAX_DIVERGENCE_RATE,
+            )?;
+            log(note.as_str());
+            chains
+        }
+        _ => chain::Chains::run(run.model.clone(), &run.settings)?,
+    };
+
+    finish_regression(run, chains, canvas_id, posteriod_id)
+}
+
+/// Set up a run of the regression with the given options - see [`run_with`].
+///
+/// Fails - before sampling - if the input or an option is invalid.
+fn setup_regression(
+    seed: u64,
+    input_data: String,
+    chain_count: u64,
+    tuning: u64,
+    samples: u64,
+    options: RunOptions,
+) -> Result<RegressionRun, MyError> {
+    let RunOptions {
+        fixed_sigma,
+        histogram_mode,
+        initial_position,
+        jitter_scale,
+        slope_laplace_scale,
+        layout,
+        seeds,
+        max_millis,
+        max_memory_mb,
+        log_response,
+        max_attempts,
+        standardize,
+        views,
+        downweight_outliers,
+        title_prefix,
+        sigma_prior,
+        sigma_prior_scale,
+        centering,
+        centering_date,
+        x_column,
+        y_column,
+        predictor_columns,
+        sampler_state,
+        fixed_beta,
+        parameter_names,
+        drop_stuck_chains,
+        subsample_n,
+        alpha_prior_mean,
+        alpha_prior_sd,
+        beta_prior_mean,
+        beta_prior_sd,
+        target_accept,
+        maxdepth,
+        max_energy_error,
+        r_hat_id,
+    } = options;
+
+    let (observed, parameters) = parse_csv(input_data)?;
+    let (x_column, y_column) = (x_column.unwrap_or(0), y_column.unwrap_or(1));
+    let predictor_columns = predictor_columns
+        .unwrap_or_else(|| default_predictor_columns(&parameters, x_column, y_column));
+    let (observed, parameters) =
+        select_columns(observed, parameters, x_column, y_column, &predictor_columns)?;
+    let observed = match subsample_n {
+        Some(n) => {
+            let total = observed.len();
+            let observed = subsample(observed, n, seed);
+            log(format!("fitting {} of the {} observations", observed.len(), total).as_str());
+            observed
+        }
+        None => observed,
+    };
+    if observed.is_empty() {
+        return Err(MyError::NoData);
+    }
+    let standardize = standardize.unwrap_or(false);
+
+    let dates = observed.iter().map(|row| row[0]).collect::<Vec<_>>();
+    let center = centering
+        .unwrap_or_default()
+        .center(&dates, centering_date)?;
+    // the outputs have alpha at the mean DATE: alpha + beta * shift
+    let shift = stats::mean(&dates) - center;
+
+    check_prior_scale("slope_laplace_scale", slope_laplace_scale)?;
+    check_prior_scale("sigma_prior_scale", sigma_prior_scale)?;
+    check_prior_scale("alpha_prior_sd", alpha_prior_sd)?;
+    check_prior_scale("beta_prior_sd", beta_prior_sd)?;
+    // moved right after the response
+    let predictors = (2..2 + predictor_columns.len()).collect::<Vec<_>>();
+    let (mut model, x_scale) = regression_model(
+        &observed,
+        &parameters,
+        &predictors,
+        fixed_sigma,
+        slope_laplace_scale,
+        log_response.unwrap_or(false),
+        standardize,
+        Some(center),
+    )?;
+    if let Some(beta) = fixed_beta {
+        // the model is on the standardized scale of the predictor
+        model = model.with_fixed_beta(beta * x_scale)?;
+    }
+    if let Some(sigma_prior) = sigma_prior {
+        model = model.with_sigma_prior(sigma_prior, sigma_prior_scale);
+    }
+    if alpha_prior_mean.is_some() || alpha_prior_sd.is_some() {
+        model = model.with_alpha_prior(PriorSpec::Normal {
+            mean: alpha_prior_mean.unwrap_or(0.),
+            sd: alpha_prior_sd.unwrap_or(Regression::DEFAULT_PRIOR_SD),
+        });
+    }
+    if beta_prior_mean.is_some() || beta_prior_sd.is_some() {
+        // the model is on the standardized scale of the predictor
+        model = model.with_beta_prior(PriorSpec::Normal {
+            mean: beta_prior_mean.unwrap_or(0.) * x_scale,
+            sd: beta_prior_sd.unwrap_or(Regression::DEFAULT_PRIOR_SD) * x_scale,
+        });
+    }
+    // from the original scale to the one of the sampling - the positions of
+    // the wrong length are left as they are: the run rejects them
+    let dim = model.dim();
+    let to_sampling_scale = |position: &mut Vec<f64>| match fixed_beta {
+        _ if position.len() != dim => {}
+        Some(beta) => position[ALPHA_IDX] -= beta * shift,
+        None => {
+            position[ALPHA_IDX] -= position[BETA_IDX] * shift;
+            position[BETA_IDX] *= x_scale;
+        }
+    };
+
+    if let Some(initial_position) = &initial_position {
+        if initial_position.len() != dim {
+            return Err(MyError::WrongPositionLength {
+                expected: dim,
+                actual: initial_position.len(),
+            });
+        }
+    }
+    // the initial position is on the original scale
+    let initial_position = initial_position.map(|mut position| {
+        to_sampling_scale(&mut position);
+        position
+    });
+    log(format!(
+        "initial_position = {:?}",
+        initial_position
+            .clone()
+            .unwrap_or_else(|| model.suggested_initial())
+    )
+    .as_str());
+
+    if let Some(seeds) = &seeds {
+        if seeds.len() as u64 != chain_count {
+            return Err(MyError::WrongSeedCount {
+                expected: chain_count,
+                actual: seeds.len(),
+            });
+        }
+    }
+    if let Some(names) = &parameter_names {
+        if names.len() != model.dim() {
+            return Err(MyError::WrongNameCount {
+                expected: model.dim(),
+                actual: names.len(),
+            });
+        }
+    }
+
+    // exported on the original scale - as the initial position
+    let sampler_state = sampler_state.map(|mut state| {
+        state.positions.iter_mut().for_each(to_sampling_scale);
+        state
+    });
+
+    let settings = chain::RunSettings {
+        seed,
+        seeds,
+        chain_count,
+        tuning,
+        samples,
+        initial_position,
+        jitter_scale: jitter_scale.unwrap_or(0.),
+        max_millis,
+        max_trace_bytes: max_memory_mb.map(|mb| (mb * 1024. * 1024.) as usize),
+        sampler_state,
+        // 0 is the default
+        target_accept: target_accept.filter(|t| *t != 0.),
+        maxdepth: maxdepth.filter(|d| *d != 0),
+        max_energy_error: max_energy_error.filter(|e| *e != 0.),
+    };
+
+    Ok(RegressionRun {
+        model,
+        settings,
+        observed,
+        parameters,
+        x_scale,
+        center,
+        shift,
+        standardize,
+        log_response: log_response.unwrap_or(false),
+        fixed_beta,
+        fixed_sigma,
+        max_attempts,
+        downweight_outliers: downweight_outliers.unwrap_or(false),
+        drop_stuck_chains: drop_stuck_chains.unwrap_or(false),
+        parameter_names,
+        histogram_mode,
+        layout,
+        title_prefix,
+        views,
+        r_hat_id,
+    })
+}
+
+/// Report a run of the regression once its chains are drawn - see
+/// [`run_with`]: the warnings and the summary, the plots in the canvas
+/// `canvas_id`, the posterior draws in the textarea `posteriod_id` if any. The
+/// chains then become the last run - for the export functions.
+///
+/// Returns the `FitResult` of the run.
+fn finish_regression(
+    run: RegressionRun,
+    mut chains: chain::Chains,
+    canvas_id: &str,
+    posteriod_id: Option<String>,
+) -> Result<JsValue, MyError> {
+    let RegressionRun {
+        model,
+        settings,
+        observed,
+        parameters,
+        x_scale,
+        center,
+        shift,
+        standardize,
+        log_response,
+        fixed_beta,
+        fixed_sigma,
+        drop_stuck_chains,
+        parameter_names,
+        histogram_mode,
+        layout,
+        title_prefix,
+        views,
+        r_hat_id,
+        ..
+    } = run;
+    let seed = settings.seed;
+
+    // returned with the result - and logged
+    let mut warnings = vec![];
+    let mut warn = |warning: String| {
+        log(format!("warning: {}", warning).as_str());
+        warnings.push(warning);
+    };
+
+    let stuck = chains.stuck_chains();
+    if !stuck.is_empty() {
+        if drop_stuck_chains {
+            chains.drop_stuck_chains()?;
+        } else {
+            warn(format!(
+                "chains {:?} did not move from their initial position - the estimates are unreliable",
+                stuck
+            ));
+        }
+    }
+    for failure in chains.failures() {
+        warn(format!("{} - the other chains are used", failure));
+    }
+    log(format!("collected {} samples per chain", chains.samples()).as_str());
+    log(format!("step sizes = {:?}", chains.step_sizes()).as_str());
+    for (i, adaptation) in chains.adaptation(1).iter().enumerate() {
+        if let Some(warmup) = adaptation.warmup.first() {
+            log(format!(
+                "chain {}: acceptance {:.2} during warmup, {:.2} during sampling",
+                i, warmup.accept, adaptation.sampling_accept
+            )
+            .as_str());
+        }
+    }
+
+    // chain means spread by more than this fraction of the within-chain sd
+    // are suspicious
+    const OVERDISPERSION_THRESHOLD: f64 = 0.5;
+    // more repeated draws than this hint at a poor acceptance
+    const REPEAT_THRESHOLD: f64 = 0.1;
+    if let Some(names) = parameter_names {
+        chains.set_display_names(names)?;
+    }
+    for (i, parameter) in chains.display_names().iter().enumerate() {
+        log(format!("{} chain means = {:?}", parameter, chains.chain_means(i)).as_str());
+        if chains.overdispersed(i, OVERDISPERSION_THRESHOLD) {
+            warn(format!(
+                "the chains disagree on {} - they may not have converged",
+                parameter
+            ));
+        }
+        let repeat_fraction = chains.repeat_fraction(i);
+        if repeat_fraction > REPEAT_THRESHOLD {
+            warn(format!(
+                "{:.0}% of the draws of {} repeat the previous one - the acceptance is poor",
+                100. * repeat_fraction,
+                parameter
+            ));
+        }
+    }
+
+    let mut posterior_mean = (0..chains.parameters.len())
+        .map(|i| stats::mean(&chains.traces(i).concat()))
+        .collect::<Vec<_>>();
+    log(format!(
+        "log-likelihood at the posterior mean = {}",
+        model.log_likelihood(&posterior_mean)
+    )
+    .as_str());
+
+    if standardize {
+        log(format!(
+            "summary (standardized - beta = beta_std / sd({0}), sd({0}) = {1}, alpha at {0} = {2}):\n{3}",
+            parameters[0],
+            x_scale,
+            center,
+            chains.summary()
+        )
+        .as_str());
+        // back to the original scale for the rest of the outputs
+        if fixed_beta.is_none() {
+            chains.rescale(BETA_IDX, x_scale.recip());
+            posterior_mean[BETA_IDX] /= x_scale;
+        }
+    }
+    if shift != 0. {
+        // alpha at the mean DATE - as the other functions expect
+        match fixed_beta {
+            Some(beta) => {
+                chains.offset(ALPHA_IDX, beta * shift);
+                posterior_mean[ALPHA_IDX] += beta * shift;
+            }
+            None => {
+                chains.shift(ALPHA_IDX, BETA_IDX, shift);
+                posterior_mean[ALPHA_IDX] += posterior_mean[BETA_IDX] * shift;
+            }
+        }
+    }
+    // the fixed coefficients are reported as constants
+    if let Some(beta) = fixed_beta {
+        posterior_mean.insert(BETA_IDX, beta);
+    }
+
+    log(format!("summary:\n{}", chains.summary()).as_str());
+
+    // run length for a reliable summary - driven by the worst parameter
+    const TARGET_ESS: f64 = 400.;
+    let min_ess = (0..chains.parameters.len())
+        .map(|i| chains.effective_sample_size(i))
+        .fold(f64::INFINITY, f64::min);
+    if min_ess < TARGET_ESS {
+        warn(format!(
+            "min ESS = {:.0} - for ESS ≥ {}, run ~{} samples",
+            min_ess,
+            TARGET_ESS,
+            stats::samples_for_ess(min_ess, chains.samples() as u64, TARGET_ESS)
+        ));
+    }
+    for (i, name) in chains.display_names().iter().enumerate() {
+        let r_hat = chains.r_hat(i);
+        if r_hat > chain::R_HAT_THRESHOLD {
+            warn(format!(
+                "R-hat of {} = {:.3} > {} - the chains have not converged: run longer",
+                name,
+                r_hat,
+                chain::R_HAT_THRESHOLD
+            ));
+        }
+    }
+    // the most autocorrelated parameter sets the thinning
+    let thin = (0..chains.parameters.len())
+        .map(|i| chains.suggested_thin(i))
+        .max()
+        .unwrap_or(1);
+    if thin > 1 {
+        log(format!("thin by ~{} to get roughly independent draws", thin).as_str());
+    }
+
+    log("Plotting");
+
+    // the posterior is still useful without the plots
+    if let Err(e) = chains.plot(
+        canvas_id,
+        &chains,
+        chains.samples() as u64,
+        histogram_mode.unwrap_or_default(),
+        layout.unwrap_or_default(),
+        title_prefix.as_deref().unwrap_or_default(),
+    ) {
+        log(format!("{}", e).as_str());
+    }
+
+    log("Sampling posterior");
+    // same seed, same textarea
+    let (posterior_str, regression) =
+        posterior_csv(&chains, POSTERIOR_SAMPLES, seed, fixed_beta, fixed_sigma);
+    // the run is kept even if a textarea is missing
+    if let Some(posteriod_id) = posteriod_id {
+        if let Err(e) = write_text(&posteriod_id, &posterior_str) {
+            log(format!("{}", e).as_str());
+        }
+    }
+    if let Some(r_hat_id) = r_hat_id {
+        if let Err(e) = write_text(&r_hat_id, &chains.convergence_csv()) {
+            log(format!("{}", e).as_str());
+        }
+    }
+
+    if let Some(views) = views {
+        plot_views(
+            &views,
+            &observed,
+            &parameters,
+            regression,
+            &posterior_mean,
+            log_response,
+            model.predictor_count(),
+        );
+    }
+
+    // the marginal likelihood of TMAX - comparable across the options: the
+    // kept draws are on the original scale of the slope, a log response gets
+    // the Jacobian of the log
+    let log_jacobian = if log_response {
+        -observed.iter().map(|row| row[1].ln()).sum::<f64>()
+    } else {
+        0.
+    };
+    let log_joint = move |position: &[f64]| {
+        let mut position = position.to_vec();
+        let log_jacobian = match fixed_beta {
+            Some(beta) => {
+                position[ALPHA_IDX] -= beta * shift;
+                log_jacobian
+            }
+            None => {
+                position[ALPHA_IDX] -= position[BETA_IDX] * shift;
+                position[BETA_IDX] *= x_scale;
+                log_jacobian + x_scale.ln()
+            }
+        };
+        Some(model.log_joint(&position)? + log_jacobian)
+    };
+
+    let fit = chains.fit_result(warnings);
+
+    LAST_RUN.with(|last_run| *last_run.borrow_mut() = Some(chains));
+    LAST_LOG_JOINT.with(|last_log_joint| *last_log_joint.borrow_mut() = Some(Box::new(log_joint)));
+
+    log("Done");
+
+    Ok(serde_wasm_bindgen::to_value(&fit).expect("A FitResult is serializable"))
+}
+
+/// Sets the text of the element with the given id - e.g. a textarea.
+///
+/// Fails with [`MyError::ElementUnavailable`] if the page has no such element.
+fn write_text(element_id: &str, text: &str) -> Result<(), MyError> {
+    let element = web_sys::window()
+        .and_then(|window| window.document())
+        .and_then(|document| document.get_element_by_id(element_id))
+        .ok_or_else(|| MyError::ElementUnavailable(element_id.to_string()))?;
+    element.set_text_content(Some(text));
+    Ok(())
+}
+
+/// Number of posterior draws written in the posterior textarea of
+/// [`run_with`] - and returned by [`run_json`].
+const POSTERIOR_SAMPLES: usize = 10;
+
+/// Pick `n` posterior draws of a run of the regression - with `seed` (see
+/// [`chain::Chains::sample_posterior`]).
+///
+/// Returns the CSV of the posterior textarea - "alpha,beta,sigma" or their
+/// display names, a fixed beta or sigma reported as a constant column - and its
+/// rows. With several predictors, beta is the slope of the 1st one: `beta_1`.
+fn posterior_csv(
+    chains: &chain::Chains,
+    n: usize,
+    seed: u64,
+    fixed_beta: Option<f64>,
+    fixed_sigma: Option<f64>,
+) -> (String, Vec<Vec<f64>>) {
+    let posterior = chains.sample_posterior(n, seed);
+
+    // the slope of the 1st predictor with several predictors
+    let beta = if chains.parameters.iter().any(|p| p == "beta_1") {
+        "beta_1"
+    } else {
+        "beta"
+    };
+    let columns = ["alpha", beta, "sigma"];
+    let header = columns
+        .iter()
+        .map(
+            |column| match chains.parameters.iter().position(|p| p == column) {
+                Some(i) => chains.display_names()[i].as_str(),
+                None => column,
+            },
+        )
+        .collect::<Vec<_>>();
+    let mut posterior_str = format!("{}\n", header.join(","));
+
+    let mut rows = vec![];
+    for i in 0..n {
+        let value = |parameter: &str| posterior.get(parameter).unwrap()[i];
+        let row = vec![
+            value("alpha"),
+            fixed_beta.unwrap_or_else(|| value(beta)),
+            fixed_sigma.unwrap_or_else(|| value("sigma")),
+        ];
+
+        let line = row.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+        posterior_str.push_str(line.join(",").as_str());
+        posterior_str.push('\n');
+        rows.push(row);
+    }
+
+    (posterior_str, rows)
+}
+
+/// Draw the additional views of a run of the regression - the failures are
+/// logged.
+///
+/// `regression` holds the `[alpha, beta, sigma]` of posterior draws. The plots
+/// take DATE and the value column: with more than one predictor, the residuals
+/// along DATE alone are not those of the fit - their view is skipped.
+fn plot_views(
+    views: &PlotViews,
+    observed: &[Vec<f64>],
+    parameters: &[String],
+    regression: Vec<Vec<f64>>,
+    posterior_mean: &[f64],
+    log_response: bool,
+    predictor_count: usize,
+) {
+    if let Some(canvas_id) = &views.data {
+        let plot = plot::TMaxPlot::new(
+            observed.to_vec(),
+            Some(regression),
+            parameters.to_vec(),
+            PlotStyle::default(),
+        )
+        .map(|plot| {
+            if log_response {
+                plot.with_log_response()
+            } else {
+                plot
+            }
+        });
+        if let Err(e) = plot.and_then(|plot| plot.plot(canvas_id)) {
+            log(format!("{}", e).as_str());
+        }
+    }
+
+    if let Some(canvas_id) = &views.residuals {
+        if predictor_count > 1 {
+            log(format!(
+                "skipped the residuals view - the fit has {} predictors",
+                predictor_count
+            )
+            .as_str());
+            return;
+        }
+        // the residuals are on the scale of the regression
+        let observed = observed
+            .iter()
+            .map(|row| {
+                if log_response {
+                    vec![row[0], row[1].ln()]
+                } else {
+                    row.to_vec()
+                }
+            })
+            .collect();
+        let plot = plot::ResidualPlot::new(observed, posterior_mean.to_vec(), parameters.to_vec());
+        if let Err(e) = plot.and_then(|plot| plot.plot(canvas_id)) {
+            log(format!("{}", e).as_str());
+        }
+    }
+}
+
+/// Run the regression on a generic time series
+///
+/// The input data is a two-column CSV of dates and values - see
+/// [`prepare_generic`] for its format and `date_format`. This is [`run_with`]
+/// on the prepared data with its defaults: the chains are run with `seed`,
+/// `chain_count`, `tuning` and `samples`, the traces and posteriors are plotted
+/// in the canvas with the given id: `canvas_id` and posterior draws are written
+/// in the textarea `posteriod_id` if any.
+///
+/// Returns a `FitResult` object - as [`run_with`].
+#[wasm_bindgen]
+#[allow(clippy::too_many_arguments)]
+pub fn run_generic(
+    canvas_id: &str,
+    posteriod_id: Option<String>,
+    seed: u64,
+    input_data: String,
+    date_format: Option<DateFormat>,
+    chain_count: u64,
+    tuning: u64,
+    samples: u64,
+) -> Result<JsValue, MyError> {
+    let input_data = prepare_generic(input_data, date_format)?;
+
+    run_regression(
+        canvas_id,
+        posteriod_id,
+        seed,
+        input_data,
+        chain_count,
+        tuning,
+        samples,
+        RunOptions::default(),
+    )
+}
+
+/// Run the regression and return its draws as an object
+///
+/// This is [`run_with`] - with the same `options` (a `RunOptions` object), and
+/// the traces and posteriors are plotted in the canvas with the given id:
+/// `canvas_id` - but instead of a CSV in a textarea, it returns a `RunDraws`
+/// object (see its TypeScript definition): the names of the parameters, the
+/// post-warmup draws of each chain, the posterior draws `run_with` would write
+/// in its textarea and the split R-hat of each parameter - ready for a
+/// charting library.
+#[wasm_bindgen]
+pub fn run_json(
+    canvas_id: &str,
+    seed: u64,
+    input_data: String,
+    chain_count: u64,
+    tuning: u64,
+    samples: u64,
+    options: Option<JsValue>,
+) -> Result<JsValue, MyError> {
+    run_regression(
+        canvas_id,
+        None,
+        seed,
+        input_data,
+        chain_count,
+        tuning,
+        samples,
+        parse_options(options)?,
+    )?;
+
+    LAST_RUN.with(|last_run| {
+        let last_run = last_run.borrow();
+        let chains = last_run.as_ref().ok_or(MyError::NoRun)?;
+
+        let draws = chains.run_draws(POSTERIOR_SAMPLES, seed);
+        // the R-hat as an object - not a Map
+        let serializer = serde_wasm_bindgen::Serializer::json_compatible();
+        Ok(draws
+            .serialize(&serializer)
+            .expect("RunDraws are serializable"))
+    })
+}
+
+/// Build the changepoint regression of a CSV of DATE and TMAX - see
+/// [`run_changepoint`].
+///
+/// Fails with [`MyError::ColumnOutOfRange`] if the CSV has fewer than two
+/// columns, with [`MyError::NoData`] if it has no observation and with
+/// [`MyError::ZeroVariancePredictor`] if DATE is constant.
+fn changepoint_model(
+    observed: Vec<Vec<f64>>,
+    parameters: Vec<String>,
+) -> Result<Changepoint, MyError> {
+    let (observed, parameters) = select_columns(observed, parameters, 0, 1, &[])?;
+    if observed.is_empty() {
+        return Err(MyError::NoData);
+    }
+
+    let x = observed.iter().map(|x| x[0]).collect::<Vec<_>>();
+    let y = observed.iter().map(|x| x[1]).collect::<Vec<_>>();
+
+    check_predictor_variance(&parameters[0], &x)?;
+
+    Ok(Changepoint::new(x, y))
+}
+
+/// Run the changepoint regression
+///
+/// The input data is a CSV with the following header:
+/// "DATE,TMAX"
+///
+/// The trend is fitted with two segments joined at a changepoint `tau`:
+/// `TMAX = alpha + beta1 * (DATE - tau)` before `tau` and
+/// `TMAX = alpha + beta2 * (DATE - tau)` after. Comparing `beta1` and `beta2`
+/// tells whether the warming accelerated.
+///
+/// The output is a plot of the traces and posteriors of `alpha, beta1, beta2,
+/// tau, sigma` in the canvas with the given id: `canvas_id` - skipped (with a
+/// log) if the canvas is unavailable.
+///
+/// The chains are run as in [`run_with`] with `seed`, `chain_count`, `tuning`
+/// and `samples`.
+///
+/// Returns the posterior mean of `[alpha, beta1, beta2, tau, sigma]` - `tau` is
+/// a DATE. Fails with [`MyError::NoData`] if the input has no observation and
+/// with [`MyError::ColumnOutOfRange`] if it has fewer than two columns.
+#[wasm_bindgen]
+pub fn run_changepoint(
+    canvas_id: &str,
+    seed: u64,
+    input_data: String,
+    chain_count: u64,
+    tuning: u64,
+    samples: u64,
+) -> Result<Vec<f64>, MyError> {
+    set_panic_hook();
+    log("Running the changepoint regression");
+
+    let (observed, parameters) = parse_csv(input_data)?;
+    let model = changepoint_model(observed, parameters)?;
+
+    let settings = chain::RunSettings {
+        seed,
+        chain_count,
+        tuning,
+        samples,
+        ..Default::default()
+    };
+    let chains = chain::Chains::run(model.clone(), &settings)?;
+
+    let posterior_mean = (0..chains.parameters.len())
+        .map(|i| stats::mean(&chains.traces(i).concat()))
+        .collect::<Vec<_>>();
+
+    log(format!("summary:\n{}", chains.summary()).as_str());
+
+    if let Err(e) = chains.plot(
+        canvas_id,
+        &chains,
+        chains.samples() as u64,
+        HistogramMode::default(),
+        PlotLayout::default(),
+        "",
+    ) {
+        log(format!("{}", e).as_str());
+    }
+
+    LAST_RUN.with(|last_run| *last_run.borrow_mut() = Some(chains));
+    LAST_LOG_JOINT.with(|last_log_joint| {
+        *last_log_joint.borrow_mut() = Some(Box::new(move |position| model.log_joint(position)))
+    });
+
+    Ok(posterior_mean)
+}
+
+/// Build the regression with a seasonal noise level of a CSV of DATE, DOY and
+/// a value - see [`run_heteroscedastic`].
+///
+/// Fails with [`MyError::MissingColumn`] if a column is missing, with
+/// [`MyError::NoData`] if there is no observation and with
+/// [`MyError::ZeroVariancePredictor`] if DATE or the season is constant.
+fn heteroscedastic_model(
+    observed: &[Vec<f64>],
+    parameters: &[String],
+) -> Result<Heteroscedastic, MyError> {
+    let date_idx = column_index(parameters, "DATE")?;
+    let doy_idx = column_index(parameters, "DOY")?;
+    let value_idx = value_column_index(parameters)?;
+    if observed.is_empty() {
+        return Err(MyError::NoData);
+    }
+
+    let x = observed.iter().map(|x| x[date_idx]).collect::<Vec<_>>();
+    let s = observed
+        .iter()
+        .map(|x| season_feature(x[doy_idx]))
+        .collect::<Vec<_>>();
+    let y = observed.iter().map(|x| x[value_idx]).collect::<Vec<_>>();
+
+    check_predictor_variance("DATE", &x)?;
+    check_predictor_variance("DOY", &s)?;
+
+    Ok(Heteroscedastic::new(x, s, y))
+}
+
+/// Run the regression with a seasonal noise level
+///
+/// The input data is a CSV with the following header:
+/// "DATE,DOY,TMAX" - as returned by [`prepare`] with the day of the year, for
+/// any of its elements.
+///
+/// The trend is fitted as in [`run_with`] but the noise level of each
+/// observation depends on its season:
+/// `TMAX = alpha + beta * (DATE - DATE_m) + sigma * eps` with
+/// `log(sigma) = gamma0 + gamma1 * cos(2π (DOY - 1) / 365.25)` - where `DATE_m`
+/// is the mean DATE. A positive `gamma1` means that winters are more variable
+/// than summers.
+///
+/// The output is a plot of the traces and posteriors of `alpha, beta, gamma0,
+/// gamma1` in the canvas with the given id: `canvas_id` - skipped (with a log)
+/// if the canvas is unavailable.
+///
+/// The chains are run as in [`run_with`] with `seed`, `chain_count`, `tuning`
+/// and `samples`.
+///
+/// Returns the posterior mean of `[alpha, beta, gamma0, gamma1]`. Fails with
+/// [`MyError::NoData`] if the input has no observation.
+#[wasm_bindgen]
+pub fn run_heteroscedastic(
+    canvas_id: &str,
+    seed: u64,
+    input_data: String,
+    chain_count: u64,
+    tuning: u64,
+    samples: u64,
+) -> Result<Vec<f64>, MyError> {
+    set_panic_hook();
+    log("Running the heteroscedastic regression");
+
+    let (observed, parameters) = parse_csv(input_data)?;
+    let model = heteroscedastic_model(&observed, &parameters)?;
+
+    let settings = chain::RunSettings {
+        seed,
+        chain_count,
+        tuning,
+        samples,
+        ..Default::default()
+    };
+    let chains = chain::Chains::run(model.clone(), &settings)?;
+
+    let posterior_mean = (0..chains.parameters.len())
+        .map(|i| stats::mean(&chains.traces(i).concat()))
+        .collect::<Vec<_>>();
+
+    log(format!("summary:\n{}", chains.summary()).as_str());
+
+    if let Err(e) = chains.plot(
+        canvas_id,
+        &chains,
+        chains.samples() as u64,
+        HistogramMode::default(),
+        PlotLayout::default(),
+        "",
+    ) {
+        log(format!("{}", e).as_str());
+    }
+
+    LAST_RUN.with(|last_run| *last_run.borrow_mut() = Some(chains));
+    LAST_LOG_JOINT.with(|last_log_joint| {
+        *last_log_joint.borrow_mut() = Some(Box::new(move |position| model.log_joint(position)))
+    });
+
+    Ok(posterior_mean)
+}
+
+/// The optional arguments of [`run_logistic_with`] - see its documentation.
+///
+/// A missing field keeps the default.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct LogisticOptions {
+    x_column: Option<usize>,
+    y_column: Option<usize>,
+    alpha_prior_mean: Option<f64>,
+    alpha_prior_sd: Option<f64>,
+    beta_prior_mean: Option<f64>,
+    beta_prior_sd: Option<f64>,
+    title_prefix: Option<String>,
+}
+
+/// Run the logistic regression
+///
+/// The input data is a CSV of a predictor and a binary outcome - 0 or 1 - e.g.
+/// "DATE,HOT" (whether TMAX was above a threshold). The predictor is the
+/// `x_column` (0 by default) and the outcome the `y_column` (1 by default) -
+/// as in [`run_with`].
+///
+/// The probability of the outcome is
+/// `P(y = 1) = sigmoid(alpha + beta * (x - x_m))` - where `x_m` is the mean of
+/// the predictor: alpha is the log-odds at `x_m`, beta the change of the
+/// log-odds per unit of the predictor.
+///
+/// `options` is an optional `LogisticOptions` object (see its TypeScript
+/// definition) - as the `RunOptions` of [`run_with`], each of its fields is
+/// optional and a missing one keeps the default. Fails with
+/// [`MyError::InvalidRunOptions`] if it is not such an object. Its fields are:
+/// - `x_column`, `y_column`: optional indices of the predictor and the outcome columns
+/// - `alpha_prior_mean`, `alpha_prior_sd`: optional mean and standard deviation of the normal
+///   prior of alpha - 0 and 10 by default
+/// - `beta_prior_mean`, `beta_prior_sd`: optional mean and standard deviation of the normal
+///   prior of beta - per unit of the predictor - 0 and 10 by default
+/// - `title_prefix`: optional prefix of the captions of the plots
+///
+/// The output is a plot of the traces and posteriors of `alpha, beta` in the
+/// canvas with the given id: `canvas_id` - skipped (with a log) if the canvas
+/// is unavailable. The chains are run with `seed`, `chain_count`, `tuning` and
+/// `samples`.
+///
+/// Returns a `FitResult` object - as [`run_with`]. Fails with
+/// [`MyError::NoData`] if the input has no observation, with
+/// [`MyError::NonBinaryOutcome`] if an outcome is neither 0 nor 1, with
+/// [`MyError::ZeroVariancePredictor`] if the predictor is constant or with
+/// [`MyError::InvalidPrior`] if a standard deviation of the priors is not
+/// positive.
+#[wasm_bindgen]
+pub fn run_logistic_with(
+    canvas_id: &str,
+    seed: u64,
+    input_data: String,
+    chain_count: u64,
+    tuning: u64,
+    samples: u64,
+    options: Option<JsValue>,
+) -> Result<JsValue, MyError> {
+    let LogisticOptions {
+        x_column,
+        y_column,
+        alpha_prior_mean,
+        alpha_prior_sd,
+        beta_prior_mean,
+        beta_prior_sd,
+        title_prefix,
+    } = parse_options(options)?;
+    set_panic_hook();
+    log("Running the logistic regression");
+
+    // returned with the result - and logged
+    let mut warnings = vec![];
+    let mut warn = |warning: String| {
+        log(format!("warning: {}", warning).as_str());
+        warnings.push(warning);
+    };
+
+    let (observed, parameters) = parse_csv(input_data)?;
+    let (observed, parameters) = select_columns(
+        observed,
+        parameters,
+        x_column.unwrap_or(0),
+        y_column.unwrap_or(1),
+        &[],
+    )?;
+    if observed.is_empty() {
+        return Err(MyError::NoData);
+    }
+
+    let x = observed.iter().map(|row| row[0]).collect::<Vec<_>>();
+    let y = observed.iter().map(|row| row[1]).collect::<Vec<_>>();
+    check_predictor_variance(&parameters[0], &x)?;
+    if let Some(y) = y.iter().find(|y| **y != 0. && **y != 1.) {
+        return Err(MyError::NonBinaryOutcome(*y));
+    }
+
+    check_prior_scale("alpha_prior_sd", alpha_prior_sd)?;
+    check_prior_scale("beta_prior_sd", beta_prior_sd)?;
+    let mut model = Logistic::new(x, y);
+    if alpha_prior_mean.is_some() || alpha_prior_sd.is_some() {
+        model = model.with_alpha_prior(PriorSpec::Normal {
+            mean: alpha_prior_mean.unwrap_or(0.),
+            sd: alpha_prior_sd.unwrap_or(Logistic::DEFAULT_PRIOR_SD),
+        });
+    }
+    if beta_prior_mean.is_some() || beta_prior_sd.is_some() {
+        model = model.with_beta_prior(PriorSpec::Normal {
+            mean: beta_prior_mean.unwrap_or(0.),
+            sd: beta_prior_sd.unwrap_or(Logistic::DEFAULT_PRIOR_SD),
+        });
+    }
+
+    let settings = chain::RunSettings {
+        seed,
+        chain_count,
+        tuning,
+        samples,
+        ..Default::default()
+    };
+    let chains = chain::Chains::run(model.clone(), &settings)?;
+
+    log(format!("summary:\n{}", chains.summary()).as_str());
+
+    for (i, name) in chains.display_names().iter().enumerate() {
+        let r_hat = chains.r_hat(i);
+        if r_hat > chain::R_HAT_THRESHOLD {
+            warn(format!(
+                "R-hat of {} = {:.3} > {} - the chains have not converged: run longer",
+                name,
+                r_hat,
+                chain::R_HAT_THRESHOLD
+            ));
+        }
+    }
+
+    if let Err(e) = chains.plot(
+        canvas_id,
+        &chains,
+        chains.samples() as u64,
+        HistogramMode::default(),
+        PlotLayout::default(),
+        title_prefix.as_deref().unwrap_or_default(),
+    ) {
+        log(format!("{}", e).as_str());
+    }
+
+    let fit = chains.fit_result(warnings);
+
+    LAST_RUN.with(|last_run| *last_run.borrow_mut() = Some(chains));
+    LAST_LOG_JOINT.with(|last_log_joint| {
+        *last_log_joint.borrow_mut() = Some(Box::new(move |position| model.log_joint(position)))
+    });
+
+    Ok(serde_wasm_bindgen::to_value(&fit).expect("A FitResult is serializable"))
+}
+
+/// Build the multivariate normal model of a CSV - see [`fit_mvnormal`].
+///
+/// Fails with [`MyError::NoData`] if the CSV has no column or no observation:
+/// with the flat prior of `mu`, the posterior would be improper.
+fn mvnormal_model(
+    observed: Vec<Vec<f64>>,
+    parameters: &[String],
+) -> Result<MultivariateNormalModel, MyError> {
+    if observed.is_empty() || parameters.is_empty() {
+        return Err(MyError::NoData);
+    }
+
+    Ok(MultivariateNormalModel::new(observed, parameters))
+}
+
+/// Estimate the mean of a multivariate normal
+///
+/// The input data is a CSV with one column per dimension, e.g. "TMAX,TMIN".
+/// The observations are modeled as draws of a normal distribution of unknown
+/// mean `mu` and unit covariance - with a flat prior on `mu`.
+///
+/// The output is a plot of the traces and posteriors of `mu_<column>` in the
+/// canvas with the given id: `canvas_id` - skipped (with a log) if the canvas
+/// is unavailable.
+///
+/// The chains are run as in [`run_with`] with `seed`, `chain_count`, `tuning`
+/// and `samples`.
+///
+/// Returns the posterior mean of `mu`. Fails with [`MyError::NoData`] if the
+/// input has no observation.
+#[wasm_bindgen]
+pub fn fit_mvnormal(
+    canvas_id: &str,
+    seed: u64,
+    input_data: String,
+    chain_count: u64,
+    tuning: u64,
+    samples: u64,
+) -> Result<Vec<f64>, MyError> {
+    set_panic_hook();
+    log("Running the multivariate normal");
+
+    let (observed, parameters) = parse_csv(input_data)?;
+    let model = mvnormal_model(observed, &parameters)?;
+
+    let settings = chain::RunSettings {
+        seed,
+        chain_count,
+        tuning,
+        samples,
+        ..Default::default()
+    };
+    let chains = chain::Chains::run(model.clone(), &settings)?;
+
+    let posterior_mean = (0..chains.parameters.len())
+        .map(|i| stats::mean(&chains.traces(i).concat()))
+        .collect::<Vec<_>>();
+
+    log(format!("summary:\n{}", chains.summary()).as_str());
+
+    if let Err(e) = chains.plot(
+        canvas_id,
+        &chains,
+        chains.samples() as u64,
+        HistogramMode::default(),
+        PlotLayout::default(),
+        "",
+    ) {
+        log(format!("{}", e).as_str());
+    }
+
+    LAST_RUN.with(|last_run| *last_run.borrow_mut() = Some(chains));
+    LAST_LOG_JOINT.with(|last_log_joint| {
+        *last_log_joint.borrow_mut() = Some(Box::new(move |position| model.log_joint(position)))
+    });
+
+    Ok(posterior_mean)
+}
+
+/// Start a regression run drawn a few draws at a time
+///
+/// The input data, the chains and the `options` are as in [`run_with`] -
+/// except `max_attempts`, `downweight_outliers` and `max_millis`: they need
+/// the whole run at once and fail with [`MyError::InvalidRunOptions`] - the
+/// time budget is the one of each step instead. Nothing is drawn yet: call
+/// [`step_streaming_run`] until it returns the result, e.g. from `setTimeout`
+/// or `requestAnimationFrame` - the browser handles the events and repaints the
+/// partial plots between two calls. Once the run is done, posterior draws are
+/// written in the textarea `posteriod_id` if any - as by [`run_with`].
+///
+/// Starting a run drops the previous streaming run, if any.
+#[wasm_bindgen]
+pub fn start_streaming_run(
+    seed: u64,
+    input_data: String,
+    chain_count: u64,
+    tuning: u64,
+    samples: u64,
+    options: Option<JsValue>,
+    posteriod_id: Option<String>,
+) -> Result<(), MyError> {
+    set_panic_hook();
+    log("Starting a streaming run");
+
+    let regression = setup_regression(
+        seed,
+        input_data,
+        chain_count,
+        tuning,
+        samples,
+        parse_options(options)?,
+    )?;
+    if regression.max_attempts.is_some_and(|attempts| attempts > 1) {
+        return Err(MyError::InvalidRunOptions(
+            "max_attempts runs the chains again - use run_with".to_string(),
+        ));
+    }
+    if regression.downweight_outliers {
+        return Err(MyError::InvalidRunOptions(
+            "downweight_outliers runs the chains again - use run_with".to_string(),
+        ));
+    }
+    if regression.settings.max_millis.is_some() {
+        return Err(MyError::InvalidRunOptions(
+            "max_millis is the time budget of each step of a streaming run".to_string(),
+        ));
+    }
+
+    let run = chain::StreamingRun::start(regression.model.clone(), &regression.settings)?;
+
+    STREAMING_RUN.with(|streaming_run| {
+        *streaming_run.borrow_mut() = Some(StreamingSession {
+            run,
+            regression,
+            posteriod_id,
+        })
+    });
+
+    Ok(())
+}
+
+/// Make `draws` more draws in each chain of the run started by
+/// [`start_streaming_run`]
+///
+/// With `max_millis`, the step also ends once this time budget is exhausted -
+/// with fewer draws: a budget of a few tens of milliseconds keeps the page
+/// responsive whatever the number of chains and draws.
+///
+/// The traces and posteriors of the draws so far are plotted in the canvas
+/// with the given id: `canvas_id` - skipped (with a log) if the canvas is
+/// unavailable. Nothing is plotted during the warmup.
+///
+/// Returns `undefined` until all the draws are done, and then the `FitResult`
+/// of the run - as [`run_with`] does: the chains then become the last run - for
+/// the export functions - and the streaming run is over. Fails with
+/// [`MyError::NoRun`] if no streaming run is in progress, and with
+/// [`MyError::SamplingFailed`] - ending the streaming run - once all the chains
+/// failed.
+#[wasm_bindgen]
+pub fn step_streaming_run(
+    canvas_id: &str,
+    draws: u64,
+    max_millis: Option<f64>,
+) -> Result<JsValue, MyError> {
+    let done = STREAMING_RUN.with(|streaming_run| {
+        let mut streaming_run = streaming_run.borrow_mut();
+        let session = streaming_run.as_mut().ok_or(MyError::NoRun)?;
+
+        let done = match session.run.advance(draws, max_millis) {
+            Ok(done) => done,
+            Err(e) => {
+                // all the chains failed: nothing left to stream
+                *streaming_run = None;
+                return Err(e);
+            }
+        };
+
+        let chains = session.run.chains();
+        let regression = &session.regression;
+        // the plots need a spread of draws
+        if chains.samples() >= 2 {
+            if let Err(e) = chains.plot(
+                canvas_id,
+                chains,
+                chains.samples() as u64,
+                regression.histogram_mode.unwrap_or_default(),
+                regression.layout.unwrap_or_default(),
+                regression.title_prefix.as_deref().unwrap_or_default(),
+            ) {
+                log(format!("{}", e).as_str());
+            }
+        }
+
+        Ok(done)
+    })?;
+
+    if !done {
+        return Ok(JsValue::UNDEFINED);
+    }
+
+    let StreamingSession {
+        run,
+        regression,
+        posteriod_id,
+    } = STREAMING_RUN
+        .with(|streaming_run| streaming_run.borrow_mut().take())
+        .expect("The streaming run is in progress");
+
+    finish_regression(regression, run.into_chains(), canvas_id, posteriod_id)
+}
+
+/// Time the stages of a run of the regression
+///
+/// The input data is a CSV as in [`run_with`] - unless both `dates` and `tmax`
+/// are given (e.g. `Float64Array`s in JS): the CSV is then ignored and its
+/// parsing avoided. The chains are run as in [`run_with`] with `seed`,
+/// `chain_count`, `tuning` and `samples` - its defaults for the rest - and
+/// plotted in the canvas with the given id: `canvas_id` - skipped (with a log)
+/// if the canvas is unavailable.
+///
+/// Returns the time spent parsing the input, in the warmup, sampling and
+/// plotting - to tell which stage dominates for a dataset size. The run is not
+/// kept for the export functions. Fails with [`MyError::LengthMismatch`] if
+/// `dates` and `tmax` have different lengths.
+#[wasm_bindgen]
+#[allow(clippy::too_many_arguments)]
+pub fn benchmark(
+    canvas_id: &str,
+    seed: u64,
+    input_data: String,
+    chain_count: u64,
+    tuning: u64,
+    samples: u64,
+    dates: Option<Vec<f64>>,
+    tmax: Option<Vec<f64>>,
+) -> Result<Timings, MyError> {
+    set_panic_hook();
+
+    let (model, parse_millis) = timed(|| {
+        let (observed, parameters) = match (dates, tmax) {
+            (Some(dates), Some(tmax)) => {
+                if dates.len() != tmax.len() {
+                    return Err(MyError::LengthMismatch {
+                        dates: dates.len(),
+                        values: tmax.len(),
+                    });
+                }
+                let observed = dates
+                    .iter()
+                    .zip(tmax.iter())
+                    .map(|(date, tmax)| vec![*date, *tmax])
+                    .collect::<Vec<_>>();
+                (observed, vec![String::from("DATE"), String::from("TMAX")])
+            }
+            _ => parse_csv(input_data)?,
+        };
+        regression_model(&observed, &parameters, &[], None, None, false, false, None)
+    });
+    let (model, _) = model?;
+
+    let settings = chain::RunSettings {
+        seed,
+        chain_count,
+        tuning,
+        samples,
+        ..Default::default()
+    };
+    // the same draws as a run in one go - with the warmup timed apart
+    let mut run = chain::StreamingRun::start(model, &settings)?;
+    let (warmup, warmup_millis) = timed(|| run.advance(tuning, None));
+    warmup?;
+    let (sampling, sampling_millis) = timed(|| run.advance(samples, None));
+    sampling?;
+    let chains = run.into_chains();
+
+    let (plotted, plotting_millis) = timed(|| {
+        chains.plot(
+            canvas_id,
+            &chains,
+            chains.samples() as u64,
+            HistogramMode::default(),
+            PlotLayout::default(),
+            "",
+        )
+    });
+    if let Err(e) = plotted {
+        log(format!("{}", e).as_str());
+    }
+
+    let timings = Timings {
+        parse_millis,
+        warmup_millis,
+        sampling_millis,
+        plotting_millis,
+    };
+    log(format!("{:?}", timings).as_str());
+
+    Ok(timings)
+}
+
+/// Estimate the log marginal likelihood of the model of the last run
+///
+/// The estimate is by bridge sampling on the draws of the last run - `seed`
+/// (0 by default) seeds the draws of the proposal. The difference of the
+/// estimates of two models fitted on the same data is the log of their Bayes
+/// factor.
+///
+/// Fails with [`MyError::ImproperPrior`] if a prior of the model is flat - the
+/// default prior of sigma in [`run_with`] (see its `sigma_prior`) and the
+/// priors of tau and sigma in [`run_changepoint`] - and with
+/// [`MyError::NoRun`] if no run happened yet.
+#[wasm_bindgen]
+pub fn log_marginal_likelihood(seed: Option<u64>) -> Result<f64, MyError> {
+    LAST_RUN.with(|last_run| {
+        LAST_LOG_JOINT.with(|last_log_joint| {
+            let chains = last_run.borrow();
+            let log_joint = last_log_joint.borrow();
+            match (chains.as_ref(), log_joint.as_ref()) {
+                (Some(chains), Some(log_joint)) => {
+                    chains.log_marginal_likelihood(log_joint, seed.unwrap_or(0))
+                }
+                _ => Err(MyError::NoRun),
+            }
+        })
+    })
+}
+
+/// Returns the number of draws per chain of the last run
+///
+/// This is `samples` unless the run had a time budget (`max_millis`).
+#[wasm_bindgen]
+pub fn samples_collected() -> Result<usize, MyError> {
+    LAST_RUN.with(|last_run| {
+        last_run
+            .borrow()
+            .as_ref()
+            .map(|chains| chains.samples())
+            .ok_or(MyError::NoRun)
+    })
+}
+
+/// Returns the draws of the last run as a flat array - a `Float64Array` in JS
+///
+/// The layout is (chain, draw, parameter): the value of parameter `p` in draw `d`
+/// of chain `c` is at index `(c * n_draws + d) * dim + p`. The dimensions are
+/// given by [`posterior_draws_shape`] and the parameter names by the header of
+/// the posterior CSV.
+#[wasm_bindgen]
+pub fn posterior_draws_flat() -> Result<Vec<f64>, MyError> {
+    LAST_RUN.with(|last_run| {
+        last_run
+            .borrow()
+            .as_ref()
+            .map(|chains| chains.draws_flat())
+            .ok_or(MyError::NoRun)
+    })
+}
+
+/// Returns the dimensions `[n_chains, n_draws, dim]` of [`posterior_draws_flat`]
+#[wasm_bindgen]
+pub fn posterior_draws_shape() -> Result<Vec<u32>, MyError> {
+    LAST_RUN.with(|last_run| {
+        last_run
+            .borrow()
+            .as_ref()
+            .map(|chains| {
+                let (n_chains, n_draws, dim) = chains.shape();
+                vec![n_chains as u32, n_draws as u32, dim as u32]
+            })
+            .ok_or(MyError::NoRun)
+    })
+}
+
+/// Returns the names of the parameters of the last run, in order - an array
+/// of strings in JS
+///
+/// This is the order of the columns of the exports (e.g. alpha, beta, sigma
+/// for the regression) - to label plots and tables without hard-coding the
+/// model. These are the display names of the run if any (see the
+/// `parameter_names` of [`run_with`]).
+#[wasm_bindgen]
+pub fn parameter_names() -> Result<Vec<String>, MyError> {
+    LAST_RUN.with(|last_run| {
+        last_run
+            .borrow()
+            .as_ref()
+            .map(|chains| chains.display_names().to_vec())
+            .ok_or(MyError::NoRun)
+    })
+}
+
+/// Returns the trace of a parameter in one chain of the last run - a
+/// `Float64Array` in JS
+///
+/// `chain_id` is in `0..chain_count` and `parameter_idx` follows the order of
+/// the header of the posterior CSV (e.g. 0 for alpha) - to inspect a single
+/// misbehaving chain.
+#[wasm_bindgen]
+pub fn chain_trace(chain_id: usize, parameter_idx: usize) -> Result<Vec<f64>, MyError> {
+    LAST_RUN.with(|last_run| {
+        last_run
+            .borrow()
+            .as_ref()
+            .ok_or(MyError::NoRun)?
+            .chain_trace(chain_id, parameter_idx)
+    })
+}
+
+/// Export the draws of the last run as ArviZ-compatible JSON
+///
+/// The output is `{"posterior": {"alpha": [[...], ...], ...}}` with dimensions
+/// (chain, draw) for each parameter - it can be loaded in Python with
+/// `az.from_dict(**json.loads(s))`.
+#[wasm_bindgen]
+pub fn export_arviz_json() -> Result<String, MyError> {
+    LAST_RUN.with(|last_run| {
+        last_run
+            .borrow()
+            .as_ref()
+            .map(|chains| chains.to_arviz_json())
+            .ok_or(MyError::NoRun)
+    })
+}
+
+/// Export the diagnostics of the last run as a CSV
+///
+/// The output has one row per parameter in the layout of Stan's summary:
+/// "parameter,mean,se_mean,sd,2.5%,25%,50%,75%,97.5%,n_eff,Rhat" - the Monte
+/// Carlo standard error of the mean, the effective sample size and the split
+/// R-hat along the posterior mean, sd and quantiles.
+#[wasm_bindgen]
+pub fn export_diagnostics_csv() -> Result<String, MyError> {
+    LAST_RUN.with(|last_run| {
+        last_run
+            .borrow()
+            .as_ref()
+            .map(|chains| chains.diagnostics_csv())
+            .ok_or(MyError::NoRun)
+    })
+}
+
+/// Summarize the adaptation of the chains of the last run
+///
+/// Returns an `Adaptation` object (see its TypeScript definition) per chain:
+/// the mean acceptance rate and the final step size of `phases` (1 by default)
+/// consecutive windows of the warmup, the step size adapted during the warmup
+/// and the mean acceptance rate of the post-warmup draws. A sampling acceptance
+/// far from the target or a step size still moving in the last window of the
+/// warmup suggests a longer tuning.
+#[wasm_bindgen]
+pub fn adaptation_summary(phases: Option<u32>) -> Result<JsValue, MyError> {
+    let adaptation = LAST_RUN.with(|last_run| {
+        last_run
+            .borrow()
+            .as_ref()
+            .map(|chains| chains.adaptation(phases.unwrap_or(1) as usize))
+            .ok_or(MyError::NoRun)
+    })?;
+
+    Ok(serde_wasm_bindgen::to_value(&adaptation).expect("An Adaptation is serializable"))
+}
+
+/// Export where the chains of the last run stopped
+///
+/// Returns a `SamplerState` object (see its TypeScript definition): the last
+/// draw and the adapted step size of each chain. Passed to [`run_with`] as
+/// `sampler_state`, the next run starts from the adapted chains - a short
+/// warmup (nuts-rs needs at least one draw) is then enough.
+///
+/// Caveats:
+/// - nuts-rs cannot start from a given mass matrix: only the step sizes are
+///   reused, with the initial (identity) mass matrix of the sampler - the
+///   adapted step size may then be too large or too small. A short warmup
+///   adapts both again from a good starting point.
+/// - The adaptation is tied to the posterior it was made for: a run on other
+///   data, with another model or other settings (standardization, centering,
+///   priors...) should not reuse it.
+#[wasm_bindgen]
+pub fn export_sampler_state() -> Result<JsValue, MyError> {
+    let state = LAST_RUN.with(|last_run| {
+        last_run
+            .borrow()
+            .as_ref()
+            .map(|chains| chains.sampler_state())
+            .ok_or(MyError::NoRun)
+    })?;
+
+    Ok(serde_wasm_bindgen::to_value(&state).expect("A SamplerState is serializable"))
+}
+
+/// Export the traces and histograms of the last run as an SVG
+///
+/// The plot of [`run_with`] rendered to an SVG document of `width` x `height`
+/// pixels (1200 x 800 by default) instead of a canvas - to download it as a
+/// vector graphic. `histogram_mode`, `layout` and `title_prefix` are those of
+/// [`run_with`].
+#[wasm_bindgen]
+pub fn export_trace_plot_svg(
+    width: Option<u32>,
+    height: Option<u32>,
+    histogram_mode: Option<HistogramMode>,
+    layout: Option<PlotLayout>,
+    title_prefix: Option<String>,
+) -> Result<String, MyError> {
+    LAST_RUN.with(|last_run| {
+        let last_run = last_run.borrow();
+        let chains = last_run.as_ref().ok_or(MyError::NoRun)?;
+
+        plot::render_svg(svg_size(width, height), |root| {
+            chains.draw(
+                root,
+                chains,
+                chains.samples() as u64,
+                histogram_mode.unwrap_or_default(),
+                layout.unwrap_or_default(),
+                title_prefix.as_deref().unwrap_or_default(),
+            )
+        })
+    })
+}
+
+/// Returns the integrated autocorrelation time of a parameter of the last run
+///
+/// `parameter_idx` follows the order of the header of the posterior CSV. This
+/// is the number of draws worth one independent draw - across all chains:
+/// keeping one draw out of its ceiling (the thinning interval suggested by
+/// [`run_with`]) gives roughly independent draws, e.g. for a downstream
+/// simulation.
+///
+/// Fails with [`MyError::UnknownParameter`] if there is no such parameter.
+#[wasm_bindgen]
+pub fn integrated_autocorr_time(parameter_idx: usize) -> Result<f64, MyError> {
+    LAST_RUN.with(|last_run| {
+        let last_run = last_run.borrow();
+        let chains = last_run.as_ref().ok_or(MyError::NoRun)?;
+
+        if parameter_idx >= chains.parameters.len() {
+            return Err(MyError::UnknownParameter {
+                parameter_idx,
+                dim: chains.parameters.len(),
+            });
+        }
+
+        Ok(chains.integrated_autocorr_time(parameter_idx))
+    })
+}
+
+/// Export the positions where the divergences of the last run started
+///
+/// The output is a CSV with one column per parameter (e.g. "alpha,beta,sigma")
+/// and one row per divergence - to overlay on a pair plot.
+#[wasm_bindgen]
+pub fn export_divergence_locations() -> Result<String, MyError> {
+    LAST_RUN.with(|last_run| {
+        let last_run = last_run.borrow();
+        let chains = last_run.as_ref().ok_or(MyError::NoRun)?;
+
+        let mut output = chains.display_names().join(",");
+        output.push('\n');
+
+        for location in chains.divergence_locations() {
+            let row = location.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+            output.push_str(&row.join(","));
+            output.push('\n');
+        }
+
+        Ok(output)
+    })
+}
+
+#[wasm_bindgen]
+extern "C" {
+
+    #[wasm_bindgen(js_namespace = console)]
+    fn log(s: &str);
+}
+
+// Download the data from the given URL
+// #[wasm_bindgen]
+// pub async fn get_data(url: String) -> String {
+//     let data = download(url).await;
+//     data.unwrap().as_string().unwrap()
+// }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_constant_predictor() {
+        let x = vec![2020.5; 10];
+        assert!(matches!(
+            check_predictor_variance("DATE", &x),
+            Err(MyError::ZeroVariancePredictor(column)) if column == "DATE"
+        ));
+
+        let x = (0..10)
+            .map(|i| 2020. + i as f64 / 365.25)
+            .collect::<Vec<_>>();
+        assert!(check_predictor_variance("DATE", &x).is_ok());
+
+        assert!(matches!(
+            check_predictor_variance("DATE", &[]),
+            Err(MyError::NoData)
+        ));
+    }
+
+    #[test]
+    fn test_select_columns() {
+        let (observed, parameters) =
+            parse_csv("ID,TMAX,DATE,COUNT\n1,12,2000,3\n1,14,2001,5".into()).unwrap();
+
+        let (selected, names) =
+            select_columns(observed.clone(), parameters.clone(), 2, 1, &[]).unwrap();
+        assert_eq!(names, vec!["DATE", "TMAX", "ID", "COUNT"]);
+        assert_eq!(selected[1], vec![2001., 14., 1., 5.]);
+
+        // the ID is not a predictor unless selected
+        let (model, _) =
+            regression_model(&selected, &names, &[], None, None, false, false, None).unwrap();
+        assert_eq!(model.parameters(), vec!["alpha", "beta", "sigma"]);
+
+        // the predictors come right after the response
+        let (selected, names) =
+            select_columns(observed.clone(), parameters.clone(), 2, 1, &[3]).unwrap();
+        assert_eq!(names, vec!["DATE", "TMAX", "COUNT", "ID"]);
+        assert_eq!(selected[1], vec![2001., 14., 5., 1.]);
+
+        // the default layout is unchanged
+        assert_eq!(
+            select_columns(observed.clone(), parameters.clone(), 0, 1, &[]).unwrap(),
+            (observed.clone(), parameters.clone())
+        );
+
+        assert!(matches!(
+            select_columns(observed.clone(), parameters.clone(), 0, 4, &[]),
+            Err(MyError::ColumnOutOfRange { index: 4, width: 4 })
+        ));
+        assert!(matches!(
+            select_columns(observed.clone(), parameters.clone(), 2, 1, &[5]),
+            Err(MyError::ColumnOutOfRange { index: 5, width: 4 })
+        ));
+        assert!(matches!(
+            select_columns(observed, parameters, 2, 1, &[2]),
+            Err(MyError::DuplicateColumn(2))
+        ));
+    }
+
+    #[test]
+    fn test_default_predictor_columns() {
+        let parameters = ["DATE", "TMAX", "PRCP", "COUNT", "ID", "TMIN"]
+            .map(String::from)
+            .to_vec();
+        // all the columns beyond the response but COUNT and ID
+        assert_eq!(default_predictor_columns(&parameters, 0, 1), vec![2, 5]);
+        // with other columns selected, DATE is one more predictor
+        assert_eq!(default_predictor_columns(&parameters, 2, 5), vec![0, 1]);
+        assert!(default_predictor_columns(&parameters[..2], 0, 1).is_empty());
+    }
+
+    #[test]
+    fn test_log_response() {
+        let parameters = vec![String::from("DATE"), String::from("TMAX")];
+        let observed = vec![vec![2000., 1.], vec![2001., std::f64::consts::E]];
+
+        let (mut model, _) =
+            regression_model(&observed, &parameters, &[], None, None, true, false, None).unwrap();
+        let mut log_model = Regression::new(vec![-0.5, 0.5], vec![0., 1.]);
+
+        let mut grad = vec![0.; 3];
+        assert_eq!(
+            model.logp(&[0.5, 1., 1.], &mut grad).unwrap(),
+            log_model.logp(&[0.5, 1., 1.], &mut grad).unwrap()
+        );
+
+        let observed = vec![vec![2000., 1.], vec![2001., 0.]];
+        assert!(matches!(
+            regression_model(&observed, &parameters, &[], None, None, true, false, None),
+            Err(MyError::NonPositiveResponse(value)) if value == 0.
+        ));
+    }
+
+    #[test]
+    fn test_invalid_priors() {
+        assert!(check_prior_scale("alpha_prior_sd", None).is_ok());
+        assert!(check_prior_scale("alpha_prior_sd", Some(2.)).is_ok());
+        for sd in [0., -1., f64::NAN, f64::INFINITY] {
+            assert!(matches!(
+                check_prior_scale("beta_prior_sd", Some(sd)),
+                Err(MyError::InvalidPrior(e)) if e.starts_with("beta_prior_sd = ")
+            ));
+        }
+
+        let parameters = vec![String::from("DATE"), String::from("TMAX")];
+        let observed = vec![vec![2000., 1.], vec![2001., 2.]];
+        assert!(matches!(
+            regression_model(&observed, &parameters, &[], Some(0.), None, false, false, None),
+            Err(MyError::InvalidFixedSigma(sigma)) if sigma == 0.
+        ));
+    }
+
+    #[test]
+    fn test_regression_model_no_data() {
+        let (observed, parameters) = parse_csv("DATE,TMAX\n".to_string()).unwrap();
+        assert!(matches!(
+            regression_model(&observed, &parameters, &[], None, None, false, false, None),
+            Err(MyError::NoData)
+        ));
+    }
+
+    #[test]
+    fn test_changepoint_model_no_data() {
+        let (observed, parameters) = parse_csv("DATE,TMAX\n".to_string()).unwrap();
+        assert!(matches!(
+            changepoint_model(observed, parameters),
+            Err(MyError::NoData)
+        ));
+
+        let (observed, parameters) = parse_csv("DATE\n2000\n2001\n".to_string()).unwrap();
+        assert!(matches!(
+            changepoint_model(observed, parameters),
+            Err(MyError::ColumnOutOfRange { index: 1, width: 1 })
+        ));
+
+        let (observed, parameters) = parse_csv(String::new()).unwrap();
+        assert!(matches!(
+            changepoint_model(observed, parameters),
+            Err(MyError::ColumnOutOfRange { index: 0, width: 0 })
+        ));
+
+        let (observed, parameters) = parse_csv("DATE,TMAX\n2000,1\n2001,2\n".to_string()).unwrap();
+        assert!(changepoint_model(observed, parameters).is_ok());
+    }
+
+    #[test]
+    fn test_heteroscedastic_model_no_data() {
+        let (observed, parameters) = parse_csv("DATE,DOY,TMAX\n".to_string()).unwrap();
+        assert!(matches!(
+            heteroscedastic_model(&observed, &parameters),
+            Err(MyError::NoData)
+        ));
+
+        let (observed, parameters) =
+            parse_csv("DATE,DOY,TMAX\n2000.0,1,5\n2000.5,183,25\n".to_string()).unwrap();
+        assert!(heteroscedastic_model(&observed, &parameters).is_ok());
+    }
+
+    #[test]
+    fn test_mvnormal_model_no_data() {
+        let (observed, parameters) = parse_csv("TMAX,TMIN\n".to_string()).unwrap();
+        assert!(matches!(
+            mvnormal_model(observed, &parameters),
+            Err(MyError::NoData)
+        ));
+
+        let (observed, parameters) = parse_csv(String::new()).unwrap();
+        assert!(matches!(
+            mvnormal_model(observed, &parameters),
+            Err(MyError::NoData)
+        ));
+
+        let (observed, parameters) = parse_csv("TMAX,TMIN\n20,10\n".to_string()).unwrap();
+        assert!(mvnormal_model(observed, &parameters).is_ok());
+    }
+
+    #[test]
+    fn test_standardize() {
+        let parameters = vec![String::from("DATE"), String::from("TMAX")];
+        let observed = vec![vec![2000., 1.], vec![2002., 2.], vec![2004., 4.]];
+
+        let (mut model, x_scale) =
+            regression_model(&observed, &parameters, &[], None, None, false, true, None).unwrap();
+        assert_eq!(x_scale, 2.);
+        let mut std_model = Regression::new(vec![-1., 0., 1.], vec![1., 2., 4.]);
+
+        let mut grad = vec![0.; 3];
+        assert_eq!(
+            model.logp(&[2., 3., 1.], &mut grad).unwrap(),
+            std_model.logp(&[2., 3., 1.], &mut grad).unwrap()
+        );
+
+        let (_, x_scale) =
+            regression_model(&observed, &parameters, &[], None, None, false, false, None).unwrap();
+        assert_eq!(x_scale, 1.);
+
+        // centered on another date
+        let (mut model, _) = regression_model(
+            &observed,
+            &parameters,
+            &[],
+            None,
+            None,
+            false,
+            false,
+            Some(2000.),
+        )
+        .unwrap();
+        let mut centered = Regression::new(vec![0., 2., 4.], vec![1., 2., 4.]);
+        assert_eq!(
+            model.logp(&[2., 3., 1.], &mut grad).unwrap(),
+            centered.logp(&[2., 3., 1.], &mut grad).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_extra_predictors() {
+        let (observed, parameters) = parse_csv(String::from(
+            "DATE,TMAX,PRCP\n2000,1,0.5\n2001,2.5,0\n2002,2,1.5\n2003,4,0.2\n",
+        ))
+        .unwrap();
+        let (model, _) =
+            regression_model(&observed, &parameters, &[2], None, None, false, false, None).unwrap();
+        assert_eq!(
+            model.parameters(),
+            vec!["alpha", "beta_1", "beta_2", "sigma"]
+        );
+
+        // the other columns are ignored
+        let (model, _) =
+            regression_model(&observed, &parameters, &[], None, None, false, false, None).unwrap();
+        assert_eq!(model.parameters(), vec!["alpha", "beta", "sigma"]);
+
+        // the counts weight the observations: they are not a predictor
+        let (observed, parameters) = parse_csv(String::from(
+            "DATE,TMAX,COUNT\n2000,1,3\n2001,2.5,1\n2002,2,2\n",
+        ))
+        .unwrap();
+        let (model, _) =
+            regression_model(&observed, &parameters, &[], None, None, false, false, None).unwrap();
+        assert_eq!(model.parameters(), vec!["alpha", "beta", "sigma"]);
+    }
+
+    #[test]
+    fn test_regression_model_invalid_count() {
+        for (count, value) in [("0", 0.), ("-2", -2.), ("inf", f64::INFINITY)] {
+            let (observed, parameters) = parse_csv(format!(
+                "DATE,TMAX,COUNT\n2000,1,3\n2001,2.5,{}\n2002,2,2\n",
+                count
+            ))
+            .unwrap();
+            assert!(matches!(
+                regression_model(&observed, &parameters, &[], None, None, false, false, None),
+                Err(MyError::InvalidWeight { row: 1, value: v }) if v == value
+            ));
+        }
+        // NaN is not equal to itself
+        let (observed, parameters) =
+            parse_csv(String::from("DATE,TMAX,COUNT\n2000,1,3\n2001,2.5,NaN\n")).unwrap();
+        assert!(matches!(
+            regression_model(&observed, &parameters, &[], None, None, false, false, None),
+            Err(MyError::InvalidWeight { row: 1, value }) if value.is_nan()
+        ));
+    }
+
+    #[test]
+    fn test_posterior_csv_reproducible() {
+        let (observed, parameters) = parse_csv(String::from(
+            "DATE,TMAX\n2000,1\n2001,2.5\n2002,2\n2003,4\n2004,4.5\n",
+        ))
+        .unwrap();
+        let settings = chain::RunSettings {
+            seed: 7,
+            chain_count: 2,
+            tuning: 100,
+            samples: 50,
+            jitter_scale: 0.1,
+            ..Default::default()
+        };
+
+        // the whole pipeline of run_with: model, chains and posterior picks
+        let posterior = |seed| {
+            let (model, _) =
+                regression_model(&observed, &parameters, &[], None, None, false, false, None)
+                    .unwrap();
+            let chains = chain::Chains::run(model, &settings).unwrap();
+            posterior_csv(&chains, 10, seed, None, None)
+        };
+
+        let (csv, rows) = posterior(7);
+        assert_eq!(posterior(7), (csv.clone(), rows.clone()));
+        assert_ne!(posterior(8).0, csv);
+        assert!(csv.starts_with("alpha,beta,sigma\n"));
+        assert_eq!(csv.lines().count(), 11);
+        assert_eq!(rows.len(), 10);
+
+        // constant columns for the fixed parameters
+        let (model, _) = regression_model(
+            &observed,
+            &parameters,
+            &[],
+            Some(0.5),
+            None,
+            false,
+            false,
+            None,
+        )
+        .unwrap();
+        let chains = chain::Chains::run(model.with_fixed_beta(0.8).unwrap(), &settings).unwrap();
+        let (_, rows) = posterior_csv(&chains, 3, 1, Some(0.8), Some(0.5));
+        assert!(rows.iter().all(|row| row[1] == 0.8 && row[2] == 0.5));
+
+        // the display names head the sampled columns
+        let mut chains = chains;
+        chains
+            .set_display_names(vec![String::from("intercept")])
+            .unwrap();
+        let (csv, _) = posterior_csv(&chains, 1, 1, Some(0.8), Some(0.5));
+        assert!(csv.starts_with("intercept,beta,sigma\n"));
+    }
+
+    #[test]
+    fn test_day_of_year() {
+        assert_eq!(parse_date_components("20230101", 0).unwrap().1, 1);
+        assert_eq!(parse_date_components("20231231", 0).unwrap().1, 365);
+        assert_eq!(parse_date_components("20240229", 0).unwrap().1, 60);
+        assert_eq!(parse_date_components("20241231", 0).unwrap().1, 366);
+
+        assert!(parse_date_components("20230229", 0).is_err());
+
+        let (years, _, year) = parse_date_components("20240229", 0).unwrap();
+        assert_eq!(years, parse_date("20240229").unwrap());
+        assert_eq!(year, 2024);
+    }
+
+    #[test]
+    fn test_parse_date_rel() {
+        assert_eq!(parse_date_rel("20000101", 2000).unwrap(), 0.);
+        let years = parse_date_rel("20240702", 2000).unwrap();
+        assert!((years - 24.5).abs() < 0.01, "{}", years);
+        assert!(parse_date_rel("19900101", 2000).unwrap() < 0.);
+        // the same dates - shifted
+        let shift = parse_date("20000101").unwrap();
+        assert!((parse_date("20240702").unwrap() - shift - years).abs() < 1e-9);
+        assert!(matches!(
+            parse_date_rel("20240702", 1_000_000),
+            Err(MyError::InvalidEpochYear(1_000_000))
+        ));
+
+        // errors rather than panics
+        for date in [
+            "",
+            "2024",
+            "2024070",
+            "2024é702",
+            "2024070é",
+            "yyyymmdd",
+            "2024-7-2",
+            "20200101garbage",
+            "202001011",
+            "+2020101",
+        ] {
+            assert!(
+                matches!(parse_date(date), Err(MyError::InvalidDateFormat)),
+                "{}",
+                date
+            );
+        }
+
+        let raw = "ID,DATE,ELEMENT,DATA_VALUE,M_FLAG,Q_FLAG,S_FLAG,OBS_TIME
+FAKESTATION,20000101,TMAX,200,,,S
+FAKESTATION,2001,TMAX,210,,,S"
+            .to_string();
+        assert!(matches!(
+            prepare(raw.clone(), "TMAX", None, None, None, None, Some(2000)),
+            Err(MyError::InvalidDateFormat)
+        ));
+        let raw = raw.replace(",2001,", ",20010101,");
+        let output = prepare(raw, "TMAX", None, None, None, None, Some(2000)).unwrap();
+        let (observed, _) = parse_csv(output).unwrap();
+        assert_eq!(observed[0][0], 0.);
+        assert!((observed[1][0] - 1.).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_prepare_with_doy() {
+        let raw = "ID,DATE,ELEMENT,DATA_VALUE,M_FLAG,Q_FLAG,S_FLAG,OBS_TIME
+FAKESTATION,20240229,TMAX,200,,,S
+FAKESTATION,20240301,TMIN,100,,,S"
+            .to_string();
+
+        let output = prepare(raw.clone(), "TMAX", Some(true), None, None, None, None).unwrap();
+        let lines = output.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0], "DATE,DOY,TMAX");
+        assert!(lines[1].ends_with(",60,20"));
+
+        let output = prepare(raw, "TMAX", None, None, None, None, None).unwrap();
+        assert!(output.starts_with("DATE,TMAX\n"));
+    }
+
+    #[test]
+    fn test_prepare_element() {
+        let raw = "ID,DATE,ELEMENT,DATA_VALUE,M_FLAG,Q_FLAG,S_FLAG,OBS_TIME
+FAKESTATION,20000101,TMAX,200,,,S
+FAKESTATION,20000101,TMIN,100,,,S
+FAKESTATION,20000102,TMIN,50,,,S
+FAKESTATION,20000102,TAVG,120,,,S"
+            .to_string();
+
+        let (observed, parameters) =
+            parse_csv(prepare(raw.clone(), "TMIN", None, None, None, None, None).unwrap()).unwrap();
+        assert_eq!(parameters, vec!["DATE", "TMIN"]);
+        assert_eq!(
+            observed.iter().map(|row| row[1]).collect::<Vec<_>>(),
+            vec![10., 5.]
+        );
+
+        let (observed, parameters) = parse_csv(
+            prepare(
+                raw.clone(),
+                "TAVG",
+                None,
+                None,
+                None,
+                Some(vec![2000, 2000]),
+                None,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(parameters, vec!["DATE", "TAVG_ANOMALY"]);
+        assert_eq!(observed.len(), 1);
+
+        assert!(matches!(
+            prepare(raw, "PRCP", None, None, None, None, None),
+            Err(MyError::UnsupportedElement(element)) if element == "PRCP"
+        ));
+    }
+
+    #[test]
+    fn test_prepare_malformed() {
+        let header = "ID,DATE,ELEMENT,DATA_VALUE,M_FLAG,Q_FLAG,S_FLAG,OBS_TIME";
+        let prepare_rows = |rows: &str| {
+            prepare(
+                format!("{}\n{}", header, rows),
+                "TMAX",
+                None,
+                None,
+                None,
+                None,
+                None,
+            )
+        };
+
+        // a truncated row
+        assert!(matches!(
+            prepare_rows("FAKESTATION,20000101,TMAX,200,,,S\nFAKESTATION,20000102,TMAX"),
+            Err(MyError::MalformedRow { line: 3 })
+        ));
+        // a value that is not an integer
+        assert!(matches!(
+            prepare_rows("FAKESTATION,20000101,TMAX,2.5,,,S"),
+            Err(MyError::MalformedRow { line: 2 })
+        ));
+        // the values of the other elements are not parsed
+        assert!(prepare_rows(
+            "FAKESTATION,20000101,TMIN,n/a,,,S\nFAKESTATION,20000101,TMAX,200,,,S"
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn test_prepare_baseline() {
+        let raw = "ID,DATE,ELEMENT,DATA_VALUE,M_FLAG,Q_FLAG,S_FLAG,OBS_TIME
+FAKESTATION,19600101,TMAX,300,,,S
+FAKESTATION,19610101,TMAX,100,,,S
+FAKESTATION,19901231,TMAX,200,,,S
+FAKESTATION,20000101,TMAX,250,,,S"
+            .to_string();
+
+        let output = prepare(
+            raw.clone(),
+            "TMAX",
+            None,
+            None,
+            None,
+            Some(vec![1961, 1990]),
+            None,
+        )
+        .unwrap();
+        let (observed, parameters) = parse_csv(output).unwrap();
+        assert_eq!(parameters, vec!["DATE", "TMAX_ANOMALY"]);
+        assert_eq!(
+            observed.iter().map(|row| row[1]).collect::<Vec<_>>(),
+            vec![15., -5., 5., 10.]
+        );
+
+        assert!(matches!(
+            prepare(
+                raw.clone(),
+                "TMAX",
+                None,
+                None,
+                None,
+                Some(vec![2010, 2020]),
+                None
+            ),
+            Err(MyError::EmptyBaseline {
+                first_year: 2010,
+                last_year: 2020
+            })
+        ));
+
+        for baseline in [vec![1961], vec![1961, 1990, 2000], vec![1990, 1961]] {
+            assert!(matches!(
+                prepare(raw.clone(), "TMAX", None, None, None, Some(baseline.clone()), None),
+                Err(MyError::InvalidBaseline(invalid)) if invalid == baseline
+            ));
+        }
+    }
+
+    #[test]
+    fn test_prepare_units() {
+        let raw = "ID,DATE,ELEMENT,DATA_VALUE,M_FLAG,Q_FLAG,S_FLAG,OBS_TIME
+FAKESTATION,20240229,TMAX,200,,,S"
+            .to_string();
+
+        let value = |units: Option<&str>| {
+            let output = prepare(
+                raw.clone(),
+                "TMAX",
+                None,
+                units.map(String::from),
+                None,
+                None,
+                None,
+            )
+            .unwrap();
+            let line = output.lines().nth(1).unwrap().to_string();
+            line.split(',').nth(1).unwrap().parse::<f64>().unwrap()
+        };
+
+        assert_eq!(value(None), 20.);
+        assert_eq!(value(Some("C")), 20.);
+        assert_eq!(value(Some("F")), 68.);
+        assert!((value(Some("K")) - 293.15).abs() < 1e-9);
+
+        assert!(matches!(
+            prepare(raw, "TMAX", None, Some(String::from("R")), None, None, None),
+            Err(MyError::UnknownUnits(units)) if units == "R"
+        ));
+    }
+
+    #[test]
+    fn test_prepare_relative_dates() {
+        let raw = "ID,DATE,ELEMENT,DATA_VALUE,M_FLAG,Q_FLAG,S_FLAG,OBS_TIME
+FAKESTATION,20000701,TMAX,200,,,S
+FAKESTATION,20000101,TMAX,100,,,S
+FAKESTATION,20020101,TMAX,150,,,S"
+            .to_string();
+
+        let dates = |relative_dates| {
+            prepare(
+                raw.clone(),
+                "TMAX",
+                None,
+                None,
+                Some(relative_dates),
+                None,
+                None,
+            )
+            .unwrap()
+            .lines()
+            .skip(1)
+            .map(|line| line.split(',').next().unwrap().parse::<f64>().unwrap())
+            .collect::<Vec<_>>()
+        };
+
+        let absolute = dates(false);
+        let relative = dates(true);
+
+        // the earliest observation - not the first line - is at 0
+        assert_eq!(relative[1], 0.);
+        for (a, r) in absolute.iter().zip(relative.iter()) {
+            assert!((a - absolute[1] - r).abs() < 1e-9);
+        }
+        assert!((relative[2] - 2.).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_prepare_generic() {
+        let raw = "year,co2\n# monthly means\n1990.5, 354.2\n\n1991.0,355.1\n";
+        let prepared = prepare_generic(String::from(raw), None).unwrap();
+        assert_eq!(prepared, "DATE,VALUE\n1990.5,354.2\n1991,355.1\n");
+        let (observed, parameters) = parse_csv(prepared).unwrap();
+        assert_eq!(parameters, vec!["DATE", "VALUE"]);
+        assert_eq!(observed, vec![vec![1990.5, 354.2], vec![1991., 355.1]]);
+
+        // YYYYMMDD dates are in years - as the prepared NOAA data
+        let raw = String::from("DATE,VALUE\n20240101,1.5\n");
+        let prepared = prepare_generic(raw, Some(DateFormat::Yyyymmdd)).unwrap();
+        let (observed, _) = parse_csv(prepared).unwrap();
+        assert_eq!(observed[0][0], parse_date("20240101").unwrap());
+
+        let prepare = |raw: &str, format| prepare_generic(String::from(raw), Some(format));
+        assert!(matches!(
+            prepare("DATE,VALUE\n2024-01-01,1\n", DateFormat::Yyyymmdd),
+            Err(MyError::InvalidDateFormat)
+        ));
+        assert!(matches!(
+            prepare("DATE,VALUE\nsoon,1\n", DateFormat::YearFraction),
+            Err(MyError::InvalidDateFormat)
+        ));
+        assert!(matches!(
+            prepare("DATE,VALUE\n2000,1\n2001,NA\n", DateFormat::YearFraction),
+            Err(MyError::MalformedRow { line: 3 })
+        ));
+        assert!(matches!(
+            prepare("DATE,VALUE\n2000,1,2\n", DateFormat::YearFraction),
+            Err(MyError::MalformedRow { line: 2 })
+        ));
+        assert!(matches!(
+            prepare("DATE,VALUE\n", DateFormat::YearFraction),
+            Err(MyError::NoData)
+        ));
+    }
+
+    #[test]
+    fn test_data_bounds() {
+        assert_eq!(
+            data_bounds("DATE,TMAX\n2000.5,12\n2001.5,9\n".to_string()).unwrap(),
+            vec![2000.5, 2001.5, 9., 12.]
+        );
+        assert!(matches!(
+            data_bounds("DATE,TMAX\n".to_string()),
+            Err(MyError::NoData)
+        ));
+    }
+
+    #[test]
+    fn test_parse_csv() {
+        let (observed, parameters) =
+            parse_csv("# a comment\nDATE, TMAX\n\n2000,1.5\n2001, 2\n".to_string()).unwrap();
+        assert_eq!(parameters, vec!["DATE", "TMAX"]);
+        assert_eq!(observed, vec![vec![2000., 1.5], vec![2001., 2.]]);
+
+        assert_eq!(parse_csv(String::new()).unwrap(), (vec![], vec![]));
+
+        // the lines of the input - comments and blank lines included
+        assert!(matches!(
+            parse_csv("DATE,TMAX\n2000,1\n\n2001,n/a\n".to_string()),
+            Err(MyError::NonNumericCell { line: 4, column }) if column == "TMAX"
+        ));
+        assert!(matches!(
+            parse_csv("DATE,TMAX\n2000\n".to_string()),
+            Err(MyError::ColumnCountMismatch {
+                line: 2,
+                expected: 2,
+                found: 1
+            })
+        ));
+
+        // propagated - checked before the canvas
+        assert!(matches!(
+            plot_tmax(
+                "missing",
+                String::new(),
+                String::from("DATE,TMAX\n2000,20,1\n"),
+                None,
+                None,
+                None
+            ),
+            Err(MyError::ColumnCountMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_plot_tmax_no_data() {
+        // checked before the canvas
+        assert!(matches!(
+            plot_tmax(
+                "missing",
+                String::new(),
+                String::from("DATE,TMAX\n"),
+                None,
+                None,
+                None
+            ),
+            Err(MyError::NoData)
+        ));
+    }
+
+    #[test]
+    fn test_subsample() {
+        let observed = (0..100)
+            .map(|i| vec![2000. + i as f64, i as f64])
+            .collect::<Vec<_>>();
+
+        let kept = subsample(observed.clone(), 10, 3);
+        assert_eq!(kept.len(), 10);
+        // in order, without repetition
+        assert!(kept.windows(2).all(|pair| pair[0][0] < pair[1][0]));
+        assert!(kept.iter().all(|row| observed.contains(row)));
+        // seeded
+        assert_eq!(subsample(observed.clone(), 10, 3), kept);
+        assert_ne!(subsample(observed.clone(), 10, 4), kept);
+
+        assert_eq!(subsample(observed.clone(), 0, 3), observed);
+        assert_eq!(subsample(observed.clone(), 1000, 3), observed);
+    }
+
+    #[test]
+    fn test_svg() {
+        let svg = plot_tmax_svg(
+            "ALPHA,BETA,SIGMA\n20,0.1,1\n".to_string(),
+            "DATE,TMAX\n2000,20\n2001,21\n2002,20.5\n".to_string(),
+            None,
+            None,
+            Some("Station X".to_string()),
+            Some(600),
+            None,
+        )
+        .unwrap();
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.contains("width=\"600\" height=\"800\""));
+        assert!(svg.contains("Station X"));
+
+        assert!(matches!(
+            export_trace_plot_svg(None, None, None, None, None),
+            Err(MyError::NoRun)
+        ));
+    }
+
+    #[test]
+    fn test_parameter_names() {
+        assert!(matches!(parameter_names(), Err(MyError::NoRun)));
+
+        let model = Regression::new(vec![0., 1., 2., 3.], vec![1., 2., 2., 4.]);
+        let settings = chain::RunSettings {
+            chain_count: 1,
+            tuning: 10,
+            samples: 10,
+            ..Default::default()
+        };
+        let chains = chain::Chains::run(model, &settings).unwrap();
+        LAST_RUN.with(|last_run| *last_run.borrow_mut() = Some(chains));
+
+        assert_eq!(parameter_names().unwrap(), vec!["alpha", "beta", "sigma"]);
+        assert!(integrated_autocorr_time(2).unwrap() > 0.);
+        assert!(matches!(
+            integrated_autocorr_time(3),
+            Err(MyError::UnknownParameter {
+                parameter_idx: 3,
+                dim: 3
+            })
+        ));
+    }
+
+    #[test]
+    fn test_split_stations() {
+        let raw = "ID,DATE,ELEMENT,DATA_VALUE,M_FLAG,Q_FLAG,S_FLAG,OBS_TIME
+DENSE,20000101,TMAX,100,,,S
+SPARSE,20000101,TMAX,100,,,S
+DENSE,20000102,TMAX,150,,,S
+DENSE,20000102,TMIN,50,,,S
+SPARSE,20000102,TMAX,100,,X,S
+DENSE,20000103,TMAX,120,,,S"
+            .to_string();
+
+        let json: serde_json::Value =
+            serde_json::from_str(&split_stations(raw.clone(), Some(2), None).unwrap()).unwrap();
+
+        // the flagged observation does not count
+        assert_eq!(json["skipped"], serde_json::json!({ "SPARSE": 1 }));
+
+        let dense = json["stations"]["DENSE"].as_str().unwrap().to_string();
+        assert_eq!(dense.lines().count(), 5);
+        assert_eq!(
+            prepare(dense, "TMAX", None, None, None, None, None)
+                .unwrap()
+                .lines()
+                .count(),
+            4
+        );
+        assert!(json["stations"].get("SPARSE").is_none());
+
+        // the observations of another element
+        let json: serde_json::Value = serde_json::from_str(
+            &split_stations(raw.clone(), Some(1), Some(String::from("TMIN"))).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(json["skipped"], serde_json::json!({ "SPARSE": 0 }));
+        assert!(json["stations"].get("DENSE").is_some());
+        assert!(matches!(
+            split_stations(raw, None, Some(String::from("PRCP"))),
+            Err(MyError::UnsupportedElement(element)) if element == "PRCP"
+        ));
+
+        assert!(matches!(
+            split_stations("DATE,TMAX\n".to_string(), None, None),
+            Err(MyError::UnexpectedRawDataHeader)
+        ));
+        assert!(matches!(
+            split_stations(
+                "ID,DATE,ELEMENT,DATA_VALUE,M_FLAG,Q_FLAG,S_FLAG,OBS_TIME\nDENSE,20000101"
+                    .to_string(),
+                None,
+                None
+            ),
+            Err(MyError::MalformedRow { line: 2 })
+        ));
+    }
+
+    #[test]
+    fn test_prepare_gz() {
+        use flate2::{write::GzEncoder, Compression};
+        use std::io::Write;
+
+        let raw = "ID,DATE,ELEMENT,DATA_VALUE,M_FLAG,Q_FLAG,S_FLAG,OBS_TIME
+FAKESTATION,20000101,TMAX,100,,,S
+FAKESTATION,20000102,TMAX,150,,,S";
+
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(raw.as_bytes()).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        assert_eq!(
+            prepare_gz(&compressed, "TMAX", None, None, None, None, None).unwrap(),
+            prepare(raw.to_string(), "TMAX", None, None, None, None, None).unwrap()
+        );
+
+        // not gzip
+        assert!(matches!(
+            prepare_gz(raw.as_bytes(), "TMAX", None, None, None, None, None),
+            Err(MyError::InvalidGzip(_))
+        ));
+        // truncated
+        assert!(matches!(
+            prepare_gz(
+                &compressed[..compressed.len() / 2],
+                "TMAX",
+                None,
+                None,
+                None,
+                None,
+                None
+            ),
+            Err(MyError::InvalidGzip(_))
+        ));
+    }
+
+    #[test]
+    fn test_blank_and_comment_lines() {
+        let input = "# exported from a spreadsheet\nDATE,TMAX\n\n2020.1,10\n  \n# a comment\n2020.2,11\n\n\n"
+            .to_string();
+
+        let (observed, parameters) = parse_csv(input).unwrap();
+        assert_eq!(parameters, vec!["DATE", "TMAX"]);
+        assert_eq!(observed, vec![vec![2020.1, 10.], vec![2020.2, 11.]]);
+
+        let raw = "ID,DATE,ELEMENT,DATA_VALUE,M_FLAG,Q_FLAG,S_FLAG,OBS_TIME
+
+FAKESTATION,20240229,TMAX,200,,,S
+# FAKESTATION,20240301,TMAX,100,,,S
+
+"
+        .to_string();
+
+        let output = prepare(raw, "TMAX", None, None, None, None, None).unwrap();
+        assert_eq!(output.lines().count(), 2);
+    }
+
+    #[test]
+    fn test_detrend() {
+        let input = "DATE,DOY,TMAX\n2000,1,12\n2001,1,14\n2002,1,19".to_string();
+
+        // y = 15 + 2 (date - 2001)
+        let output = detrend(input, vec![15., 2., 1.]).unwrap();
+        assert_eq!(
+            output,
+            "DATE,DOY,TMAX_DETRENDED\n2000,1,-1\n2001,1,-1\n2002,1,2\n"
+        );
+
+        let output = detrend("DATE,TMAX\n2000,13\n2002,17".to_string(), vec![15., 2.]).unwrap();
+        assert_eq!(output, "DATE,TMAX_DETRENDED\n2000,0\n2002,0\n");
+
+        // the value column of the other elements
+        let output = detrend("DATE,TMIN_ANOMALY\n2000,1".to_string(), vec![0.5, 0.]).unwrap();
+        assert_eq!(output, "DATE,TMIN_ANOMALY_DETRENDED\n2000,0.5\n");
+
+        assert!(matches!(
+            detrend("DATE,PRCP\n2000,1".to_string(), vec![0., 0.]),
+            Err(MyError::MissingColumn(column)) if column == "TMAX, TMIN or TAVG"
+        ));
+        assert!(matches!(
+            detrend("DATE,TMAX\n2000,13".to_string(), vec![15.]),
+            Err(MyError::PosteriorMeanTooShort(1))
+        ));
+    }
+
+    #[test]
+    fn test_benchmark_length_mismatch() {
+        assert!(matches!(
+            benchmark(
+                "canvas",
+                0,
+                String::new(),
+                2,
+                10,
+                10,
+                Some(vec![2000., 2001.]),
+                Some(vec![10.]),
+            ),
+            Err(MyError::LengthMismatch {
+                dates: 2,
+                values: 1
+            })
+        ));
+    }
+
+    #[test]
+    fn test_join_by_date() {
+        let tmax = "DATE,TMAX\n2020.1,10\n2020.2,11\n2020.3,12".to_string();
+        let prcp = "PRCP,DATE\n0.5,2020.3\n0,2020.1\n1.5,2020.4".to_string();
+
+        let joined = join_by_date(vec![tmax.clone(), prcp]).unwrap();
+        assert_eq!(joined, "DATE,TMAX,PRCP\n2020.1,10,0\n2020.3,12,0.5\n");
+
+        assert!(matches!(
+            join_by_date(vec![tmax, "TMIN\n1".to_string()]),
+            Err(MyError::MissingColumn(column)) if column == "DATE"
+        ));
+    }
+
+    #[test]
+    fn test_run_options() {
+        let options: RunOptions = serde_json::from_str(
+            r#"{"fixed_sigma": 0.5, "centering": 1, "layout": {"columns": 2, "legend": 5}}"#,
+        )
+        .unwrap();
+        assert_eq!(options.fixed_sigma, Some(0.5));
+        assert_eq!(options.centering, Some(Centering::Median));
+        let layout = options.layout.unwrap();
+        assert_eq!(layout.columns, 2);
+        assert_eq!(layout.legend, plot::LegendPosition::Hidden);
+        // the missing fields keep the default
+        assert_eq!(layout.panels, chain::PlotPanels::Both);
+        assert_eq!(options.seeds, None);
+
+        // a misspelled field or a variant out of range is an error
+        assert!(serde_json::from_str::<RunOptions>(r#"{"fixed_sigm": 0.5}"#).is_err());
+        assert!(serde_json::from_str::<RunOptions>(r#"{"sigma_prior": 3}"#).is_err());
+    }
+
+    #[test]
+    fn test_logistic_options() {
+        let options: LogisticOptions =
+            serde_json::from_str(r#"{"y_column": 2, "beta_prior_sd": 2.5}"#).unwrap();
+        assert_eq!(options.y_column, Some(2));
+        assert_eq!(options.beta_prior_sd, Some(2.5));
+        // the missing fields keep the default
+        assert_eq!(options.x_column, None);
+        assert_eq!(options.title_prefix, None);
+
+        // the options of run_with are not those of the logistic regression
+        assert!(serde_json::from_str::<LogisticOptions>(r#"{"fixed_sigma": 0.5}"#).is_err());
+    }
+}
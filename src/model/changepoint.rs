@@ -0,0 +1,230 @@
+//! # changepoint
+use nuts_rs::{CpuLogpFunc, LogpError};
+
+use crate::chain::{Model, Support};
+use crate::numerics::log_pdf_normal_propto;
+
+/// A simple error type.
+#[derive(Debug)]
+pub(crate) enum ChangepointError {
+    /// Sigma is negative.
+    NegativeSigma,
+    /// The changepoint is outside of the observed period.
+    ChangepointOutOfRange,
+}
+
+impl std::fmt::Display for ChangepointError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ChangepointError::NegativeSigma => write!(f, "Sigma is negative"),
+            ChangepointError::ChangepointOutOfRange => {
+                write!(f, "The changepoint is outside of the observed period")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ChangepointError {}
+
+impl LogpError for ChangepointError {
+    fn is_recoverable(&self) -> bool {
+        true
+    }
+}
+
+/// A two-segment regression model.
+///
+/// The trend changes slope at a changepoint `tau` and is continuous there:
+/// - `y = alpha + beta1 * (x - tau)` before `tau`
+/// - `y = alpha + beta2 * (x - tau)` after `tau`
+///
+/// so `alpha` is the value at the changepoint. The intercept and the slopes
+/// have normal priors, `tau` a flat prior over the observed period and sigma a
+/// flat prior.
+///
+/// The mean is not differentiable in `tau` at the observations - the gradient
+/// uses the slope of the segment the observation belongs to (a subgradient).
+#[derive(Clone)]
+pub(crate) struct Changepoint {
+    x: Vec<f64>,
+    y: Vec<f64>,
+    /// The observed period - the support of `tau`.
+    x_range: (f64, f64),
+}
+
+impl Changepoint {
+    /// Create a new changepoint model.
+    pub fn new(x: Vec<f64>, y: Vec<f64>) -> Self {
+        assert_eq!(x.len(), y.len(), "Dimension mismatch");
+        assert!(!x.is_empty(), "No observations");
+
+        let x_range = x
+            .iter()
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), x| {
+                (min.min(*x), max.max(*x))
+            });
+
+        Self { x, y, x_range }
+    }
+}
+
+impl CpuLogpFunc for Changepoint {
+    type Err = ChangepointError;
+
+    fn logp(&mut self, position: &[f64], grad: &mut [f64]) -> Result<f64, Self::Err> {
+        // positions = alpha, beta1, beta2, tau, sigma
+
+        const ALPHA: usize = 0;
+        const BETA1: usize = 1;
+        const BETA2: usize = 2;
+        const TAU: usize = 3;
+        const SIGMA: usize = 4;
+
+        let alpha = position[ALPHA];
+        let beta1 = position[BETA1];
+        let beta2 = position[BETA2];
+        let tau = position[TAU];
+        let sigma = position[SIGMA];
+
+        if sigma <= 0.0 {
+            return Err(ChangepointError::NegativeSigma);
+        }
+
+        if tau < self.x_range.0 || tau > self.x_range.1 {
+            return Err(ChangepointError::ChangepointOutOfRange);
+        }
+
+        let logp_alpha = log_pdf_normal_propto(alpha, 10f64.ln(), 0.01);
+        let logp_beta1 = log_pdf_normal_propto(beta1, 10f64.ln(), 0.01);
+        let logp_beta2 = log_pdf_normal_propto(beta2, 10f64.ln(), 0.01);
+        // flat priors on tau and sigma
+
+        let mut d_logp_d_alpha = -alpha / 100.;
+        let mut d_logp_d_beta1 = -beta1 / 100.;
+        let mut d_logp_d_beta2 = -beta2 / 100.;
+        let mut d_logp_d_tau = 0.;
+        let mut d_logp_d_sigma = 0.;
+
+        let mut logp_y = 0.;
+
+        let sigma_inv = sigma.recip();
+        let var_inv = (sigma * sigma).recip();
+        let var_sigma_inv = var_inv * sigma_inv;
+        let log_sigma = sigma.ln();
+        for (x, y) in self.x.iter().zip(self.y.iter()) {
+            let before = *x < tau;
+            let beta = if before { beta1 } else { beta2 };
+
+            let mu_ = alpha + beta * (x - tau);
+            let diff = y - mu_;
+
+            logp_y += log_pdf_normal_propto(diff, log_sigma, var_inv);
+
+            d_logp_d_alpha += diff * var_inv;
+            if before {
+                d_logp_d_beta1 += diff * (x - tau) * var_inv;
+            } else {
+                d_logp_d_beta2 += diff * (x - tau) * var_inv;
+            }
+            d_logp_d_tau -= diff * beta * var_inv;
+            d_logp_d_sigma += diff * diff * var_sigma_inv - sigma_inv;
+        }
+
+        grad[ALPHA] = d_logp_d_alpha;
+        grad[BETA1] = d_logp_d_beta1;
+        grad[BETA2] = d_logp_d_beta2;
+        grad[TAU] = d_logp_d_tau;
+        grad[SIGMA] = d_logp_d_sigma;
+
+        Ok(logp_y + logp_alpha + logp_beta1 + logp_beta2)
+    }
+
+    fn dim(&self) -> usize {
+        5
+    }
+}
+
+impl Model for Changepoint {
+    fn parameters(&self) -> Vec<String> {
+        ["alpha", "beta1", "beta2", "tau", "sigma"]
+            .iter()
+            .map(|p| p.to_string())
+            .collect()
+    }
+
+    fn supports(&self) -> Vec<Support> {
+        vec![
+            Support::Real,
+            Support::Real,
+            Support::Real,
+            Support::Real,
+            Support::Positive,
+        ]
+    }
+
+    /// A flat trend at the mean of the observations, breaking in the middle of
+    /// the observed period.
+    fn suggested_initial(&self) -> Vec<f64> {
+        let n = self.y.len() as f64;
+        let y_mean = self.y.iter().sum::<f64>() / n;
+        let y_sd = (self.y.iter().map(|y| (y - y_mean).powi(2)).sum::<f64>() / n).sqrt();
+
+        let tau = (self.x_range.0 + self.x_range.1) / 2.;
+
+        vec![y_mean, 0., 0., tau, if y_sd > 0. { y_sd } else { 1. }]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gradient() {
+        let x = (0..40).map(|i| 1980. + i as f64).collect::<Vec<_>>();
+        let y = x
+            .iter()
+            .map(|x| 15. + if *x < 2000. { 0.01 } else { 0.05 } * (x - 2000.) + (x * 3.).sin())
+            .collect::<Vec<_>>();
+        let mut model = Changepoint::new(x, y);
+
+        // tau between two observations - the density is smooth there
+        let position = [15.2, 0.02, 0.04, 1999.5, 0.8];
+        let mut grad = vec![0.; 5];
+        model.logp(&position, &mut grad).unwrap();
+
+        for i in 0..5 {
+            let h = 1e-6;
+            let (mut plus, mut minus) = (position, position);
+            plus[i] += h;
+            minus[i] -= h;
+
+            let mut g = vec![0.; 5];
+            let fd = (model.logp(&plus, &mut g).unwrap() - model.logp(&minus, &mut g).unwrap())
+                / (2. * h);
+            assert!(
+                (grad[i] - fd).abs() < 1e-4 * fd.abs().max(1.),
+                "{}: {} vs {}",
+                i,
+                grad[i],
+                fd
+            );
+        }
+    }
+
+    #[test]
+    fn test_changepoint_support() {
+        let mut model = Changepoint::new(vec![2000., 2010., 2020.], vec![1., 2., 4.]);
+        let mut grad = vec![0.; 5];
+
+        assert!(model.logp(&[2., 0.1, 0.2, 2010., 1.], &mut grad).is_ok());
+        assert!(matches!(
+            model.logp(&[2., 0.1, 0.2, 2021., 1.], &mut grad),
+            Err(ChangepointError::ChangepointOutOfRange)
+        ));
+
+        // the suggested start is valid
+        let initial = model.suggested_initial();
+        assert!(model.logp(&initial, &mut grad).is_ok());
+    }
+}
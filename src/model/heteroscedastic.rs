@@ -0,0 +1,287 @@
+//! # heteroscedastic
+use nuts_rs::{CpuLogpFunc, LogpError};
+
+use crate::chain::Model;
+use crate::numerics::log_pdf_normal_propto;
+
+/// A simple error type.
+#[derive(Debug)]
+pub(crate) enum HeteroscedasticError {
+    /// The noise level of an observation is zero or infinite.
+    DegenerateSigma,
+}
+
+impl std::fmt::Display for HeteroscedasticError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            HeteroscedasticError::DegenerateSigma => {
+                write!(f, "The noise level of an observation is zero or infinite")
+            }
+        }
+    }
+}
+
+impl std::error::Error for HeteroscedasticError {}
+
+impl LogpError for HeteroscedasticError {
+    fn is_recoverable(&self) -> bool {
+        true
+    }
+}
+
+/// The season feature of a day of the year (1 to 366): 1 at the start of
+/// January, -1 at the start of July.
+pub(crate) fn season_feature(doy: f64) -> f64 {
+    (2. * std::f64::consts::PI * (doy - 1.) / 365.25).cos()
+}
+
+/// A linear regression whose noise level depends on a covariate.
+///
+/// - `y = alpha + beta * (x - x_m) + sigma_i * eps` with `eps ~ N(0, 1)`
+/// - `log(sigma_i) = gamma0 + gamma1 * s_i`
+///
+/// where `x_m` is the mean of `x` and `s_i` the covariate of the observation -
+/// e.g. its [`season_feature`]. `gamma1` tells how much the spread changes
+/// along the covariate: with the season feature, a positive `gamma1` means
+/// more variable winters.
+///
+/// The intercept and the slope have normal priors, `gamma0` and `gamma1` flat
+/// priors.
+#[derive(Clone)]
+pub(crate) struct Heteroscedastic {
+    x: Vec<f64>,
+    s: Vec<f64>,
+    y: Vec<f64>,
+    /// The reference of `x` - the middle of the observed period.
+    x_m: f64,
+}
+
+impl Heteroscedastic {
+    /// Create a new model of `y` against `x` with the noise level driven by `s`.
+    pub fn new(x: Vec<f64>, s: Vec<f64>, y: Vec<f64>) -> Self {
+        assert_eq!(x.len(), y.len(), "Dimension mismatch");
+        assert_eq!(s.len(), y.len(), "Dimension mismatch");
+        assert!(!x.is_empty(), "No observations");
+
+        let x_m = x.iter().sum::<f64>() / x.len() as f64;
+
+        Self { x, s, y, x_m }
+    }
+}
+
+impl CpuLogpFunc for Heteroscedastic {
+    type Err = HeteroscedasticError;
+
+    fn logp(&mut self, position: &[f64], grad: &mut [f64]) -> Result<f64, Self::Err> {
+        // positions = alpha, beta, gamma0, gamma1
+
+        const ALPHA: usize = 0;
+        const BETA: usize = 1;
+        const GAMMA0: usize = 2;
+        const GAMMA1: usize = 3;
+
+        let alpha = position[ALPHA];
+        let beta = position[BETA];
+        let gamma0 = position[GAMMA0];
+        let gamma1 = position[GAMMA1];
+
+        let logp_alpha = log_pdf_normal_propto(alpha, 10f64.ln(), 0.01);
+        let logp_beta = log_pdf_normal_propto(beta, 10f64.ln(), 0.01);
+        // flat priors on gamma0 and gamma1
+
+        let mut d_logp_d_alpha = -alpha / 100.;
+        let mut d_logp_d_beta = -beta / 100.;
+        let mut d_logp_d_gamma0 = 0.;
+        let mut d_logp_d_gamma1 = 0.;
+
+        let mut logp_y = 0.;
+
+        for ((x, s), y) in self.x.iter().zip(self.s.iter()).zip(self.y.iter()) {
+            let log_sigma = gamma0 + gamma1 * s;
+            let var_inv = (-2. * log_sigma).exp();
+            if var_inv == 0. || !var_inv.is_finite() {
+                return Err(HeteroscedasticError::DegenerateSigma);
+            }
+
+            let x = x - self.x_m;
+            let diff = y - (alpha + beta * x);
+
+            logp_y += log_pdf_normal_propto(diff, log_sigma, var_inv);
+
+            d_logp_d_alpha += diff * var_inv;
+            d_logp_d_beta += diff * x * var_inv;
+            // derivative with respect to log(sigma_i)
+            let d_log_sigma = diff * diff * var_inv - 1.;
+            d_logp_d_gamma0 += d_log_sigma;
+            d_logp_d_gamma1 += d_log_sigma * s;
+        }
+
+        grad[ALPHA] = d_logp_d_alpha;
+        grad[BETA] = d_logp_d_beta;
+        grad[GAMMA0] = d_logp_d_gamma0;
+        grad[GAMMA1] = d_logp_d_gamma1;
+
+        Ok(logp_y + logp_alpha + logp_beta)
+    }
+
+    fn dim(&self) -> usize {
+        4
+    }
+}
+
+impl Model for Heteroscedastic {
+    fn parameters(&self) -> Vec<String> {
+        ["alpha", "beta", "gamma0", "gamma1"]
+            .iter()
+            .map(|p| p.to_string())
+            .collect()
+    }
+
+    /// The least-squares line with a constant noise level - the spread of its
+    /// residuals.
+    fn suggested_initial(&self) -> Vec<f64> {
+        let n = self.y.len() as f64;
+        let y_mean = self.y.iter().sum::<f64>() / n;
+
+        let sxx = self.x.iter().map(|x| (x - self.x_m).powi(2)).sum::<f64>();
+        let sxy = self
+            .x
+            .iter()
+            .zip(self.y.iter())
+            .map(|(x, y)| (x - self.x_m) * (y - y_mean))
+            .sum::<f64>();
+        let beta = if sxx > 0. { sxy / sxx } else { 0. };
+
+        let residual_sd = (self
+            .x
+            .iter()
+            .zip(self.y.iter())
+            .map(|(x, y)| (y - y_mean - beta * (x - self.x_m)).powi(2))
+            .sum::<f64>()
+            / n)
+            .sqrt();
+        let gamma0 = if residual_sd > 0. {
+            residual_sd.ln()
+        } else {
+            0.
+        };
+
+        vec![y_mean, beta, gamma0, 0.]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use rand_distr::{Distribution, StandardNormal};
+
+    use super::*;
+    use crate::chain;
+    use crate::stats::mean;
+
+    #[test]
+    fn test_gradient() {
+        let x = (0..40).map(|i| 1980. + i as f64 / 4.).collect::<Vec<_>>();
+        let s = (0..40)
+            .map(|i| season_feature((i * 91 % 365 + 1) as f64))
+            .collect::<Vec<_>>();
+        let y = x
+            .iter()
+            .zip(s.iter())
+            .map(|(x, s)| 15. + 0.03 * (x - 1985.) + (1. + s) * (x * 3.).sin())
+            .collect::<Vec<_>>();
+        let mut model = Heteroscedastic::new(x, s, y);
+
+        let position = [15.2, 0.02, -0.1, 0.4];
+        let mut grad = vec![0.; 4];
+        model.logp(&position, &mut grad).unwrap();
+
+        for i in 0..4 {
+            let h = 1e-6;
+            let (mut plus, mut minus) = (position, position);
+            plus[i] += h;
+            minus[i] -= h;
+
+            let mut g = vec![0.; 4];
+            let fd = (model.logp(&plus, &mut g).unwrap() - model.logp(&minus, &mut g).unwrap())
+                / (2. * h);
+            assert!(
+                (grad[i] - fd).abs() < 1e-4 * fd.abs().max(1.),
+                "{}: {} vs {}",
+                i,
+                grad[i],
+                fd
+            );
+        }
+
+        // sigma overflows
+        assert!(matches!(
+            model.logp(&[15., 0., 1000., 0.], &mut grad),
+            Err(HeteroscedasticError::DegenerateSigma)
+        ));
+    }
+
+    #[test]
+    fn test_season_feature() {
+        assert_eq!(season_feature(1.), 1.);
+        assert!((season_feature(183.625) + 1.).abs() < 1e-12);
+        assert!(season_feature(92.).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_suggested_initial() {
+        let model = Heteroscedastic::new(
+            vec![2000., 2001., 2002., 2003.],
+            vec![1., -1., 1., -1.],
+            vec![10., 11., 12., 13.],
+        );
+
+        // perfect fit: unit noise level
+        assert_eq!(model.suggested_initial(), vec![11.5, 1., 0., 0.]);
+    }
+
+    #[test]
+    fn test_recover_noise_level() {
+        let mut rng = StdRng::seed_from_u64(5);
+
+        // twice the spread in winter as in summer
+        let (gamma0, gamma1) = (0.5, 0.35);
+        let doy = (0..600)
+            .map(|i| (i * 7 % 365 + 1) as f64)
+            .collect::<Vec<_>>();
+        let x = (0..600).map(|i| 2000. + i as f64 / 50.).collect::<Vec<_>>();
+        let s = doy.iter().map(|d| season_feature(*d)).collect::<Vec<_>>();
+        let y = x
+            .iter()
+            .zip(s.iter())
+            .map(|(x, s)| {
+                let eps: f64 = StandardNormal.sample(&mut rng);
+                20. + 0.1 * (x - 2006.) + (gamma0 + gamma1 * s).exp() * eps
+            })
+            .collect::<Vec<_>>();
+        let model = Heteroscedastic::new(x, s, y);
+
+        let settings = chain::RunSettings {
+            seed: 1,
+            chain_count: 2,
+            tuning: 300,
+            samples: 300,
+            ..Default::default()
+        };
+        let chains = chain::Chains::run(model, &settings).unwrap();
+
+        let estimate = |i: usize| mean(&chains.traces(i).concat());
+        assert!((estimate(1) - 0.1).abs() < 0.05, "beta: {}", estimate(1));
+        assert!(
+            (estimate(2) - gamma0).abs() < 0.1,
+            "gamma0: {}",
+            estimate(2)
+        );
+        assert!(
+            (estimate(3) - gamma1).abs() < 0.1,
+            "gamma1: {}",
+            estimate(3)
+        );
+    }
+}
@@ -0,0 +1,284 @@
+//! # logistic
+use nuts_rs::{CpuLogpFunc, LogpError};
+
+use crate::chain::{Model, Support};
+use crate::model::prior::PriorSpec;
+
+/// A simple error type.
+#[derive(Debug)]
+pub(crate) enum LogisticError {
+    /// The log-odds of an observation are not finite.
+    NonFiniteLogOdds,
+}
+
+impl std::fmt::Display for LogisticError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            LogisticError::NonFiniteLogOdds => {
+                write!(f, "The log-odds of an observation are not finite")
+            }
+        }
+    }
+}
+
+impl std::error::Error for LogisticError {}
+
+impl LogpError for LogisticError {
+    fn is_recoverable(&self) -> bool {
+        true
+    }
+}
+
+/// `log(1 + exp(x))` - without overflow for large `x`.
+fn softplus(x: f64) -> f64 {
+    x.max(0.) + (-x.abs()).exp().ln_1p()
+}
+
+/// `1 / (1 + exp(-x))`
+fn sigmoid(x: f64) -> f64 {
+    if x >= 0. {
+        1. / (1. + (-x).exp())
+    } else {
+        let e = x.exp();
+        e / (1. + e)
+    }
+}
+
+/// A logistic regression of a binary outcome.
+///
+/// - `P(y = 1) = sigmoid(alpha + beta * (x - x_m))`
+///
+/// where `x_m` is the mean of `x`: `alpha` is the log-odds of the outcome in
+/// the middle of the observed range and `beta` the change of the log-odds per
+/// unit of `x`.
+///
+/// The intercept and the slope have normal priors - of mean 0 and standard
+/// deviation [`Logistic::DEFAULT_PRIOR_SD`] by default.
+#[derive(Clone)]
+pub(crate) struct Logistic {
+    x: Vec<f64>,
+    y: Vec<f64>,
+    /// The reference of `x` - the middle of the observed period.
+    x_m: f64,
+    /// Prior of the intercept
+    alpha_prior: PriorSpec,
+    /// Prior of the slope
+    beta_prior: PriorSpec,
+}
+
+impl Logistic {
+    /// Standard deviation of the default priors of alpha and beta.
+    pub const DEFAULT_PRIOR_SD: f64 = 10.;
+
+    /// Create a new model of the outcomes `y` - 0 or 1 - against `x`.
+    pub fn new(x: Vec<f64>, y: Vec<f64>) -> Self {
+        assert_eq!(x.len(), y.len(), "Dimension mismatch");
+        assert!(!x.is_empty(), "No observations");
+        assert!(
+            y.iter().all(|y| *y == 0. || *y == 1.),
+            "The outcomes must be 0 or 1"
+        );
+
+        let x_m = x.iter().sum::<f64>() / x.len() as f64;
+        let prior = PriorSpec::Normal {
+            mean: 0.,
+            sd: Self::DEFAULT_PRIOR_SD,
+        };
+
+        Self {
+            x,
+            y,
+            x_m,
+            alpha_prior: prior,
+            beta_prior: prior,
+        }
+    }
+
+    /// Use `prior` as the prior of the intercept.
+    pub fn with_alpha_prior(mut self, prior: PriorSpec) -> Self {
+        prior.validate();
+        self.alpha_prior = prior;
+        self
+    }
+
+    /// Use `prior` as the prior of the slope - per unit of `x`.
+    pub fn with_beta_prior(mut self, prior: PriorSpec) -> Self {
+        prior.validate();
+        self.beta_prior = prior;
+        self
+    }
+
+    /// Returns the Bernoulli log likelihood at `alpha` and `beta` and its
+    /// gradient.
+    fn log_likelihood(&self, alpha: f64, beta: f64) -> Result<(f64, f64, f64), LogisticError> {
+        let mut logp = 0.;
+        let mut d_alpha = 0.;
+        let mut d_beta = 0.;
+
+        for (x, y) in self.x.iter().zip(self.y.iter()) {
+            let x = x - self.x_m;
+            let eta = alpha + beta * x;
+            if !eta.is_finite() {
+                return Err(LogisticError::NonFiniteLogOdds);
+            }
+
+            // y log(p) + (1 - y) log(1 - p)
+            logp += y * eta - softplus(eta);
+
+            let residual = y - sigmoid(eta);
+            d_alpha += residual;
+            d_beta += residual * x;
+        }
+
+        Ok((logp, d_alpha, d_beta))
+    }
+}
+
+impl CpuLogpFunc for Logistic {
+    type Err = LogisticError;
+
+    fn logp(&mut self, position: &[f64], grad: &mut [f64]) -> Result<f64, Self::Err> {
+        // positions = alpha, beta
+
+        const ALPHA: usize = 0;
+        const BETA: usize = 1;
+
+        let alpha = position[ALPHA];
+        let beta = position[BETA];
+
+        let (logp_alpha, d_logp_d_alpha) = self.alpha_prior.log_density(alpha);
+        let (logp_beta, d_logp_d_beta) = self.beta_prior.log_density(beta);
+
+        let (logp_y, d_alpha, d_beta) = self.log_likelihood(alpha, beta)?;
+
+        grad[ALPHA] = d_logp_d_alpha + d_alpha;
+        grad[BETA] = d_logp_d_beta + d_beta;
+
+        Ok(logp_y + logp_alpha + logp_beta)
+    }
+
+    fn dim(&self) -> usize {
+        2
+    }
+}
+
+impl Model for Logistic {
+    fn parameters(&self) -> Vec<String> {
+        ["alpha", "beta"].iter().map(|p| p.to_string()).collect()
+    }
+
+    /// The log-odds of the observed rate - with a flat slope.
+    fn suggested_initial(&self) -> Vec<f64> {
+        let n = self.y.len() as f64;
+        // away from 0 and 1 - finite log-odds
+        let rate = (self.y.iter().sum::<f64>() + 0.5) / (n + 1.);
+
+        vec![(rate / (1. - rate)).ln(), 0.]
+    }
+
+    /// The Bernoulli likelihood is normalized: this is the log likelihood plus
+    /// the normalized log priors - `None` if a prior is improper.
+    fn log_joint(&self, position: &[f64]) -> Option<f64> {
+        let log_prior = self.alpha_prior.log_pdf(position[0], Support::Real)?
+            + self.beta_prior.log_pdf(position[1], Support::Real)?;
+        let (log_likelihood, _, _) = self.log_likelihood(position[0], position[1]).ok()?;
+
+        Some(log_prior + log_likelihood)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use super::*;
+    use crate::chain;
+    use crate::stats::mean;
+
+    #[test]
+    fn test_gradient() {
+        let x = (0..30).map(|i| 1990. + i as f64).collect::<Vec<_>>();
+        let y = (0..30)
+            .map(|i| if (i * 7) % 5 < 2 { 1. } else { 0. })
+            .collect::<Vec<_>>();
+        let mut model =
+            Logistic::new(x, y).with_beta_prior(PriorSpec::Normal { mean: 0.1, sd: 1. });
+
+        let position = [-0.3, 0.05];
+        let mut grad = vec![0.; 2];
+        model.logp(&position, &mut grad).unwrap();
+
+        for i in 0..2 {
+            let h = 1e-6;
+            let (mut plus, mut minus) = (position, position);
+            plus[i] += h;
+            minus[i] -= h;
+
+            let mut g = vec![0.; 2];
+            let fd = (model.logp(&plus, &mut g).unwrap() - model.logp(&minus, &mut g).unwrap())
+                / (2. * h);
+            assert!(
+                (grad[i] - fd).abs() < 1e-4 * fd.abs().max(1.),
+                "{}: {} vs {}",
+                i,
+                grad[i],
+                fd
+            );
+        }
+
+        // no overflow for extreme log-odds
+        assert!(model.logp(&[800., 0.], &mut grad).unwrap().is_finite());
+        assert!(model.logp(&[-800., 0.], &mut grad).unwrap().is_finite());
+        assert!(matches!(
+            model.logp(&[f64::INFINITY, 0.], &mut grad),
+            Err(LogisticError::NonFiniteLogOdds)
+        ));
+    }
+
+    #[test]
+    fn test_sigmoid() {
+        assert_eq!(sigmoid(0.), 0.5);
+        assert!((sigmoid(2.) + sigmoid(-2.) - 1.).abs() < 1e-15);
+        assert_eq!(sigmoid(-1000.), 0.);
+        assert_eq!(sigmoid(1000.), 1.);
+        assert!((softplus(-1000.)).abs() < 1e-300);
+        assert_eq!(softplus(1000.), 1000.);
+        assert!((softplus(0.) - 2f64.ln()).abs() < 1e-15);
+    }
+
+    #[test]
+    fn test_recover_coefficients() {
+        let mut rng = StdRng::seed_from_u64(3);
+
+        let (alpha, beta) = (0.5, 1.5);
+        let x = (0..2000).map(|i| i as f64 / 500. - 2.).collect::<Vec<_>>();
+        let y = x
+            .iter()
+            .map(|x| {
+                let p = sigmoid(alpha + beta * x);
+                if rng.gen::<f64>() < p {
+                    1.
+                } else {
+                    0.
+                }
+            })
+            .collect::<Vec<_>>();
+        let model = Logistic::new(x, y);
+        assert!(model.log_joint(&[alpha, beta]).unwrap().is_finite());
+
+        let settings = chain::RunSettings {
+            seed: 1,
+            chain_count: 2,
+            tuning: 300,
+            samples: 300,
+            ..Default::default()
+        };
+        let chains = chain::Chains::run(model, &settings).unwrap();
+
+        let estimate = |i: usize| mean(&chains.traces(i).concat());
+        // x is centered on 0
+        assert!((estimate(0) - alpha).abs() < 0.2, "alpha: {}", estimate(0));
+        assert!((estimate(1) - beta).abs() < 0.2, "beta: {}", estimate(1));
+    }
+}
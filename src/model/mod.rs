@@ -1,3 +1,7 @@
 //! Models
+pub(crate) mod changepoint;
+pub(crate) mod heteroscedastic;
+pub(crate) mod logistic;
 pub(crate) mod mv;
+pub(crate) mod prior;
 pub(crate) mod regression;
@@ -1,4 +1,5 @@
-//! The model for the example is a 2D normal distribution.
+//! The model for the example is a multivariate normal distribution of unknown
+//! mean and known (unit) covariance.
 use std::fmt::Display;
 use std::fmt::Formatter;
 
@@ -13,6 +14,27 @@ pub(crate) struct MultivariateNormalModel {
     pub(crate) parameters: Vec<String>,
 }
 
+impl MultivariateNormalModel {
+    /// Create a new model of the mean of the observations - `mu_<column>` for
+    /// each column.
+    pub fn new(observed: Vec<Vec<f64>>, columns: &[String]) -> Self {
+        let dims = columns.len();
+        assert!(
+            observed.iter().all(|obs| obs.len() == dims),
+            "Dimension mismatch"
+        );
+
+        Self {
+            observed,
+            dims,
+            parameters: columns
+                .iter()
+                .map(|column| format!("mu_{}", column))
+                .collect(),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub(crate) enum MutlivariateNormalError {}
 
@@ -35,6 +57,14 @@ impl Model for MultivariateNormalModel {
     fn parameters(&self) -> Vec<String> {
         self.parameters.clone()
     }
+
+    /// The sample mean - the mode of the posterior.
+    fn suggested_initial(&self) -> Vec<f64> {
+        let n = self.observed.len() as f64;
+        (0..self.dims)
+            .map(|d| self.observed.iter().map(|obs| obs[d]).sum::<f64>() / n)
+            .collect()
+    }
 }
 
 impl CpuLogpFunc for MultivariateNormalModel {
@@ -78,3 +108,35 @@ impl CpuLogpFunc for MultivariateNormalModel {
         Ok(logp)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mvnormal() {
+        let columns = vec![String::from("X"), String::from("Y")];
+        let observed = vec![vec![1., 2.], vec![3., -2.], vec![2., 3.]];
+        let mut model = MultivariateNormalModel::new(observed, &columns);
+
+        assert_eq!(model.parameters(), vec!["mu_X", "mu_Y"]);
+
+        // the gradient vanishes at the sample mean
+        let initial = model.suggested_initial();
+        assert_eq!(initial, vec![2., 1.]);
+        let mut grad = vec![0.; 2];
+        model.logp(&initial, &mut grad).unwrap();
+        assert!(grad.iter().all(|g| g.abs() < 1e-12));
+
+        let position = [0.5, 1.5];
+        let logp = model.logp(&position, &mut grad).unwrap();
+        for i in 0..2 {
+            let h = 1e-6;
+            let mut plus = position;
+            plus[i] += h;
+            let mut g = vec![0.; 2];
+            let fd = (model.logp(&plus, &mut g).unwrap() - logp) / h;
+            assert!((grad[i] - fd).abs() < 1e-4, "{}: {} vs {}", i, grad[i], fd);
+        }
+    }
+}
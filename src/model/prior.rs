@@ -0,0 +1,235 @@
+//! # prior
+use serde::Deserialize;
+use std::convert::TryFrom;
+use wasm_bindgen::prelude::*;
+
+use crate::chain::Support;
+use crate::numerics::{
+    d_log_pdf_laplace_propto, log_pdf_laplace_propto, log_pdf_normal, log_pdf_normal_propto,
+};
+use crate::utils::variant_from_index;
+
+/// The prior of a parameter.
+///
+/// The log densities are up to an additive constant - as the sampler needs.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum PriorSpec {
+    /// Normal of mean `mean` and standard deviation `sd`
+    Normal {
+        /// Mean
+        mean: f64,
+        /// Standard deviation
+        sd: f64,
+    },
+    /// Normal of mean 0 and standard deviation `sd` restricted to positive
+    /// values - for scale parameters
+    HalfNormal {
+        /// Standard deviation
+        sd: f64,
+    },
+    /// Cauchy of location 0 and scale `scale` - a half-Cauchy on a positive
+    /// parameter
+    Cauchy {
+        /// Scale
+        scale: f64,
+    },
+    /// Improper uniform prior - the likelihood alone drives the posterior
+    Flat,
+    /// Laplace of location 0 and scale `b` - shrinks the parameter towards 0
+    /// (Bayesian LASSO)
+    Laplace {
+        /// Scale
+        b: f64,
+    },
+}
+
+/// The family of the prior of a scale parameter - e.g. sigma
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "u32")]
+pub enum ScalePrior {
+    /// Improper uniform prior
+    #[default]
+    Flat,
+    /// Half-normal - light tail: large scales are unlikely
+    HalfNormal,
+    /// Half-Cauchy - heavy tail: weakly informative
+    HalfCauchy,
+}
+
+impl TryFrom<u32> for ScalePrior {
+    type Error = String;
+
+    fn try_from(index: u32) -> Result<Self, Self::Error> {
+        use ScalePrior::*;
+        variant_from_index(&[Flat, HalfNormal, HalfCauchy], index)
+    }
+}
+
+impl ScalePrior {
+    /// Returns the prior of this family with the given scale.
+    pub(crate) fn with_scale(self, scale: f64) -> PriorSpec {
+        match self {
+            ScalePrior::Flat => PriorSpec::Flat,
+            ScalePrior::HalfNormal => PriorSpec::HalfNormal { sd: scale },
+            ScalePrior::HalfCauchy => PriorSpec::Cauchy { scale },
+        }
+    }
+}
+
+impl PriorSpec {
+    /// Checks the parameters of the prior.
+    pub fn validate(&self) {
+        let scale = match *self {
+            PriorSpec::Normal { sd, .. } | PriorSpec::HalfNormal { sd } => sd,
+            PriorSpec::Cauchy { scale } => scale,
+            PriorSpec::Laplace { b } => b,
+            PriorSpec::Flat => return,
+        };
+        assert!(scale > 0., "The scale of a prior must be positive");
+    }
+
+    /// Returns the log density of the prior at `x` and its derivative.
+    ///
+    /// A half-normal has no mass on negative values: the log density is then
+    /// -inf.
+    pub fn log_density(&self, x: f64) -> (f64, f64) {
+        match *self {
+            PriorSpec::Normal { mean, sd } => {
+                let var_inv = (sd * sd).recip();
+                (
+                    log_pdf_normal_propto(x - mean, sd.ln(), var_inv),
+                    -(x - mean) * var_inv,
+                )
+            }
+            PriorSpec::HalfNormal { .. } if x < 0. => (f64::NEG_INFINITY, 0.),
+            PriorSpec::HalfNormal { sd } => {
+                let var_inv = (sd * sd).recip();
+                (log_pdf_normal_propto(x, sd.ln(), var_inv), -x * var_inv)
+            }
+            PriorSpec::Cauchy { scale } => {
+                let z = x / scale;
+                (
+                    -scale.ln() - (1. + z * z).ln(),
+                    -2. * x / (scale * scale + x * x),
+                )
+            }
+            PriorSpec::Flat => (0., 0.),
+            PriorSpec::Laplace { b } => {
+                (log_pdf_laplace_propto(x, b), d_log_pdf_laplace_propto(x, b))
+            }
+        }
+    }
+
+    /// Returns the normalized log density of the prior at `x` for a parameter
+    /// with the given support - for the marginal likelihood.
+    ///
+    /// On positive parameters, the priors centered at 0 are folded (e.g. a
+    /// half-Cauchy). `None` if the prior is improper (flat) or cannot be
+    /// normalized on the support (off-center normal on a positive parameter).
+    pub fn log_pdf(&self, x: f64, support: Support) -> Option<f64> {
+        let log_pdf = match *self {
+            PriorSpec::Flat => return None,
+            PriorSpec::Normal { mean, sd } => {
+                if support != Support::Real && mean != 0. {
+                    return None;
+                }
+                log_pdf_normal(x, mean, sd)
+            }
+            PriorSpec::HalfNormal { sd } => {
+                return Some(if x < 0. {
+                    f64::NEG_INFINITY
+                } else {
+                    std::f64::consts::LN_2 + log_pdf_normal(x, 0., sd)
+                });
+            }
+            PriorSpec::Cauchy { scale } => {
+                let z = x / scale;
+                -std::f64::consts::PI.ln() - scale.ln() - (1. + z * z).ln()
+            }
+            PriorSpec::Laplace { b } => -x.abs() / b - (2. * b).ln(),
+        };
+
+        match support {
+            Support::Real => Some(log_pdf),
+            // symmetric around 0 - twice the density on half the line
+            Support::Positive => Some(std::f64::consts::LN_2 + log_pdf),
+            Support::UnitInterval => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_log_density_gradient() {
+        let priors = [
+            PriorSpec::Normal { mean: 1., sd: 2. },
+            PriorSpec::HalfNormal { sd: 3. },
+            PriorSpec::Cauchy { scale: 0.5 },
+            PriorSpec::Flat,
+            PriorSpec::Laplace { b: 2. },
+        ];
+
+        for prior in priors {
+            prior.validate();
+            for x in [0.3, 1., 4.] {
+                let h = 1e-6;
+                let fd = (prior.log_density(x + h).0 - prior.log_density(x - h).0) / (2. * h);
+                let (_, d) = prior.log_density(x);
+                assert!((d - fd).abs() < 1e-6, "{:?} at {}", prior, x);
+            }
+        }
+
+        assert_eq!(
+            PriorSpec::HalfNormal { sd: 1. }.log_density(-1.).0,
+            f64::NEG_INFINITY
+        );
+
+        // normalized
+        let support = Support::Real;
+        assert!(
+            (PriorSpec::Normal { mean: 0., sd: 1. }
+                .log_pdf(0., support)
+                .unwrap()
+                + 0.9189385332046727)
+                .abs()
+                < 1e-14
+        );
+        assert!(
+            (PriorSpec::Cauchy { scale: 1. }
+                .log_pdf(0., support)
+                .unwrap()
+                + std::f64::consts::PI.ln())
+            .abs()
+                < 1e-14
+        );
+        assert!((PriorSpec::Laplace { b: 0.5 }.log_pdf(0., support).unwrap()).abs() < 1e-14);
+        assert_eq!(PriorSpec::Flat.log_pdf(0., support), None);
+        // folded on positive parameters
+        let support = Support::Positive;
+        assert!(
+            (PriorSpec::Cauchy { scale: 1. }
+                .log_pdf(0., support)
+                .unwrap()
+                - (2. / std::f64::consts::PI).ln())
+            .abs()
+                < 1e-14
+        );
+        assert_eq!(
+            PriorSpec::HalfNormal { sd: 2. }.log_pdf(1., support),
+            PriorSpec::Normal { mean: 0., sd: 2. }.log_pdf(1., support)
+        );
+        assert_eq!(
+            PriorSpec::Normal { mean: 1., sd: 2. }.log_pdf(1., support),
+            None
+        );
+
+        // the default prior of the intercept of the regression
+        let (logp, d) = PriorSpec::Normal { mean: 0., sd: 10. }.log_density(3.);
+        assert!((logp - log_pdf_normal_propto(3., 10f64.ln(), 0.01)).abs() < 1e-12);
+        assert!((d - -0.03).abs() < 1e-12);
+    }
+}
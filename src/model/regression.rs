@@ -1,7 +1,15 @@
 //! # regression
 use nuts_rs::{CpuLogpFunc, LogpError};
+use serde::Deserialize;
+use std::convert::TryFrom;
+use wasm_bindgen::prelude::*;
 
-use crate::chain::Model;
+use crate::chain::{Model, Support};
+use crate::model::prior::{PriorSpec, ScalePrior};
+use crate::numerics::{kahan_sum, log_pdf_normal, log_pdf_normal_propto, KahanSum};
+use crate::stats::{mean, quantile};
+use crate::utils::variant_from_index;
+use crate::MyError;
 
 /// A simple error type.
 #[derive(Debug)]
@@ -26,36 +34,466 @@ impl LogpError for RegressionError {
     }
 }
 
+/// The reference of the predictor of a regression - the intercept is the
+/// value of the line there
+///
+/// A reference close to the bulk of the data decorrelates the intercept and
+/// the slope.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "u32")]
+pub enum Centering {
+    /// The mean of the predictor
+    #[default]
+    Mean,
+    /// The median of the predictor - robust to clustered or duplicated values
+    Median,
+    /// A given value of the predictor
+    Reference,
+    /// No centering: the reference is 0
+    None,
+}
+
+impl TryFrom<u32> for Centering {
+    type Error = String;
+
+    fn try_from(index: u32) -> Result<Self, Self::Error> {
+        variant_from_index(
+            &[
+                Centering::Mean,
+                Centering::Median,
+                Centering::Reference,
+                Centering::None,
+            ],
+            index,
+        )
+    }
+}
+
+impl Centering {
+    /// Returns the reference of the predictor `x` - `reference` is the value
+    /// of [`Centering::Reference`].
+    ///
+    /// Fails with [`MyError::NoData`] if `x` is empty and with
+    /// [`MyError::InvalidRunOptions`] if the reference of
+    /// [`Centering::Reference`] is missing.
+    pub(crate) fn center(self, x: &[f64], reference: Option<f64>) -> Result<f64, MyError> {
+        if x.is_empty() {
+            return Err(MyError::NoData);
+        }
+
+        match self {
+            Centering::Mean => Ok(mean(x)),
+            Centering::Median => Ok(quantile(x, 0.5)),
+            Centering::Reference => reference.ok_or_else(|| {
+                MyError::InvalidRunOptions(String::from(
+                    "the Reference centering needs a centering_date",
+                ))
+            }),
+            Centering::None => Ok(0.),
+        }
+    }
+}
+
 /// A regression model.
 ///
-/// The model is a Bayesian regression model with a normal likelihood and
-/// normal priors on the intercept and slope. The standard deviation of the
-/// Gaussian has a flat prior.
+/// The model is a Bayesian regression model with a normal likelihood. By
+/// default, the intercept and slope have normal priors and the standard
+/// deviation of the Gaussian has a flat prior - unless it is fixed to a known
+/// value, in which case it is not sampled. The slope can be fixed too - to
+/// test a hypothesized trend. Each prior can be changed (see
+/// [`Regression::with_priors`]).
+///
+/// Observations can be weighted - e.g. by the number of days averaged in each
+/// observation: the variance of observation `i` is then `sigma² / weight_i`.
+///
+/// With `k` predictors (see [`Regression::new_multiple`]), the mean of `y` is
+/// `alpha + beta_1 x_1 + ... + beta_k x_k`: `beta_1` has the prior of beta and
+/// the other slopes the default one.
 #[derive(Clone)]
 pub(crate) struct Regression {
-    x: Vec<f64>,
+    /// The predictors of each observation - `k` values per observation.
+    x: Vec<Vec<f64>>,
     y: Vec<f64>,
+    /// Number of predictors.
+    k: usize,
+    /// Known standard deviation of the noise.
+    fixed_sigma: Option<f64>,
+    /// Hypothesized slope.
+    fixed_beta: Option<f64>,
+    /// Priors of alpha, the 1st slope and sigma.
+    priors: [PriorSpec; 3],
+    /// Precision weight of each observation - 1 if `None`.
+    weights: Option<Vec<f64>>,
 }
 
 impl Regression {
+    /// Standard deviation of the default normal priors of alpha and beta.
+    pub const DEFAULT_PRIOR_SD: f64 = 10.;
+
+    /// Default priors of alpha, beta and sigma: weakly informative normal
+    /// priors on the intercept and slope, a flat prior on sigma.
+    pub const DEFAULT_PRIORS: [PriorSpec; 3] = [
+        PriorSpec::Normal {
+            mean: 0.,
+            sd: Self::DEFAULT_PRIOR_SD,
+        },
+        PriorSpec::Normal {
+            mean: 0.,
+            sd: Self::DEFAULT_PRIOR_SD,
+        },
+        PriorSpec::Flat,
+    ];
+
     /// Create a new regression model.
     pub fn new(x: Vec<f64>, y: Vec<f64>) -> Self {
         assert_eq!(x.len(), y.len(), "Dimension mismatch");
-        Self { x, y }
+        Self {
+            x: x.into_iter().map(|x| vec![x]).collect(),
+            y,
+            k: 1,
+            fixed_sigma: None,
+            fixed_beta: None,
+            priors: Self::DEFAULT_PRIORS,
+            weights: None,
+        }
+    }
+
+    /// Create a new regression model with several predictors - a row of `k`
+    /// values per observation.
+    ///
+    /// The slopes are named `beta_1` to `beta_k` - `beta` with a single
+    /// predictor, as with [`Regression::new`].
+    ///
+    /// Fails with [`MyError::LengthMismatch`] unless there is a row per value
+    /// of `y`, with [`MyError::NoData`] without observations and with
+    /// [`MyError::RegressionRowTooShort`] unless the rows have the same
+    /// positive number of predictors.
+    pub fn new_multiple(x: Vec<Vec<f64>>, y: Vec<f64>) -> Result<Self, MyError> {
+        if x.len() != y.len() {
+            return Err(MyError::LengthMismatch {
+                dates: x.len(),
+                values: y.len(),
+            });
+        }
+        let k = x.first().ok_or(MyError::NoData)?.len();
+        if let Some((row, values)) = x
+            .iter()
+            .enumerate()
+            .find(|(_, row)| row.is_empty() || row.len() != k)
+        {
+            return Err(MyError::RegressionRowTooShort {
+                row,
+                width: values.len(),
+                expected: k.max(1),
+            });
+        }
+
+        Ok(Self {
+            x,
+            y,
+            k,
+            fixed_sigma: None,
+            fixed_beta: None,
+            priors: Self::DEFAULT_PRIORS,
+            weights: None,
+        })
+    }
+
+    /// Returns the number of predictors `k`.
+    pub fn predictor_count(&self) -> usize {
+        self.k
+    }
+
+    /// Treat sigma as a known constant rather than a sampled parameter.
+    pub fn with_fixed_sigma(mut self, sigma: f64) -> Self {
+        assert!(sigma > 0., "Sigma must be positive");
+        self.fixed_sigma = Some(sigma);
+        self
+    }
+
+    /// Treat beta as a known constant rather than a sampled parameter - e.g. to
+    /// compare the fit under a hypothesized slope with the fit of the free
+    /// model.
+    ///
+    /// The position is then `[alpha, sigma]` (or `[alpha]` with a fixed sigma
+    /// too). Fails with [`MyError::InvalidFixedBeta`] if `beta` is not finite
+    /// and with [`MyError::FixedBetaNeedsOnePredictor`] with several
+    /// predictors.
+    pub fn with_fixed_beta(mut self, beta: f64) -> Result<Self, MyError> {
+        if !beta.is_finite() {
+            return Err(MyError::InvalidFixedBeta(beta));
+        }
+        if self.k != 1 {
+            return Err(MyError::FixedBetaNeedsOnePredictor(self.k));
+        }
+        self.fixed_beta = Some(beta);
+        Ok(self)
+    }
+
+    /// Use the given priors for alpha, beta (of the 1st predictor) and sigma -
+    /// the priors of the fixed parameters are ignored.
+    ///
+    /// E.g. a Laplace prior on the slope shrinks irrelevant slopes towards 0.
+    pub fn with_priors(mut self, priors: [PriorSpec; 3]) -> Self {
+        priors.iter().for_each(PriorSpec::validate);
+        self.priors = priors;
+        self
+    }
+
+    /// Use the given prior for alpha - the intercept at the reference of `x`.
+    pub fn with_alpha_prior(mut self, prior: PriorSpec) -> Self {
+        prior.validate();
+        self.priors[0] = prior;
+        self
+    }
+
+    /// Use the given prior for beta - the slope of the 1st predictor with
+    /// several predictors - ignored with a fixed beta.
+    ///
+    /// The priors are on the scale of the model: a prior on the slope per unit
+    /// of an unscaled predictor is to be scaled along the predictor.
+    pub fn with_beta_prior(mut self, prior: PriorSpec) -> Self {
+        prior.validate();
+        self.priors[1] = prior;
+        self
+    }
+
+    /// Use a prior of the given family for sigma - of scale `scale`, or by
+    /// default the standard deviation of the residuals of the ordinary least
+    /// squares fit (see [`Regression::residual_sd`]).
+    ///
+    /// The residual spread is the scale of the noise suggested by the data: the
+    /// prior then puts most of its mass below a few times this spread whatever
+    /// the units of the response - rather than requiring a guess.
+    pub fn with_sigma_prior(mut self, prior: ScalePrior, scale: Option<f64>) -> Self {
+        let scale = scale.unwrap_or_else(|| self.residual_sd());
+        let prior = prior.with_scale(scale);
+        prior.validate();
+        self.priors[2] = prior;
+        self
+    }
+
+    /// Weight each observation: its variance becomes `sigma² / weight`.
+    ///
+    /// For aggregated data, the weight is the number of values averaged in the
+    /// observation (e.g. a COUNT column). Panics unless the weights are
+    /// positive: the COUNT column of the input is checked first.
+    pub fn with_weights(mut self, weights: Vec<f64>) -> Self {
+        assert_eq!(weights.len(), self.x.len(), "Dimension mismatch");
+        assert!(weights.iter().all(|w| *w > 0.), "Weights must be positive");
+        self.weights = Some(weights);
+        self
+    }
+
+    /// Down-weight the observations whose standardized residual at `position`
+    /// exceeds `threshold` in absolute value - a simple robust regression.
+    ///
+    /// The weight of such an observation is scaled by `(threshold / z)²`: its
+    /// standardized residual `z` becomes `threshold`. Returns the reweighted
+    /// model and the (index, standardized residual) of the down-weighted
+    /// observations.
+    pub fn downweight_outliers(
+        mut self,
+        position: &[f64],
+        threshold: f64,
+    ) -> (Self, Vec<(usize, f64)>) {
+        let (alpha, betas, sigma) = self.unpack(position);
+
+        let mut weights = (0..self.x.len())
+            .map(|i| self.weight(i))
+            .collect::<Vec<_>>();
+        let mut outliers = vec![];
+        for (i, (x, y)) in self.x.iter().zip(self.y.iter()).enumerate() {
+            let z = (y - alpha - dot(x, betas)) * weights[i].sqrt() / sigma;
+            if z.abs() > threshold {
+                weights[i] *= (threshold / z).powi(2);
+                outliers.push((i, z));
+            }
+        }
+
+        self.weights = Some(weights);
+        (self, outliers)
+    }
+
+    /// Returns the index of sigma in a position - after the sampled
+    /// coefficients.
+    fn sigma_idx(&self) -> usize {
+        if self.fixed_beta.is_some() {
+            1
+        } else {
+            1 + self.k
+        }
+    }
+
+    /// Returns alpha, the slopes and sigma at a position - the fixed ones
+    /// included.
+    fn unpack<'a>(&'a self, position: &'a [f64]) -> (f64, &'a [f64], f64) {
+        let alpha = position[0];
+        let betas = match &self.fixed_beta {
+            Some(beta) => std::slice::from_ref(beta),
+            None => &position[1..=self.k],
+        };
+        let sigma = self
+            .fixed_sigma
+            .unwrap_or_else(|| position[self.sigma_idx()]);
+        (alpha, betas, sigma)
+    }
+
+    /// Returns the prior of the slope of the predictor `j` (from 0) - the prior
+    /// of beta for the 1st one and the default one for the others: they are
+    /// on the scale of their own predictor.
+    fn slope_prior(&self, j: usize) -> PriorSpec {
+        if j == 0 {
+            self.priors[1]
+        } else {
+            Self::DEFAULT_PRIORS[1]
+        }
+    }
+
+    /// Returns the priors of the sampled parameters - in the order of the
+    /// position.
+    fn sampled_priors(&self) -> Vec<PriorSpec> {
+        let mut priors = vec![self.priors[0]];
+        if self.fixed_beta.is_none() {
+            priors.extend((0..self.k).map(|j| self.slope_prior(j)));
+        }
+        if self.fixed_sigma.is_none() {
+            priors.push(self.priors[2]);
+        }
+        priors
+    }
+
+    /// Returns the intercept and the slopes of the ordinary least squares fit -
+    /// the least squares intercept under the fixed slope if any.
+    ///
+    /// The slopes are 0 if the predictors are collinear (or constant).
+    fn ols(&self) -> (f64, Vec<f64>) {
+        let x_means = (0..self.k)
+            .map(|j| mean(&self.x.iter().map(|x| x[j]).collect::<Vec<_>>()))
+            .collect::<Vec<_>>();
+        let y_mean = mean(&self.y);
+
+        let betas = match self.fixed_beta {
+            Some(beta) => vec![beta],
+            None => {
+                // the normal equations of the centered predictors
+                let cov = |j: usize, l: usize| {
+                    kahan_sum(
+                        self.x
+                            .iter()
+                            .map(|x| (x[j] - x_means[j]) * (x[l] - x_means[l])),
+                    )
+                };
+                let xx = (0..self.k)
+                    .map(|j| (0..self.k).map(|l| cov(j, l)).collect())
+                    .collect();
+                let xy = (0..self.k)
+                    .map(|j| {
+                        kahan_sum(
+                            self.x
+                                .iter()
+                                .zip(self.y.iter())
+                                .map(|(x, y)| (x[j] - x_means[j]) * (y - y_mean)),
+                        )
+                    })
+                    .collect();
+                solve(xx, xy).unwrap_or_else(|| vec![0.; self.k])
+            }
+        };
+        let alpha = y_mean - dot(&x_means, &betas);
+
+        (alpha, betas)
+    }
+
+    /// Returns the standard deviation of the residuals of the ordinary least
+    /// squares fit - 1 for a perfect fit, to stay a valid scale.
+    pub fn residual_sd(&self) -> f64 {
+        let (alpha, betas) = self.ols();
+
+        let residual_var = self
+            .x
+            .iter()
+            .zip(self.y.iter())
+            .map(|(x, y)| (y - alpha - dot(x, &betas)).powi(2))
+            .sum::<f64>()
+            / self.x.len() as f64;
+
+        let sigma = residual_var.sqrt();
+        if sigma > 0. {
+            sigma
+        } else {
+            1.
+        }
+    }
+
+    /// Returns the weight of the i-th observation.
+    fn weight(&self, i: usize) -> f64 {
+        self.weights.as_ref().map_or(1., |weights| weights[i])
+    }
+
+    /// Normalized log-likelihood of the observations at a given position.
+    ///
+    /// Unlike [`CpuLogpFunc::logp`], this includes all the constants so it can
+    /// be compared across models.
+    pub fn log_likelihood(&self, position: &[f64]) -> f64 {
+        let (alpha, betas, sigma) = self.unpack(position);
+
+        kahan_sum(
+            self.x
+                .iter()
+                .zip(self.y.iter())
+                .enumerate()
+                .map(|(i, (x, y))| {
+                    log_pdf_normal(*y, alpha + dot(x, betas), sigma / self.weight(i).sqrt())
+                }),
+        )
     }
 }
 
-fn log_pdf_normal_propto(diff: f64, log_sigma: f64, var_inv: f64) -> f64 {
-    let norm = -log_sigma;
-    let b = -0.5 * diff * diff * var_inv;
-    norm + b
+/// Returns the sum of the products of the predictors `x` and the slopes.
+fn dot(x: &[f64], betas: &[f64]) -> f64 {
+    x.iter().zip(betas.iter()).map(|(x, beta)| x * beta).sum()
+}
+
+/// Solves `a x = b` by Gaussian elimination with partial pivoting - `None` if
+/// `a` is (nearly) singular.
+fn solve(mut a: Vec<Vec<f64>>, mut b: Vec<f64>) -> Option<Vec<f64>> {
+    let n = b.len();
+    let scale = a.iter().flatten().fold(0., |max: f64, a| max.max(a.abs()));
+
+    for col in 0..n {
+        let pivot = (col..n).max_by(|i, j| a[*i][col].abs().total_cmp(&a[*j][col].abs()))?;
+        if a[pivot][col].abs() <= 1e-12 * scale {
+            return None;
+        }
+        a.swap(col, pivot);
+        b.swap(col, pivot);
+
+        let pivot_row = a[col].clone();
+        for row in col + 1..n {
+            let factor = a[row][col] / pivot_row[col];
+            for (a, pivot) in a[row][col..].iter_mut().zip(&pivot_row[col..]) {
+                *a -= factor * pivot;
+            }
+            b[row] -= factor * b[col];
+        }
+    }
+
+    let mut x = vec![0.; n];
+    for row in (0..n).rev() {
+        let sum = (row + 1..n).map(|c| a[row][c] * x[c]).sum::<f64>();
+        x[row] = (b[row] - sum) / a[row][row];
+    }
+    Some(x)
 }
 
 impl CpuLogpFunc for Regression {
     type Err = RegressionError;
 
     fn logp(&mut self, position: &[f64], grad: &mut [f64]) -> Result<f64, Self::Err> {
-        // positions = alpha, beta, sigma
+        // positions = alpha, beta (beta_1 to beta_k), sigma - without the fixed
+        // ones
 
         // alpha: intercept
         // beta: slope
@@ -69,8 +507,8 @@ impl CpuLogpFunc for Regression {
         // denominator of Bayes' rule) as it is constant. Now since we are
         // dealing with the log density, we can simply add the log likelihood and the
         // log priors. The likelihood is a normal distribution with mean alpha +
-        // beta * x and standard deviation sigma. And finally: we are using a
-        // flat prior for sigma, so we can ignore it.
+        // beta * x and standard deviation sigma. The priors are given by
+        // `self.priors`.
 
         // For the gradient, we need to compute the partial derivatives of the log
         // density with respect to the parameters: alpha, beta, sigma.
@@ -79,60 +517,139 @@ impl CpuLogpFunc for Regression {
         const BETA: usize = 1;
         const SIGMA: usize = 2;
 
-        if position[SIGMA] <= 0.0 {
+        let (alpha, betas, sigma) = self.unpack(position);
+
+        if sigma <= 0.0 {
             return Err(RegressionError::NegativeSigma);
         }
 
-        let alpha = position[ALPHA];
-        let beta = position[BETA];
-        let sigma = position[SIGMA];
-
-        let logp_alpha = log_pdf_normal_propto(alpha, 10f64.ln(), 0.01);
-        let logp_beta = log_pdf_normal_propto(beta, 10f64.ln(), 0.01);
-        let logp_sigma = 0.; // flat prior
+        let (logp_alpha, d_logp_alpha) = self.priors[ALPHA].log_density(alpha);
+        // the prior of each slope
+        let beta_priors = match self.fixed_beta {
+            Some(_) => vec![(0., 0.)],
+            None => betas
+                .iter()
+                .enumerate()
+                .map(|(j, beta)| self.slope_prior(j).log_density(*beta))
+                .collect(),
+        };
+        let logp_beta = beta_priors.iter().map(|(logp, _)| logp).sum::<f64>();
+        let (logp_sigma, d_logp_sigma) = match self.fixed_sigma {
+            Some(_) => (0., 0.),
+            None => self.priors[SIGMA].log_density(sigma),
+        };
 
-        let mut d_logp_d_alpha = -alpha / 100.;
-        let mut d_logp_d_beta = -beta / 100.;
-        let mut d_logp_d_sigma = 0.;
+        // compensated sums: long records add up many small terms
+        let mut d_logp_d_alpha = KahanSum::new(d_logp_alpha);
+        let mut d_logp_d_beta = beta_priors
+            .iter()
+            .map(|(_, d_logp_beta)| KahanSum::new(*d_logp_beta))
+            .collect::<Vec<_>>();
+        let mut d_logp_d_sigma = KahanSum::new(d_logp_sigma);
 
-        let mut logp_y = 0.;
+        let mut logp_y = KahanSum::default();
 
         let sigma_inv = sigma.recip();
         let var_inv = (sigma * sigma).recip();
         let var_sigma_inv = var_inv * sigma_inv;
         let log_sigma = sigma.ln();
-        for (x, y) in self.x.iter().zip(self.y.iter()) {
-            let mu_ = alpha + beta * x;
+        for (i, (x, y)) in self.x.iter().zip(self.y.iter()).enumerate() {
+            let mu_ = alpha + dot(x, betas);
             let diff = y - mu_;
 
-            logp_y += log_pdf_normal_propto(diff, log_sigma, var_inv);
+            // the variance of this observation is sigma² / w
+            let w = self.weight(i);
+
+            logp_y.add(log_pdf_normal_propto(
+                diff,
+                log_sigma - 0.5 * w.ln(),
+                w * var_inv,
+            ));
 
-            d_logp_d_alpha += diff * var_inv;
-            d_logp_d_beta += diff * x * var_inv;
-            d_logp_d_sigma += diff * diff * var_sigma_inv - sigma_inv;
+            d_logp_d_alpha.add(w * diff * var_inv);
+            for (d_logp_d_beta, x) in d_logp_d_beta.iter_mut().zip(x.iter()) {
+                d_logp_d_beta.add(w * diff * x * var_inv);
+            }
+            d_logp_d_sigma.add(w * diff * diff * var_sigma_inv - sigma_inv);
         }
 
-        let logp = logp_y + logp_alpha + logp_beta + logp_sigma;
+        let logp = logp_y.value() + logp_alpha + logp_beta + logp_sigma;
 
-        grad[ALPHA] = d_logp_d_alpha;
-        grad[BETA] = d_logp_d_beta;
-        grad[SIGMA] = d_logp_d_sigma;
+        grad[ALPHA] = d_logp_d_alpha.value();
+        if self.fixed_beta.is_none() {
+            for (j, d_logp_d_beta) in d_logp_d_beta.iter().enumerate() {
+                grad[BETA + j] = d_logp_d_beta.value();
+            }
+        }
+        if self.fixed_sigma.is_none() {
+            grad[self.sigma_idx()] = d_logp_d_sigma.value();
+        }
 
         Ok(logp)
     }
 
     fn dim(&self) -> usize {
-        3
+        1 + if self.fixed_beta.is_none() { self.k } else { 0 } + self.fixed_sigma.is_none() as usize
     }
 }
 
 impl Model for Regression {
     fn parameters(&self) -> Vec<String> {
-        vec![
-            String::from("alpha"),
-            String::from("beta"),
-            String::from("sigma"),
-        ]
+        let mut parameters = vec![String::from("alpha")];
+        if self.fixed_beta.is_some() {
+            // not sampled
+        } else if self.k == 1 {
+            parameters.push(String::from("beta"));
+        } else {
+            parameters.extend((1..=self.k).map(|j| format!("beta_{}", j)));
+        }
+        if self.fixed_sigma.is_none() {
+            parameters.push(String::from("sigma"));
+        }
+        parameters
+    }
+
+    fn supports(&self) -> Vec<Support> {
+        let mut supports = vec![Support::Real];
+        if self.fixed_beta.is_none() {
+            supports.extend(vec![Support::Real; self.k]);
+        }
+        if self.fixed_sigma.is_none() {
+            supports.push(Support::Positive);
+        }
+        supports
+    }
+
+    /// The ordinary least squares fit - and the standard deviation of its
+    /// residuals for sigma.
+    fn suggested_initial(&self) -> Vec<f64> {
+        let (alpha, betas) = self.ols();
+
+        let mut initial = vec![alpha];
+        if self.fixed_beta.is_none() {
+            initial.extend(betas);
+        }
+
+        if self.fixed_sigma.is_none() {
+            // sigma must be positive - even for a perfect fit
+            initial.push(self.residual_sd());
+        }
+
+        initial
+    }
+
+    /// Defined when the priors of the sampled parameters are proper - not with
+    /// the default flat prior on sigma.
+    fn log_joint(&self, position: &[f64]) -> Option<f64> {
+        let log_prior = self
+            .sampled_priors()
+            .iter()
+            .zip(self.supports())
+            .zip(position)
+            .map(|((prior, support), x)| prior.log_pdf(*x, support))
+            .sum::<Option<f64>>()?;
+
+        Some(log_prior + self.log_likelihood(position))
     }
 }
 
@@ -144,9 +661,427 @@ mod tests {
     use rand_distr::Distribution;
 
     use crate::chain;
+    use crate::numerics::log_pdf_laplace_propto;
+    use crate::MyError;
 
     use super::*;
 
+    #[test]
+    fn test_downweight_outliers() {
+        let x = vec![0., 1., 2., 3.];
+        let y = vec![0., 1., 8., 3.];
+        let model = Regression::new(x, y).with_weights(vec![1., 1., 4., 1.]);
+
+        let (model, outliers) = model.downweight_outliers(&[0., 1., 1.], 3.);
+        // (8 - 2) * sqrt(4) / 1
+        assert_eq!(outliers, vec![(2, 12.)]);
+        assert_eq!(model.weight(2), 4. / 16.);
+        assert_eq!(model.weight(0), 1.);
+
+        // the down-weighted residual is at the threshold
+        let (_, outliers) = model.downweight_outliers(&[0., 1., 1.], 3.);
+        assert!(outliers.is_empty());
+    }
+
+    #[test]
+    fn test_alpha_beta_priors() {
+        let x = vec![-2., -1., 0., 1., 2.];
+        let y = vec![0.5, 1.8, 2.1, 3.2, 3.9];
+        let settings = chain::RunSettings {
+            seed: 3,
+            chain_count: 2,
+            tuning: 300,
+            samples: 300,
+            ..Default::default()
+        };
+        let posterior_mean = |model: Regression, i: usize| {
+            let chains = chain::Chains::run(model, &settings).unwrap();
+            mean(&chains.traces(i).concat())
+        };
+
+        // the data alone: a slope of about 0.83
+        let model = Regression::new(x.clone(), y.clone()).with_fixed_sigma(0.3);
+        let beta = posterior_mean(model.clone(), 1);
+        assert!((beta - 0.83).abs() < 0.1, "beta = {}", beta);
+
+        // a tight prior dominates five observations
+        let tight = model
+            .clone()
+            .with_alpha_prior(PriorSpec::Normal {
+                mean: 10.,
+                sd: 0.01,
+            })
+            .with_beta_prior(PriorSpec::Normal {
+                mean: -1.,
+                sd: 0.01,
+            });
+        let alpha = posterior_mean(tight.clone(), 0);
+        let beta = posterior_mean(tight, 1);
+        assert!((alpha - 10.).abs() < 0.05, "alpha = {}", alpha);
+        assert!((beta + 1.).abs() < 0.05, "beta = {}", beta);
+    }
+
+    #[test]
+    fn test_log_marginal_likelihood() {
+        let x = vec![-2., -1., 0., 1., 2.];
+        let y = vec![0.5, 1.8, 2.1, 3.2, 3.9];
+        let sigma = 1.;
+        let model = Regression::new(x.clone(), y.clone()).with_fixed_sigma(sigma);
+
+        // conjugate: y ~ N(0, sigma² I + X S0 Xᵀ) with S0 = 100 I - through
+        // the 2x2 posterior precision A = S0⁻¹ + XᵀX / sigma²
+        let n = x.len() as f64;
+        let prior_var = 100.;
+        let sx = x.iter().sum::<f64>();
+        let sxx = x.iter().map(|x| x * x).sum::<f64>();
+        let a = [
+            [1. / prior_var + n / sigma.powi(2), sx / sigma.powi(2)],
+            [sx / sigma.powi(2), 1. / prior_var + sxx / sigma.powi(2)],
+        ];
+        let det_a = a[0][0] * a[1][1] - a[0][1] * a[1][0];
+        let b = [
+            y.iter().sum::<f64>() / sigma.powi(2),
+            x.iter().zip(&y).map(|(x, y)| x * y).sum::<f64>() / sigma.powi(2),
+        ];
+        let b_a_inv_b =
+            (a[1][1] * b[0] * b[0] - 2. * a[0][1] * b[0] * b[1] + a[0][0] * b[1] * b[1]) / det_a;
+        let yy = y.iter().map(|y| y * y).sum::<f64>();
+        let exact = -0.5 * n * (2. * std::f64::consts::PI).ln()
+            - 0.5 * (n * sigma.powi(2).ln() + 2. * prior_var.ln() + det_a.ln())
+            - 0.5 * (yy / sigma.powi(2) - b_a_inv_b);
+
+        let settings = chain::RunSettings {
+            seed: 1,
+            chain_count: 2,
+            tuning: 500,
+            samples: 1000,
+            ..Default::default()
+        };
+        let chains = chain::Chains::run(model.clone(), &settings).unwrap();
+        let estimate = chains
+            .log_marginal_likelihood(|position| model.log_joint(position), 3)
+            .unwrap();
+        assert!((estimate - exact).abs() < 0.05, "{} vs {}", estimate, exact);
+
+        // the default prior of sigma is flat
+        let model = Regression::new(x, y);
+        let chains = chain::Chains::run(model.clone(), &settings).unwrap();
+        assert!(matches!(
+            chains.log_marginal_likelihood(|position| model.log_joint(position), 3),
+            Err(MyError::ImproperPrior)
+        ));
+    }
+
+    #[test]
+    fn test_explicit_seeds() {
+        let x = (0..20).map(|i| i as f64).collect::<Vec<_>>();
+        let y = x.iter().map(|x| 1. + 0.5 * x).collect::<Vec<_>>();
+        let model = Regression::new(x, y);
+
+        let settings = chain::RunSettings {
+            seed: 7,
+            chain_count: 2,
+            tuning: 50,
+            samples: 20,
+            initial_position: Some(vec![1., 0., 1.]),
+            ..Default::default()
+        };
+        let default_seeds = chain::Chains::run(model.clone(), &settings).unwrap();
+
+        // the default is seed + chain_id
+        let explicit = chain::RunSettings {
+            seeds: Some(vec![7, 8]),
+            ..settings.clone()
+        };
+        assert_eq!(
+            chain::Chains::run(model.clone(), &explicit)
+                .unwrap()
+                .traces(0),
+            default_seeds.traces(0)
+        );
+
+        // each chain only depends on its own seed
+        let swapped = chain::RunSettings {
+            seeds: Some(vec![8, 1234]),
+            ..settings
+        };
+        assert_eq!(
+            chain::Chains::run(model, &swapped).unwrap().traces(0)[0],
+            default_seeds.traces(0)[1]
+        );
+    }
+
+    #[test]
+    fn test_streaming_run() {
+        let x = (0..20).map(|i| i as f64).collect::<Vec<_>>();
+        let y = x.iter().map(|x| 1. + 0.5 * x).collect::<Vec<_>>();
+        let model = Regression::new(x, y);
+
+        let settings = chain::RunSettings {
+            seed: 7,
+            chain_count: 2,
+            tuning: 50,
+            samples: 20,
+            initial_position: Some(vec![1., 0., 1.]),
+            ..Default::default()
+        };
+        let chains = chain::Chains::run(model.clone(), &settings).unwrap();
+
+        let mut run = chain::StreamingRun::start(model, &settings).unwrap();
+        // still warming up
+        assert!(!run.advance(30, None).unwrap());
+        assert_eq!(run.chains().samples(), 0);
+        assert!(!run.advance(30, None).unwrap());
+        assert_eq!(run.chains().samples(), 10);
+        // the last step stops at the end of the run
+        assert!(run.advance(30, None).unwrap());
+
+        // the same draws as a run in one go
+        let streamed = run.into_chains();
+        for i in 0..3 {
+            assert_eq!(streamed.traces(i), chains.traces(i));
+        }
+    }
+
+    #[test]
+    fn test_bad_initial_position() {
+        let x = (0..20).map(|i| i as f64).collect::<Vec<_>>();
+        let y = x.iter().map(|x| 1. + 0.5 * x).collect::<Vec<_>>();
+        let model = Regression::new(x, y);
+
+        let settings = chain::RunSettings {
+            chain_count: 2,
+            tuning: 10,
+            samples: 10,
+            initial_position: Some(vec![1., 0., 0.]),
+            ..Default::default()
+        };
+        match chain::Chains::run(model.clone(), &settings) {
+            Err(MyError::BadInitialPosition {
+                parameter: Some(parameter),
+                position,
+            }) => {
+                assert_eq!(parameter, "sigma");
+                assert_eq!(position, vec![1., 0., 0.]);
+            }
+            _ => panic!("sigma = 0 is not a valid initial position"),
+        }
+
+        let settings = chain::RunSettings {
+            initial_position: Some(vec![f64::NAN, 0., 1.]),
+            ..settings
+        };
+        assert!(matches!(
+            chain::StreamingRun::start(model, &settings),
+            Err(MyError::BadInitialPosition {
+                parameter: Some(parameter),
+                ..
+            }) if parameter == "alpha"
+        ));
+    }
+
+    #[test]
+    fn test_wrong_seed_count() {
+        let model = Regression::new(vec![0., 1.], vec![0., 1.]);
+
+        let settings = chain::RunSettings {
+            seeds: Some(vec![1, 2, 3]),
+            chain_count: 2,
+            initial_position: Some(vec![0., 0., 1.]),
+            ..Default::default()
+        };
+        assert!(matches!(
+            chain::Chains::run(model, &settings),
+            Err(MyError::WrongSeedCount {
+                expected: 2,
+                actual: 3
+            })
+        ));
+    }
+
+    #[test]
+    fn test_too_few_samples() {
+        let model = Regression::new(vec![0., 1.], vec![0., 1.]);
+
+        for samples in 0..4 {
+            let settings = chain::RunSettings {
+                tuning: 10,
+                samples,
+                initial_position: Some(vec![0., 0., 1.]),
+                ..Default::default()
+            };
+            assert!(matches!(
+                chain::Chains::run(model.clone(), &settings),
+                Err(MyError::NotEnoughDraws { draws, needed: 4 }) if draws == samples as usize
+            ));
+        }
+    }
+
+    #[test]
+    fn test_too_many_draws() {
+        // noisy: the gradient is not zero at the suggested initial position
+        let model = Regression::new(vec![0., 1., 2., 3.], vec![0.1, 0.9, 2.2, 2.8]);
+
+        // 4 chains x 10^9 draws x 3 parameters x 8 bytes
+        let settings = chain::RunSettings {
+            chain_count: 4,
+            tuning: 10,
+            samples: 1_000_000_000,
+            ..Default::default()
+        };
+        assert!(matches!(
+            chain::Chains::run(model.clone(), &settings),
+            Err(MyError::TooManyDraws { bytes, budget })
+                if bytes == 96_000_000_000 && budget == chain::DEFAULT_MAX_TRACE_BYTES
+        ));
+
+        let settings = chain::RunSettings {
+            chain_count: 2,
+            tuning: 10,
+            samples: 10,
+            max_trace_bytes: Some(2 * 10 * 3 * 8 - 1),
+            ..Default::default()
+        };
+        assert!(matches!(
+            chain::Chains::run(model.clone(), &settings),
+            Err(MyError::TooManyDraws { bytes, budget }) if bytes == 2 * 10 * 3 * 8 && budget == bytes - 1
+        ));
+
+        let settings = chain::RunSettings {
+            max_trace_bytes: Some(2 * 10 * 3 * 8),
+            ..settings
+        };
+        chain::Chains::run(model, &settings).unwrap();
+    }
+
+    #[test]
+    fn test_run_with_retries() {
+        let x = (0..20).map(|i| i as f64).collect::<Vec<_>>();
+        let y = x.iter().map(|x| 1. + 0.5 * x).collect::<Vec<_>>();
+        let model = Regression::new(x, y);
+
+        let settings = chain::RunSettings {
+            seed: 7,
+            chain_count: 2,
+            tuning: 50,
+            samples: 20,
+            ..Default::default()
+        };
+
+        // within the divergence budget - a single run
+        let (_, note) = chain::Chains::run_with_retries(model.clone(), &settings, 3, 1.).unwrap();
+        assert!(!note.contains("attempt"));
+
+        // a negative budget always retries - up to the max number of attempts
+        let (chains, note) = chain::Chains::run_with_retries(model, &settings, 3, -1.).unwrap();
+        assert!(note.contains("attempt 1") && note.contains("target_accept = 0.9 and 100"));
+        assert!(note.contains("attempt 2") && note.contains("target_accept = 0.95 and 200"));
+        assert!(!note.contains("attempt 3"));
+        assert_eq!(chains.samples(), 20);
+    }
+
+    #[test]
+    fn test_mcse_mean() {
+        let x = (0..50).map(|i| i as f64).collect::<Vec<_>>();
+        let y = x
+            .iter()
+            .map(|x| 1. + 0.5 * x + (x * 7.).sin())
+            .collect::<Vec<_>>();
+        let model = Regression::new(x, y);
+
+        let mcse = |samples| {
+            let settings = chain::RunSettings {
+                seed: 42,
+                chain_count: 2,
+                tuning: 500,
+                samples,
+                initial_position: Some(vec![1., 0.5, 1.]),
+                ..Default::default()
+            };
+            chain::Chains::run(model.clone(), &settings)
+                .unwrap()
+                .mcse_mean(0)
+        };
+
+        // the standard error decreases like 1/sqrt(samples)
+        let (few, many) = (mcse(200), mcse(3200));
+        assert!(many < few / 2., "mcse: {} -> {}", few, many);
+    }
+
+    #[test]
+    fn test_time_budget() {
+        let x = (0..50).map(|i| i as f64).collect::<Vec<_>>();
+        let y = x.iter().map(|x| 1. + 0.5 * x).collect::<Vec<_>>();
+        let model = Regression::new(x, y);
+
+        let settings = chain::RunSettings {
+            seed: 42,
+            chain_count: 2,
+            tuning: 100,
+            samples: 1_000_000,
+            initial_position: Some(vec![1., 0.5, 1.]),
+            max_millis: Some(100.),
+            ..Default::default()
+        };
+        let chains = chain::Chains::run(model, &settings).unwrap();
+
+        // stopped early - with the same number of draws in every chain
+        let samples = chains.samples();
+        assert!(samples > 0 && samples < 1_000_000);
+        for trace in chains.traces(0) {
+            assert_eq!(trace.len(), samples);
+        }
+    }
+
+    #[test]
+    fn test_step_sizes() {
+        let x = (0..50).map(|i| i as f64).collect::<Vec<_>>();
+        let y = x.iter().map(|x| 1. + 0.5 * x).collect::<Vec<_>>();
+
+        let settings = chain::RunSettings {
+            seed: 42,
+            chain_count: 3,
+            tuning: 200,
+            samples: 10,
+            initial_position: Some(vec![1., 0.5, 1.]),
+            ..Default::default()
+        };
+        let chains = chain::Chains::run(Regression::new(x, y), &settings).unwrap();
+
+        let step_sizes = chains.step_sizes();
+        assert_eq!(step_sizes.len(), 3);
+        assert!(step_sizes.iter().all(|s| s.is_finite() && *s > 0.));
+    }
+
+    #[test]
+    fn test_suggested_initial() {
+        let x = vec![-2., -1., 0., 1., 2.];
+        let y = vec![-1., 1., 1., 3., 3.];
+
+        // y = 1.4 + 1.0 x fits with residuals (-0.4, 0.6, -0.4, 0.6, -0.4)
+        let initial = Regression::new(x.clone(), y.clone()).suggested_initial();
+        assert!((initial[0] - 1.4).abs() < 1e-12);
+        assert!((initial[1] - 1.).abs() < 1e-12);
+        assert!((initial[2] - 0.24f64.sqrt()).abs() < 1e-12);
+
+        let fixed = Regression::new(x, y).with_fixed_sigma(1.);
+        assert_eq!(fixed.suggested_initial().len(), fixed.dim());
+    }
+
+    #[test]
+    fn test_trace_length() {
+        let x = (0..50).map(|i| i as f64).collect::<Vec<_>>();
+        let y = x.iter().map(|x| 1. + 0.5 * x).collect::<Vec<_>>();
+
+        let (tuning, samples) = (100, 37);
+        let traces = run_regression(x, y, 2, tuning, samples, 42, vec![1., 0., 1.]).unwrap();
+
+        // only the post-warmup draws are kept
+        for trace in traces.values().flatten() {
+            assert_eq!(trace.len() as u64, samples);
+        }
+    }
+
     /// Run the regression.
     fn run_regression(
         x: Vec<f64>,
@@ -157,10 +1092,17 @@ mod tests {
         seed: u64,
         initial_position: Vec<f64>,
     ) -> Result<HashMap<String, Vec<Vec<f64>>>, RegressionError> {
-        let model = Regression { x, y };
+        let model = Regression::new(x, y);
         assert_eq!(initial_position.len(), model.dim(), "Dimension mismatch");
-        let chains =
-            chain::Chains::run(seed, model, chain_count, tuning, samples, initial_position);
+        let settings = chain::RunSettings {
+            seed,
+            chain_count,
+            tuning,
+            samples,
+            initial_position: Some(initial_position),
+            ..Default::default()
+        };
+        let chains = chain::Chains::run(model, &settings).unwrap();
 
         let parameters = chains.parameters.clone();
 
@@ -213,4 +1155,374 @@ mod tests {
         println!("beta: {}", beta);
         println!("sigma: {}", sigma);
     }
+
+    #[test]
+    fn test_fixed_sigma() {
+        let x = vec![-2., -1., 0., 1., 2.];
+        let y = vec![0., 1.5, 2., 2.5, 4.];
+
+        let mut free = Regression::new(x.clone(), y.clone());
+        let mut fixed = Regression::new(x, y).with_fixed_sigma(0.5);
+
+        assert_eq!(fixed.dim(), 2);
+        assert_eq!(fixed.parameters(), vec!["alpha", "beta"]);
+
+        // same density as the free model evaluated at sigma = 0.5
+        let mut grad_free = vec![0.; 3];
+        let mut grad_fixed = vec![0.; 2];
+        let logp_free = free.logp(&[2., 0.9, 0.5], &mut grad_free).unwrap();
+        let logp_fixed = fixed.logp(&[2., 0.9], &mut grad_fixed).unwrap();
+
+        assert!((logp_free - logp_fixed).abs() < 1e-12);
+        assert_eq!(grad_free[..2], grad_fixed[..]);
+    }
+
+    #[test]
+    fn test_fixed_beta() {
+        let x = vec![-2., -1., 0., 1., 2.];
+        let y = vec![0., 1.5, 2., 2.5, 4.];
+        let priors = [
+            PriorSpec::Normal { mean: 0., sd: 10. },
+            PriorSpec::Normal { mean: 0., sd: 10. },
+            PriorSpec::HalfNormal { sd: 2. },
+        ];
+
+        let mut free = Regression::new(x.clone(), y.clone()).with_priors(priors);
+        let mut fixed = Regression::new(x.clone(), y.clone())
+            .with_priors(priors)
+            .with_fixed_beta(0.9)
+            .unwrap();
+
+        assert_eq!(fixed.dim(), 2);
+        assert_eq!(fixed.parameters(), vec!["alpha", "sigma"]);
+        assert_eq!(fixed.supports(), vec![Support::Real, Support::Positive]);
+        // the least squares intercept under the fixed slope
+        assert_eq!(fixed.suggested_initial()[0], 2.);
+
+        // same likelihood as the free model evaluated at beta = 0.9 - without
+        // the prior of beta
+        let mut grad_free = vec![0.; 3];
+        let mut grad_fixed = vec![0.; 2];
+        let logp_free = free.logp(&[2., 0.9, 0.5], &mut grad_free).unwrap();
+        let logp_fixed = fixed.logp(&[2., 0.5], &mut grad_fixed).unwrap();
+
+        let (logp_beta, _) = priors[1].log_density(0.9);
+        assert!((logp_free - logp_beta - logp_fixed).abs() < 1e-12);
+        assert_eq!(grad_fixed, vec![grad_free[0], grad_free[2]]);
+        assert_eq!(
+            fixed.log_likelihood(&[2., 0.5]),
+            free.log_likelihood(&[2., 0.9, 0.5])
+        );
+        let log_prior_beta = priors[1].log_pdf(0.9, Support::Real).unwrap();
+        assert!(
+            (free.log_joint(&[2., 0.9, 0.5]).unwrap()
+                - log_prior_beta
+                - fixed.log_joint(&[2., 0.5]).unwrap())
+            .abs()
+                < 1e-12
+        );
+
+        // with a fixed sigma too, only alpha is sampled
+        let mut both = Regression::new(x, y)
+            .with_fixed_sigma(0.5)
+            .with_fixed_beta(0.9)
+            .unwrap();
+        assert_eq!(both.dim(), 1);
+        assert_eq!(both.parameters(), vec!["alpha"]);
+        let mut grad_both = vec![0.];
+        both.logp(&[2.], &mut grad_both).unwrap();
+        assert!((grad_both[0] - grad_free[0]).abs() < 1e-12);
+
+        assert!(matches!(
+            Regression::new(vec![0., 1.], vec![0., 1.]).with_fixed_beta(f64::NAN),
+            Err(MyError::InvalidFixedBeta(beta)) if beta.is_nan()
+        ));
+        let two = Regression::new_multiple(vec![vec![0., 1.], vec![1., 0.]], vec![0., 1.]).unwrap();
+        assert!(matches!(
+            two.with_fixed_beta(0.9),
+            Err(MyError::FixedBetaNeedsOnePredictor(2))
+        ));
+    }
+
+    #[test]
+    fn test_long_record() {
+        // 50 years of daily observations of the same value: every term of the
+        // sums is the same - n times the term of one observation is the
+        // reference
+        let n = 18_262;
+        let position = [15., 0.02, 2.];
+        // without the priors
+        let flat = [PriorSpec::Flat; 3];
+        let mut one = Regression::new(vec![0.], vec![15.3]).with_priors(flat);
+        let mut long = Regression::new(vec![0.; n], vec![15.3; n]).with_priors(flat);
+
+        let mut grad_one = vec![0.; 3];
+        let mut grad_long = vec![0.; 3];
+        let logp_one = one.logp(&position, &mut grad_one).unwrap();
+        let logp_long = long.logp(&position, &mut grad_long).unwrap();
+
+        let close = |a: f64, b: f64| (a - b).abs() <= 4. * f64::EPSILON * b.abs();
+        assert!(close(logp_long, n as f64 * logp_one));
+        for (long, one) in grad_long.iter().zip(grad_one.iter()) {
+            assert!(
+                close(*long, n as f64 * one),
+                "{} vs {}",
+                long,
+                n as f64 * one
+            );
+        }
+        assert!(close(
+            long.log_likelihood(&position),
+            n as f64 * one.log_likelihood(&position)
+        ));
+    }
+
+    #[test]
+    fn test_weights() {
+        let x = vec![-2., -1., 0., 1., 2.];
+        let y = vec![0., 1.5, 2., 2.5, 4.];
+        let position = [2., 0.9, 0.5];
+
+        let mut unweighted = Regression::new(x.clone(), y.clone());
+        let mut ones = Regression::new(x.clone(), y.clone()).with_weights(vec![1.; 5]);
+
+        let mut grad_unweighted = vec![0.; 3];
+        let mut grad_ones = vec![0.; 3];
+        let logp_unweighted = unweighted.logp(&position, &mut grad_unweighted).unwrap();
+        let logp_ones = ones.logp(&position, &mut grad_ones).unwrap();
+        assert_eq!(logp_unweighted, logp_ones);
+        assert_eq!(grad_unweighted, grad_ones);
+
+        // a weight of 4 halves the standard deviation of the observation
+        let weights = vec![1., 4., 1., 1., 1.];
+        let weighted = Regression::new(x.clone(), y.clone()).with_weights(weights);
+        let expected = x
+            .iter()
+            .zip(y.iter())
+            .enumerate()
+            .map(|(i, (x, y))| {
+                let sigma = if i == 1 { 0.25 } else { 0.5 };
+                log_pdf_normal(*y, 2. + 0.9 * x, sigma)
+            })
+            .sum::<f64>();
+        assert!((weighted.log_likelihood(&position) - expected).abs() < 1e-12);
+
+        // the gradient matches finite differences
+        let mut weighted = weighted;
+        let mut grad = vec![0.; 3];
+        weighted.logp(&position, &mut grad).unwrap();
+        for i in 0..3 {
+            let h = 1e-6;
+            let (mut plus, mut minus) = (position, position);
+            plus[i] += h;
+            minus[i] -= h;
+
+            let mut g = vec![0.; 3];
+            let fd = (weighted.logp(&plus, &mut g).unwrap()
+                - weighted.logp(&minus, &mut g).unwrap())
+                / (2. * h);
+            assert!((grad[i] - fd).abs() < 1e-5 * fd.abs().max(1.), "{}", i);
+        }
+    }
+
+    #[test]
+    fn test_priors() {
+        let x = vec![-2., -1., 0., 1., 2.];
+        let y = vec![0., 1.5, 2., 2.5, 4.];
+        let position = [2., 0.9, 0.5];
+
+        let priors = [
+            PriorSpec::Normal { mean: 1., sd: 2. },
+            PriorSpec::Cauchy { scale: 1. },
+            PriorSpec::HalfNormal { sd: 1. },
+        ];
+        let mut default = Regression::new(x.clone(), y.clone());
+        let mut custom = Regression::new(x, y).with_priors(priors);
+
+        let mut grad_default = vec![0.; 3];
+        let mut grad_custom = vec![0.; 3];
+        let logp_default = default.logp(&position, &mut grad_default).unwrap();
+        let logp_custom = custom.logp(&position, &mut grad_custom).unwrap();
+
+        // only the priors change
+        let mut delta_logp = 0.;
+        for i in 0..3 {
+            let (logp, d) = priors[i].log_density(position[i]);
+            let (default_logp, default_d) = Regression::DEFAULT_PRIORS[i].log_density(position[i]);
+            delta_logp += logp - default_logp;
+
+            assert!((grad_custom[i] - grad_default[i] - (d - default_d)).abs() < 1e-12);
+        }
+        assert!((logp_custom - logp_default - delta_logp).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_centering() {
+        // most of the observations on the same date
+        let x = [2000., 2000., 2000., 2000., 2020.];
+
+        assert_eq!(Centering::default().center(&x, None).unwrap(), 2004.);
+        assert_eq!(Centering::Median.center(&x, None).unwrap(), 2000.);
+        assert_eq!(Centering::Reference.center(&x, Some(1990.)).unwrap(), 1990.);
+        assert_eq!(Centering::None.center(&x, Some(1990.)).unwrap(), 0.);
+    }
+
+    #[test]
+    fn test_centering_without_reference() {
+        assert!(matches!(
+            Centering::Reference.center(&[2000.], None),
+            Err(MyError::InvalidRunOptions(_))
+        ));
+    }
+
+    #[test]
+    fn test_centering_no_data() {
+        assert!(matches!(
+            Centering::Median.center(&[], None),
+            Err(MyError::NoData)
+        ));
+    }
+
+    #[test]
+    fn test_sigma_prior() {
+        // residuals of the least squares fit: -0.4, 0.6, -0.4, 0.6, -0.4
+        let x = vec![-2., -1., 0., 1., 2.];
+        let y = vec![-1., 1., 1., 3., 3.];
+        let model = Regression::new(x, y);
+        let spread = 0.24f64.sqrt();
+        assert!((model.residual_sd() - spread).abs() < 1e-12);
+
+        // the scale defaults to the residual spread
+        let half_normal = model.clone().with_sigma_prior(ScalePrior::HalfNormal, None);
+        assert_eq!(
+            half_normal.priors[2],
+            PriorSpec::HalfNormal {
+                sd: model.residual_sd()
+            }
+        );
+        let half_cauchy = model
+            .clone()
+            .with_sigma_prior(ScalePrior::HalfCauchy, Some(2.));
+        assert_eq!(half_cauchy.priors[2], PriorSpec::Cauchy { scale: 2. });
+        let flat = model.with_sigma_prior(ScalePrior::Flat, None);
+        assert_eq!(flat.priors, Regression::DEFAULT_PRIORS);
+
+        // a perfect fit
+        assert_eq!(
+            Regression::new(vec![0., 1.], vec![1., 2.]).residual_sd(),
+            1.
+        );
+    }
+
+    #[test]
+    fn test_laplace_slope_prior() {
+        let x = vec![-2., -1., 0., 1., 2.];
+        let y = vec![0., 1.5, 2., 2.5, 4.];
+
+        let mut normal = Regression::new(x.clone(), y.clone());
+        let mut priors = Regression::DEFAULT_PRIORS;
+        priors[1] = PriorSpec::Laplace { b: 0.5 };
+        let mut laplace = Regression::new(x, y).with_priors(priors);
+
+        let position = [2., 0.9, 0.5];
+        let mut grad_normal = vec![0.; 3];
+        let mut grad_laplace = vec![0.; 3];
+        let logp_normal = normal.logp(&position, &mut grad_normal).unwrap();
+        let logp_laplace = laplace.logp(&position, &mut grad_laplace).unwrap();
+
+        // only the prior on the slope changes
+        let prior_normal = log_pdf_normal_propto(0.9, 10f64.ln(), 0.01);
+        let prior_laplace = log_pdf_laplace_propto(0.9, 0.5);
+        assert!((logp_laplace - logp_normal - (prior_laplace - prior_normal)).abs() < 1e-12);
+
+        assert_eq!(grad_normal[0], grad_laplace[0]);
+        assert!((grad_laplace[1] - (grad_normal[1] + 0.009 - 1. / 0.5)).abs() < 1e-5);
+        assert_eq!(grad_normal[2], grad_laplace[2]);
+    }
+
+    #[test]
+    fn test_multiple_predictors() {
+        let x = (0..8)
+            .map(|i| vec![i as f64 - 3.5, ((i * 3) % 5) as f64 - 2.])
+            .collect::<Vec<_>>();
+        let y = x
+            .iter()
+            .map(|x| 1. + 2. * x[0] - 0.5 * x[1])
+            .collect::<Vec<_>>();
+
+        let model = Regression::new_multiple(x.clone(), y.clone()).unwrap();
+        assert_eq!(model.predictor_count(), 2);
+        assert_eq!(model.dim(), 4);
+        assert_eq!(
+            model.parameters(),
+            vec!["alpha", "beta_1", "beta_2", "sigma"]
+        );
+
+        // least squares recovers a noise-free fit
+        let initial = model.suggested_initial();
+        for (estimate, expected) in initial.iter().zip([1., 2., -0.5]) {
+            assert!((estimate - expected).abs() < 1e-9, "{:?}", initial);
+        }
+        assert!(initial[3] > 0.);
+
+        // the gradient matches finite differences
+        let mut model = model.with_weights(vec![1., 2., 1., 1., 0.5, 1., 1., 3.]);
+        let position = [0.8, 1.7, -0.2, 0.6];
+        let mut grad = vec![0.; 4];
+        model.logp(&position, &mut grad).unwrap();
+        for i in 0..4 {
+            let h = 1e-6;
+            let (mut plus, mut minus) = (position, position);
+            plus[i] += h;
+            minus[i] -= h;
+
+            let mut g = vec![0.; 4];
+            let fd = (model.logp(&plus, &mut g).unwrap() - model.logp(&minus, &mut g).unwrap())
+                / (2. * h);
+            assert!((grad[i] - fd).abs() < 1e-5 * fd.abs().max(1.), "{}", i);
+        }
+
+        // the prior of beta is the one of the 1st slope only
+        let mut shrunk = model.clone().with_beta_prior(PriorSpec::Laplace { b: 0.1 });
+        let mut shrunk_grad = vec![0.; 4];
+        shrunk.logp(&position, &mut shrunk_grad).unwrap();
+        assert_ne!(shrunk_grad[1], grad[1]);
+        assert_eq!(shrunk_grad[2], grad[2]);
+
+        // a single predictor keeps the names of the simple regression
+        let single =
+            Regression::new_multiple(x.iter().map(|x| vec![x[0]]).collect(), y.clone()).unwrap();
+        assert_eq!(single.parameters(), vec!["alpha", "beta", "sigma"]);
+
+        // a row per value, of the same positive width
+        assert!(matches!(
+            Regression::new_multiple(x[1..].to_vec(), y.clone()),
+            Err(MyError::LengthMismatch {
+                dates: 7,
+                values: 8
+            })
+        ));
+        assert!(matches!(
+            Regression::new_multiple(vec![], vec![]),
+            Err(MyError::NoData)
+        ));
+        let mut ragged = x.clone();
+        ragged[3].pop();
+        assert!(matches!(
+            Regression::new_multiple(ragged, y.clone()),
+            Err(MyError::RegressionRowTooShort {
+                row: 3,
+                width: 1,
+                expected: 2
+            })
+        ));
+        assert!(matches!(
+            Regression::new_multiple(vec![vec![]; 8], y),
+            Err(MyError::RegressionRowTooShort {
+                row: 0,
+                width: 0,
+                expected: 1
+            })
+        ));
+    }
 }
@@ -0,0 +1,177 @@
+//! Point estimates by optimization of the log density
+use nuts_rs::CpuLogpFunc;
+use wasm_bindgen::prelude::*;
+
+/// Maximum a posteriori estimate
+#[wasm_bindgen(getter_with_clone)]
+#[derive(Clone, Debug)]
+pub struct MapEstimate {
+    /// Position of the maximum
+    pub position: Vec<f64>,
+    /// Unnormalized log density at the maximum
+    pub logp: f64,
+    /// Number of iterations run
+    pub iterations: u32,
+    /// Whether the gradient vanished - otherwise the iterations ran out
+    pub converged: bool,
+}
+
+/// The gradient is considered zero below this norm.
+const GRADIENT_TOLERANCE: f64 = 1e-6;
+
+/// Relative changes below this are lost in the rounding errors of the log density.
+const RELATIVE_TOLERANCE: f64 = 1e-12;
+
+/// Number of past log densities the line search compares to.
+const LINE_SEARCH_MEMORY: usize = 10;
+
+/// Maximize the log density by gradient ascent from `initial`.
+///
+/// The steps follow the Barzilai-Borwein rule - which copes with parameters on
+/// very different scales - with a non-monotone backtracking line search
+/// (Grippo et al.) so that the log density keeps increasing over a few
+/// iterations. Positions where the log density fails (e.g. a negative sigma)
+/// are rejected by the line search.
+///
+/// The optimization has converged when the gradient vanishes or when the steps
+/// no longer change the position or the log density beyond rounding errors.
+///
+/// Fails if the log density cannot be evaluated at `initial`.
+pub(crate) fn maximize<F: CpuLogpFunc>(
+    logp_func: &mut F,
+    initial: &[f64],
+    max_iterations: u32,
+) -> Result<MapEstimate, F::Err> {
+    let dim = logp_func.dim();
+    assert_eq!(dim, initial.len(), "Dimension mismatch");
+
+    let mut evaluate = |position: &[f64]| {
+        let mut grad = vec![0.; dim];
+        let logp = logp_func.logp(position, &mut grad)?;
+        Ok((logp, grad))
+    };
+
+    let dot = |a: &[f64], b: &[f64]| a.iter().zip(b).map(|(a, b)| a * b).sum::<f64>();
+
+    let mut position = initial.to_vec();
+    let (mut logp, mut grad) = evaluate(&position)?;
+    let mut history = vec![logp];
+    let mut step = 1e-3;
+
+    let estimate = |position, logp, iterations, converged| MapEstimate {
+        position,
+        logp,
+        iterations,
+        converged,
+    };
+
+    for iteration in 0..max_iterations {
+        let grad_norm_sq = dot(&grad, &grad);
+        if grad_norm_sq.sqrt() < GRADIENT_TOLERANCE {
+            return Ok(estimate(position, logp, iteration, true));
+        }
+
+        // the worst of the recent log densities - the candidate must do better
+        let reference = history.iter().copied().fold(f64::INFINITY, f64::min);
+
+        // backtrack until the log density increases enough (Armijo)
+        let (candidate, candidate_logp, candidate_grad) = loop {
+            let candidate = position
+                .iter()
+                .zip(grad.iter())
+                .map(|(x, g)| x + step * g)
+                .collect::<Vec<_>>();
+
+            match evaluate(&candidate) {
+                Ok((candidate_logp, candidate_grad))
+                    if candidate_logp.is_finite()
+                        && candidate_logp >= reference + 1e-4 * step * grad_norm_sq =>
+                {
+                    break (candidate, candidate_logp, candidate_grad)
+                }
+                _ => step /= 2.,
+            }
+
+            if step < f64::MIN_POSITIVE {
+                // no progress possible along the gradient
+                return Ok(estimate(position, logp, iteration, false));
+            }
+        };
+
+        let s = candidate
+            .iter()
+            .zip(position.iter())
+            .map(|(a, b)| a - b)
+            .collect::<Vec<_>>();
+        let y = candidate_grad
+            .iter()
+            .zip(grad.iter())
+            .map(|(a, b)| b - a)
+            .collect::<Vec<_>>();
+
+        let stalled = s
+            .iter()
+            .zip(position.iter())
+            .all(|(s, x)| s.abs() <= RELATIVE_TOLERANCE * (1. + x.abs()))
+            || (candidate_logp - logp).abs() <= RELATIVE_TOLERANCE * (1. + logp.abs());
+
+        // Barzilai-Borwein step for the next iteration
+        let sy = dot(&s, &y);
+        if sy > 0. {
+            step = dot(&s, &s) / sy;
+        }
+
+        position = candidate;
+        logp = candidate_logp;
+        grad = candidate_grad;
+
+        if stalled {
+            return Ok(estimate(position, logp, iteration + 1, true));
+        }
+
+        history.push(logp);
+        if history.len() > LINE_SEARCH_MEMORY {
+            history.remove(0);
+        }
+    }
+
+    Ok(estimate(position, logp, max_iterations, false))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::regression::Regression;
+
+    use super::*;
+
+    #[test]
+    fn test_maximize_regression() {
+        // long predictor span: the slope is much more constrained than the intercept
+        let x = (0..100).map(|i| i as f64 - 49.5).collect::<Vec<_>>();
+        let y = x
+            .iter()
+            .map(|x| 20. + 0.03 * x + (x * 1.7).sin())
+            .collect::<Vec<_>>();
+
+        let mut model = Regression::new(x.clone(), y.clone());
+        let map = maximize(&mut model, &[0., 0., 1.], 10_000).unwrap();
+        assert!(map.converged, "{:?}", map);
+
+        // the priors are weak: the MAP is close to the least squares fit
+        let n = x.len() as f64;
+        let y_mean = y.iter().sum::<f64>() / n;
+        let beta = x.iter().zip(y.iter()).map(|(x, y)| x * y).sum::<f64>()
+            / x.iter().map(|x| x * x).sum::<f64>();
+        let sigma = (x
+            .iter()
+            .zip(y.iter())
+            .map(|(x, y)| (y - y_mean - beta * x).powi(2))
+            .sum::<f64>()
+            / n)
+            .sqrt();
+
+        assert!((map.position[0] - y_mean).abs() < 0.05, "{:?}", map);
+        assert!((map.position[1] - beta).abs() < 1e-3, "{:?}", map);
+        assert!((map.position[2] - sigma).abs() < 0.01, "{:?}", map);
+    }
+}
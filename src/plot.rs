@@ -1,54 +1,327 @@
 //! Plot data
+use plotters::coord::Shift;
 use plotters::prelude::*;
 use plotters_canvas::CanvasBackend;
+use serde::Deserialize;
+use std::convert::TryFrom;
+use wasm_bindgen::prelude::*;
+
+use crate::chain::{axis_bounds, histogram_bins};
+use crate::stats::{mean, moving_average};
+use crate::utils::variant_from_index;
+use crate::MyError;
+
+/// Width and height of the SVG plots - in pixels - when not given.
+pub(crate) const DEFAULT_SVG_SIZE: (u32, u32) = (1200, 800);
+
+/// Returns the drawing area of the canvas with the given id.
+///
+/// Fails if the canvas is missing or has no 2D context.
+pub(crate) fn canvas_area(canvas_id: &str) -> Result<DrawingArea<CanvasBackend, Shift>, MyError> {
+    let backend = CanvasBackend::new(canvas_id)
+        .ok_or_else(|| MyError::CanvasUnavailable(canvas_id.to_string()))?;
+    Ok(backend.into_drawing_area())
+}
+
+/// Draws with `draw` on an SVG of `size` (width, height) pixels and returns
+/// the SVG document - to embed the plots in reports and scale them without
+/// loss.
+pub(crate) fn render_svg(
+    size: (u32, u32),
+    draw: impl FnOnce(&DrawingArea<SVGBackend, Shift>) -> Result<(), MyError>,
+) -> Result<String, MyError> {
+    let mut svg = String::new();
+    {
+        // the document is written when the backend is dropped
+        let root = SVGBackend::with_string(&mut svg, size).into_drawing_area();
+        draw(&root)?;
+    }
+    Ok(svg)
+}
+
+/// Where the legend of a plot is drawn - if at all
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "u32")]
+pub enum LegendPosition {
+    /// Middle of the right side
+    #[default]
+    MiddleRight,
+    /// Upper left corner
+    UpperLeft,
+    /// Upper right corner
+    UpperRight,
+    /// Lower left corner
+    LowerLeft,
+    /// Lower right corner
+    LowerRight,
+    /// No legend - e.g. when it hides the lines of many chains
+    Hidden,
+}
+
+impl TryFrom<u32> for LegendPosition {
+    type Error = String;
+
+    fn try_from(index: u32) -> Result<Self, Self::Error> {
+        use LegendPosition::*;
+        variant_from_index(
+            &[
+                MiddleRight,
+                UpperLeft,
+                UpperRight,
+                LowerLeft,
+                LowerRight,
+                Hidden,
+            ],
+            index,
+        )
+    }
+}
+
+impl LegendPosition {
+    /// Returns the position of the series labels - `None` without a legend.
+    pub(crate) fn position(self) -> Option<SeriesLabelPosition> {
+        match self {
+            LegendPosition::MiddleRight => Some(SeriesLabelPosition::MiddleRight),
+            LegendPosition::UpperLeft => Some(SeriesLabelPosition::UpperLeft),
+            LegendPosition::UpperRight => Some(SeriesLabelPosition::UpperRight),
+            LegendPosition::LowerLeft => Some(SeriesLabelPosition::LowerLeft),
+            LegendPosition::LowerRight => Some(SeriesLabelPosition::LowerRight),
+            LegendPosition::Hidden => None,
+        }
+    }
+}
+
+/// Styling of [`TMaxPlot`]
+///
+/// Colors are `0xRRGGBB` integers.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug)]
+pub struct PlotStyle {
+    /// Color of the observations
+    pub observed_color: u32,
+    /// Color of the regression lines
+    pub regression_color: u32,
+    /// Radius of the markers of the observations
+    pub marker_radius: u32,
+    /// Width of the lines
+    pub stroke_width: u32,
+    /// Draw a marker for each observation
+    pub show_points: bool,
+    /// Connect the observations with a line
+    pub show_line: bool,
+    /// Sort the observations by date - the input order is kept otherwise
+    pub sort_by_date: bool,
+    /// Position of the legend
+    pub legend: LegendPosition,
+    /// Window (in observations) of a centered moving average of TMAX drawn
+    /// over the data - 0 for none. The observations are averaged in the order
+    /// they are drawn and the window shrinks at the edges (see
+    /// [`moving_average`])
+    pub moving_average_window: u32,
+    /// Color of the moving average
+    pub moving_average_color: u32,
+    /// Above this many observations, the markers are replaced by the mean and
+    /// the range of TMAX in `max_points` bins of dates (see [`bin_by_date`]) -
+    /// 0 to always draw every observation. The regression lines and the
+    /// moving average still use every observation
+    pub max_points: u32,
+}
+
+impl Default for PlotStyle {
+    fn default() -> Self {
+        Self {
+            observed_color: 0xFF0000,
+            regression_color: 0x0000FF,
+            marker_radius: 1,
+            stroke_width: 1,
+            show_points: true,
+            show_line: false,
+            sort_by_date: true,
+            legend: LegendPosition::default(),
+            moving_average_window: 0,
+            moving_average_color: 0x008000,
+            max_points: 10_000,
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl PlotStyle {
+    /// Create the default style: red markers and faint blue regression lines
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+/// Canvases of the additional views of a run - see `run_with`
+///
+/// Each view is drawn in the canvas with the given id - or skipped without
+/// one.
+#[wasm_bindgen(getter_with_clone)]
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PlotViews {
+    /// The observations and the regression lines of posterior draws
+    pub data: Option<String>,
+    /// The residuals at the posterior mean
+    pub residuals: Option<String>,
+}
+
+#[wasm_bindgen]
+impl PlotViews {
+    /// Create views that are all skipped
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+/// Returns the DATE and the value columns of the observations - the first two
+/// ones. The other columns (e.g. COUNT) are left out.
+///
+/// Fails with [`MyError::UnexpectedColumns`] if the first column is not DATE -
+/// e.g. for columns selected by index - or if there is no value column.
+fn date_value_columns(
+    observed: Vec<Vec<f64>>,
+    parameters: &[String],
+) -> Result<Vec<Vec<f64>>, MyError> {
+    if parameters.len() < 2 || parameters[0] != "DATE" {
+        return Err(MyError::UnexpectedColumns(parameters.to_vec()));
+    }
+    Ok(observed
+        .into_iter()
+        .map(|mut row| {
+            row.truncate(2);
+            row
+        })
+        .collect())
+}
+
+/// Convert a `0xRRGGBB` integer to a color
+fn rgb(color: u32) -> RGBColor {
+    RGBColor((color >> 16) as u8, (color >> 8) as u8, color as u8)
+}
 
 /// Plot TMAX as a function of time
+///
+/// The observations are date-ordered (unless disabled in the [`PlotStyle`]) so
+/// that the line connecting them and the regression lines follow time -
+/// whatever the order of the input rows.
 pub(crate) struct TMaxPlot {
     observed: Vec<Vec<f64>>,
     regression: Option<Vec<Vec<f64>>>,
+    style: PlotStyle,
+    /// The regression is on the log of TMAX
+    log_response: bool,
+    /// Caption of the plot
+    title: String,
 }
 
 impl TMaxPlot {
+    /// Number of values in a row of the regression: alpha, beta and sigma
+    const REGRESSION_WIDTH: usize = 3;
+
     /// Create a new plot
+    ///
+    /// Fails if the first two columns are not DATE and a temperature element
+    /// (or VALUE) or if a row of the regression has fewer than alpha, beta and
+    /// sigma. The other columns are left out.
     pub(crate) fn new(
         observed: Vec<Vec<f64>>,
         regression: Option<Vec<Vec<f64>>>,
         parameters: Vec<String>,
-    ) -> Self {
-        assert_eq!(parameters.len(), 2);
-        assert_eq!(parameters[0], "DATE");
-        assert_eq!(parameters[1], "TMAX");
+        style: PlotStyle,
+    ) -> Result<Self, MyError> {
+        let observed = date_value_columns(observed, &parameters)?;
+        // anomalies relative to a baseline period are captioned as such
+        let title = match parameters[1].as_str() {
+            "TMAX" => "TMax (C)",
+            "TMAX_ANOMALY" => "TMax anomaly (C)",
+            "TMIN" => "TMin (C)",
+            "TMIN_ANOMALY" => "TMin anomaly (C)",
+            "TAVG" => "TAvg (C)",
+            "TAVG_ANOMALY" => "TAvg anomaly (C)",
+            // a generic time series - see `prepare_generic`
+            "VALUE" => "Value",
+            _ => return Err(MyError::UnexpectedColumns(parameters)),
+        };
 
-        Self {
+        if let Some(regression) = &regression {
+            if let Some((row, draw)) = regression
+                .iter()
+                .enumerate()
+                .find(|(_, draw)| draw.len() < Self::REGRESSION_WIDTH)
+            {
+                return Err(MyError::RegressionRowTooShort {
+                    row,
+                    width: draw.len(),
+                    expected: Self::REGRESSION_WIDTH,
+                });
+            }
+        }
+
+        let mut observed = observed;
+        if style.sort_by_date {
+            // stable: observations of the same date keep their order
+            observed.sort_by(|a, b| a[0].total_cmp(&b[0]));
+        }
+
+        Ok(Self {
             observed,
             regression,
+            style,
+            log_response: false,
+            title: String::from(title),
+        })
+    }
+
+    /// Caption the plot with `title` instead of "TMax (C)" - or "TMax anomaly
+    /// (C)" for anomalies.
+    pub(crate) fn with_title(mut self, title: String) -> Self {
+        self.title = title;
+        self
+    }
+
+    /// The regression is on the log of TMAX: its lines are back-transformed to
+    /// the mean of the lognormal - `exp(mu + sigma² / 2)`.
+    pub(crate) fn with_log_response(mut self) -> Self {
+        self.log_response = true;
+        self
+    }
+
+    /// The regression line at `x` for a draw `[alpha, beta, sigma]` - `x_m` is
+    /// the reference of the intercept.
+    fn regression_line(&self, alpha_beta_sigma: &[f64], x: f64, x_m: f64) -> f64 {
+        let (alpha, beta) = (alpha_beta_sigma[0], alpha_beta_sigma[1]);
+        let mu = alpha + beta * (x - x_m);
+
+        if self.log_response {
+            let sigma = alpha_beta_sigma[2];
+            (mu + 0.5 * sigma * sigma).exp()
+        } else {
+            mu
         }
     }
 
     /// Plot the data
-    pub fn plot(&self, canvas_id: &str) {
-        let backend = CanvasBackend::new(canvas_id).expect("cannot find canvas");
-        let root = backend.into_drawing_area();
+    pub fn plot(&self, canvas_id: &str) -> Result<(), MyError> {
+        self.draw(&canvas_area(canvas_id)?)
+    }
 
+    /// Draw the plot on `root` - e.g. an SVG (see [`render_svg`]).
+    pub(crate) fn draw<DB: DrawingBackend>(
+        &self,
+        root: &DrawingArea<DB, Shift>,
+    ) -> Result<(), MyError> {
         root.fill(&WHITE).unwrap();
 
-        let (date_min, date_max) = self
-            .observed
-            .iter()
-            .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), x| {
-                (min.min(x[0]), max.max(x[0]))
-            });
+        let ((date_min, date_max), (t_max_min, t_max_max)) = data_bounds(&self.observed);
 
-        let (t_max_min, t_max_max) = self
-            .observed
-            .iter()
-            .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), x| {
-                (min.min(x[1]), max.max(x[1]))
-            });
-
-        let mut chart = ChartBuilder::on(&root)
+        let mut chart = ChartBuilder::on(root)
             .margin(5)
-            .caption("TMax (C)", ("sans-serif", 30))
+            .caption(self.title.as_str(), ("sans-serif", 30))
             .x_label_area_size(30)
             .y_label_area_size(50)
             .set_label_area_size(LabelAreaPosition::Right, 60)
@@ -67,53 +340,831 @@ impl TMaxPlot {
 
         let observed = self.observed.clone();
 
-        chart
-            // .draw_series(LineSeries::new(
-            // observed.iter().map(|d_t| (d_t[0], d_t[1])),
-            // Into::<ShapeStyle>::into(RED).stroke_width(1),
-            .draw_series(
-                observed
-                    .iter()
-                    .map(|d_t| (d_t[0], d_t[1]))
-                    .map(|(x, y)| Circle::new((x, y), 1, RED.filled())),
-            )
-            .unwrap()
-            .label("TMax")
-            .legend(move |(x, y)| Rectangle::new([(x, y - 5), (x + 10, y + 5)], RED.filled()));
+        let style = self.style;
+        let observed_color = rgb(style.observed_color);
+        let regression_color = rgb(style.regression_color);
+
+        if style.show_line {
+            let c = chart
+                .draw_series(LineSeries::new(
+                    observed.iter().map(|d_t| (d_t[0], d_t[1])),
+                    Into::<ShapeStyle>::into(observed_color).stroke_width(style.stroke_width),
+                ))
+                .unwrap();
+
+            if !style.show_points {
+                c.label("TMax").legend(move |(x, y)| {
+                    Rectangle::new([(x, y - 5), (x + 10, y + 5)], observed_color.filled())
+                });
+            }
+        }
+
+        let binned = style.max_points > 0 && observed.len() > style.max_points as usize;
+
+        if style.show_points && binned {
+            let bins = bin_by_date(&observed, style.max_points as usize);
+
+            chart
+                .draw_series(bins.iter().map(|bin| {
+                    PathElement::new(
+                        vec![(bin.date, bin.min), (bin.date, bin.max)],
+                        observed_color.mix(0.3),
+                    )
+                }))
+                .unwrap();
+            chart
+                .draw_series(bins.iter().map(|bin| {
+                    Circle::new(
+                        (bin.date, bin.mean),
+                        style.marker_radius,
+                        observed_color.filled(),
+                    )
+                }))
+                .unwrap()
+                .label(format!("TMax ({} bins: mean and range)", bins.len()))
+                .legend(move |(x, y)| {
+                    Rectangle::new([(x, y - 5), (x + 10, y + 5)], observed_color.filled())
+                });
+        } else if style.show_points {
+            chart
+                .draw_series(observed.iter().map(|d_t| (d_t[0], d_t[1])).map(|(x, y)| {
+                    Circle::new((x, y), style.marker_radius, observed_color.filled())
+                }))
+                .unwrap()
+                .label("TMax")
+                .legend(move |(x, y)| {
+                    Rectangle::new([(x, y - 5), (x + 10, y + 5)], observed_color.filled())
+                });
+        }
+
+        if style.moving_average_window > 0 {
+            let t_max = observed.iter().map(|d_t| d_t[1]).collect::<Vec<_>>();
+            let smoothed = moving_average(&t_max, style.moving_average_window as usize);
+            let color = rgb(style.moving_average_color);
+
+            chart
+                .draw_series(LineSeries::new(
+                    observed.iter().zip(smoothed).map(|(d_t, t)| (d_t[0], t)),
+                    Into::<ShapeStyle>::into(color).stroke_width(style.stroke_width + 1),
+                ))
+                .unwrap()
+                .label(format!("Moving average ({})", style.moving_average_window))
+                .legend(move |(x, y)| {
+                    Rectangle::new([(x, y - 5), (x + 10, y + 5)], color.filled())
+                });
+        }
 
         if let Some(regression) = &self.regression {
             let mut first = true;
             let x = observed.iter().map(|x| x[0]).collect::<Vec<_>>();
-            let x_m = x.iter().sum::<f64>() / x.len() as f64;
+            let x_m = mean(&x);
 
             for alpha_beta_sigma in regression {
-                let alpha = alpha_beta_sigma[0];
-                let beta = alpha_beta_sigma[1];
-                // let sigma = alpha_beta_sigma[2];
-
                 let y_ = x
                     .iter()
-                    .map(|x| alpha + beta * (x - x_m))
+                    .map(|x| self.regression_line(alpha_beta_sigma, *x, x_m))
                     .collect::<Vec<_>>();
 
                 let c = chart
                     .draw_series(LineSeries::new(
                         x.iter().zip(y_.iter()).map(|(x, y)| (*x, *y)),
-                        Into::<ShapeStyle>::into(BLUE.mix(0.6)).stroke_width(1),
+                        Into::<ShapeStyle>::into(regression_color.mix(0.6))
+                            .stroke_width(style.stroke_width),
                     ))
                     .unwrap();
 
                 if first {
                     c.label("Regression").legend(move |(x, y)| {
-                        Rectangle::new([(x, y - 5), (x + 10, y + 5)], BLUE.filled())
+                        Rectangle::new([(x, y - 5), (x + 10, y + 5)], regression_color.filled())
                     });
                     first = false;
                 }
             }
         }
 
-        chart.configure_series_labels().draw().unwrap();
+        if let Some(position) = style.legend.position() {
+            chart
+                .configure_series_labels()
+                .position(position)
+                .draw()
+                .unwrap();
+        }
+
+        root.present().unwrap();
+
+        Ok(())
+    }
+}
+
+/// Plot the residuals of the regression to diagnose the fit
+pub(crate) struct ResidualPlot {
+    /// (date, fitted, residual) for each observation
+    residuals: Vec<(f64, f64, f64)>,
+    /// Prefix of the captions of the panels
+    title_prefix: String,
+}
+
+impl ResidualPlot {
+    /// Create a new residual plot from the observations and the posterior mean
+    /// of the regression parameters (alpha, beta, ...).
+    ///
+    /// Fails if the first two columns are not DATE and a value or if the
+    /// posterior mean misses alpha or beta. The other columns are left out.
+    pub(crate) fn new(
+        observed: Vec<Vec<f64>>,
+        posterior_mean: Vec<f64>,
+        parameters: Vec<String>,
+    ) -> Result<Self, MyError> {
+        let observed = date_value_columns(observed, &parameters)?;
+        if posterior_mean.len() < 2 {
+            return Err(MyError::PosteriorMeanTooShort(posterior_mean.len()));
+        }
+
+        let alpha = posterior_mean[0];
+        let beta = posterior_mean[1];
+
+        // same reference as the regression - the middle of the time period
+        let x_m = observed.iter().map(|x| x[0]).sum::<f64>() / observed.len() as f64;
+
+        let residuals = observed
+            .iter()
+            .map(|d_t| {
+                let fitted = alpha + beta * (d_t[0] - x_m);
+                (d_t[0], fitted, d_t[1] - fitted)
+            })
+            .collect();
+
+        Ok(Self {
+            residuals,
+            title_prefix: String::new(),
+        })
+    }
+
+    /// Prefix the captions of the panels with `title_prefix` - e.g. the name
+    /// of the station.
+    pub(crate) fn with_title_prefix(mut self, title_prefix: String) -> Self {
+        self.title_prefix = title_prefix;
+        self
+    }
+
+    /// Plot the residuals against the date (left) and against the fitted
+    /// value (right).
+    pub fn plot(&self, canvas_id: &str) -> Result<(), MyError> {
+        self.draw(&canvas_area(canvas_id)?)
+    }
+
+    /// Draw the plot on `root` - e.g. an SVG (see [`render_svg`]).
+    pub(crate) fn draw<DB: DrawingBackend>(
+        &self,
+        root: &DrawingArea<DB, Shift>,
+    ) -> Result<(), MyError> {
+        root.fill(&WHITE).unwrap();
+
+        let subplots = root.split_evenly((1, 2));
+
+        let (r_min, r_max) = extrema(self.residuals.iter().map(|(_, _, r)| *r));
+
+        let panels = [("Residuals vs date", 0), ("Residuals vs fitted", 1)];
+
+        for (subplot, (caption, column)) in subplots.iter().zip(panels) {
+            let x_of =
+                |(date, fitted, _): &(f64, f64, f64)| if column == 0 { *date } else { *fitted };
+
+            let (x_min, x_max) = extrema(self.residuals.iter().map(x_of));
+
+            let mut chart = ChartBuilder::on(subplot)
+                .margin(5)
+                .caption(
+                    format!("{}{}", self.title_prefix, caption),
+                    ("sans-serif", 30),
+                )
+                .x_label_area_size(30)
+                .y_label_area_size(50)
+                .set_label_area_size(LabelAreaPosition::Bottom, 30)
+                .build_cartesian_2d(x_min..x_max, r_min..r_max)
+                .unwrap();
+
+            chart
+                .configure_mesh()
+                .x_labels(3)
+                .y_labels(3)
+                .x_label_style(TextStyle::from(("sans-serif", 20)).color(&BLACK))
+                .y_label_style(TextStyle::from(("sans-serif", 20)).color(&BLACK))
+                .draw()
+                .unwrap();
+
+            chart
+                .draw_series(
+                    self.residuals
+                        .iter()
+                        .map(|r| Circle::new((x_of(r), r.2), 1, RED.filled())),
+                )
+                .unwrap();
+
+            // a structure around this line hints at a missed pattern
+            chart
+                .draw_series(LineSeries::new(
+                    [(x_min, 0.), (x_max, 0.)],
+                    Into::<ShapeStyle>::into(BLACK).stroke_width(1),
+                ))
+                .unwrap();
+        }
+
+        root.present().unwrap();
+
+        Ok(())
+    }
+}
+
+/// Plot detrended observations against the day of the year - the annual cycle
+pub(crate) struct SeasonalPlot {
+    /// (day of year, detrended value) for each observation
+    points: Vec<(f64, f64)>,
+    /// Caption of the plot
+    title: String,
+}
+
+impl SeasonalPlot {
+    /// Create a new seasonal plot
+    pub(crate) fn new(points: Vec<(f64, f64)>) -> Self {
+        Self {
+            points,
+            title: String::from("Detrended TMax vs day of year"),
+        }
+    }
+
+    /// Caption the plot with `title` instead of "Detrended TMax vs day of year".
+    pub(crate) fn with_title(mut self, title: String) -> Self {
+        self.title = title;
+        self
+    }
+
+    /// Plot the data
+    pub fn plot(&self, canvas_id: &str) -> Result<(), MyError> {
+        self.draw(&canvas_area(canvas_id)?)
+    }
+
+    /// Draw the plot on `root` - e.g. an SVG (see [`render_svg`]).
+    pub(crate) fn draw<DB: DrawingBackend>(
+        &self,
+        root: &DrawingArea<DB, Shift>,
+    ) -> Result<(), MyError> {
+        root.fill(&WHITE).unwrap();
+
+        let (v_min, v_max) = extrema(self.points.iter().map(|(_, v)| *v));
+
+        let mut chart = ChartBuilder::on(root)
+            .margin(5)
+            .caption(self.title.as_str(), ("sans-serif", 30))
+            .x_label_area_size(30)
+            .y_label_area_size(50)
+            .set_label_area_size(LabelAreaPosition::Bottom, 30)
+            .build_cartesian_2d(1f64..366f64, v_min..v_max)
+            .unwrap();
+
+        chart
+            .configure_mesh()
+            .x_labels(6)
+            .y_labels(3)
+            .x_desc("Day of year")
+            .x_label_style(TextStyle::from(("sans-serif", 20)).color(&BLACK))
+            .y_label_style(TextStyle::from(("sans-serif", 20)).color(&BLACK))
+            .draw()
+            .unwrap();
+
+        chart
+            .draw_series(
+                self.points
+                    .iter()
+                    .map(|(doy, v)| Circle::new((*doy, *v), 1, RED.filled())),
+            )
+            .unwrap();
+
+        chart
+            .draw_series(LineSeries::new(
+                [(1., 0.), (366., 0.)],
+                Into::<ShapeStyle>::into(BLACK).stroke_width(1),
+            ))
+            .unwrap();
+
+        root.present().unwrap();
+
+        Ok(())
+    }
+}
+
+/// Plot TMAX (line, left axis) and PRCP (bars, right axis) as functions of time
+pub(crate) struct DualAxisPlot {
+    /// (date, TMAX, PRCP) for each observation - date-ordered
+    observed: Vec<(f64, f64, f64)>,
+    /// Caption of the plot
+    title: String,
+}
+
+impl DualAxisPlot {
+    /// Create a new plot - the observations are sorted by date.
+    pub(crate) fn new(mut observed: Vec<(f64, f64, f64)>) -> Self {
+        observed.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        Self {
+            observed,
+            title: String::from("TMax (C) and PRCP (mm)"),
+        }
+    }
+
+    /// Caption the plot with `title` instead of "TMax (C) and PRCP (mm)".
+    pub(crate) fn with_title(mut self, title: String) -> Self {
+        self.title = title;
+        self
+    }
+
+    /// Width of the PRCP bars: the smallest gap between two dates - a day
+    /// (in years) for a single date.
+    fn bar_width(&self) -> f64 {
+        let gap = self
+            .observed
+            .windows(2)
+            .map(|w| w[1].0 - w[0].0)
+            .filter(|gap| *gap > 0.)
+            .fold(f64::INFINITY, f64::min);
+
+        if gap.is_finite() {
+            gap
+        } else {
+            1. / 365.25
+        }
+    }
+
+    /// Plot the data
+    pub fn plot(&self, canvas_id: &str) -> Result<(), MyError> {
+        self.draw(&canvas_area(canvas_id)?)
+    }
+
+    /// Draw the plot on `root` - e.g. an SVG (see [`render_svg`]).
+    pub(crate) fn draw<DB: DrawingBackend>(
+        &self,
+        root: &DrawingArea<DB, Shift>,
+    ) -> Result<(), MyError> {
+        root.fill(&WHITE).unwrap();
+
+        let width = self.bar_width();
+        let (date_min, date_max) = padded(extrema(self.observed.iter().map(|x| x.0)));
+        let (date_min, date_max) = (date_min - width / 2., date_max + width / 2.);
+        let (t_max_min, t_max_max) = padded(extrema(self.observed.iter().map(|x| x.1)));
+        // the bars start at 0
+        let (_, prcp_max) = padded((0., extrema(self.observed.iter().map(|x| x.2)).1));
+
+        let mut chart = ChartBuilder::on(root)
+            .margin(5)
+            .caption(self.title.as_str(), ("sans-serif", 30))
+            .x_label_area_size(30)
+            .y_label_area_size(50)
+            .right_y_label_area_size(50)
+            .set_label_area_size(LabelAreaPosition::Bottom, 30)
+            .build_cartesian_2d(date_min..date_max, t_max_min..t_max_max)
+            .unwrap()
+            .set_secondary_coord(date_min..date_max, 0f64..prcp_max);
+
+        chart
+            .configure_mesh()
+            .x_labels(3)
+            .y_labels(3)
+            .y_desc("TMax")
+            .x_label_style(TextStyle::from(("sans-serif", 20)).color(&BLACK))
+            .y_label_style(TextStyle::from(("sans-serif", 20)).color(&BLACK))
+            .draw()
+            .unwrap();
+
+        chart
+            .configure_secondary_axes()
+            .y_labels(3)
+            .y_desc("PRCP")
+            .label_style(TextStyle::from(("sans-serif", 20)).color(&BLACK))
+            .draw()
+            .unwrap();
+
+        // the bars first: the TMAX line stays visible on top
+        chart
+            .draw_secondary_series(self.observed.iter().map(|(date, _, prcp)| {
+                Rectangle::new(
+                    [(date - width / 2., 0.), (date + width / 2., *prcp)],
+                    BLUE.mix(0.4).filled(),
+                )
+            }))
+            .unwrap()
+            .label("PRCP")
+            .legend(|(x, y)| Rectangle::new([(x, y - 5), (x + 10, y + 5)], BLUE.mix(0.4).filled()));
+
+        chart
+            .draw_series(LineSeries::new(
+                self.observed.iter().map(|(date, t_max, _)| (*date, *t_max)),
+                Into::<ShapeStyle>::into(RED).stroke_width(1),
+            ))
+            .unwrap()
+            .label("TMax")
+            .legend(|(x, y)| Rectangle::new([(x, y - 5), (x + 10, y + 5)], RED.filled()));
+
+        chart
+            .configure_series_labels()
+            .background_style(WHITE.mix(0.8))
+            .draw()
+            .unwrap();
+
+        root.present().unwrap();
+
+        Ok(())
+    }
+}
+
+/// Plot the posterior of a quantity derived from the parameters
+pub(crate) struct DerivedPlot {
+    /// The derived quantity at each draw
+    values: Vec<f64>,
+    /// Caption of the plot
+    caption: String,
+}
+
+impl DerivedPlot {
+    /// Create a new plot of `f` evaluated at each draw of the parameters.
+    pub(crate) fn new(draws: &[Vec<f64>], f: impl Fn(&[f64]) -> f64, caption: String) -> Self {
+        let values = draws.iter().map(|draw| f(draw)).collect();
+
+        Self { values, caption }
+    }
+
+    /// Plot the histogram of the derived quantity
+    pub fn plot(&self, canvas_id: &str) -> Result<(), MyError> {
+        self.draw(&canvas_area(canvas_id)?)
+    }
+
+    /// Draw the plot on `root` - e.g. an SVG (see [`render_svg`]).
+    pub(crate) fn draw<DB: DrawingBackend>(
+        &self,
+        root: &DrawingArea<DB, Shift>,
+    ) -> Result<(), MyError> {
+        root.fill(&WHITE).unwrap();
+
+        let (min_, max_) = extrema(self.values.iter().copied());
+        let (min_, max_) = axis_bounds(min_, max_);
+        let (step, max_height) = histogram_bins(std::slice::from_ref(&self.values), min_, max_);
+
+        let mut chart = ChartBuilder::on(root)
+            .margin(5)
+            .caption(self.caption.as_str(), ("sans-serif", 30))
+            .set_label_area_size(LabelAreaPosition::Left, 70)
+            .set_label_area_size(LabelAreaPosition::Bottom, 30)
+            .build_cartesian_2d((min_..max_).step(step).use_round(), 0f64..max_height as f64)
+            .unwrap();
+
+        chart
+            .configure_mesh()
+            .disable_x_mesh()
+            .disable_y_mesh()
+            .y_desc("Count")
+            .y_label_style(TextStyle::from(("sans-serif", 20)).color(&BLACK))
+            .x_label_style(TextStyle::from(("sans-serif", 20)).color(&BLACK))
+            .draw()
+            .unwrap();
+
+        chart
+            .draw_series(
+                Histogram::vertical(&chart)
+                    .style(BLUE.mix(0.2).filled())
+                    .data(self.values.iter().map(|x| (*x, 1.))),
+            )
+            .unwrap();
 
         root.present().unwrap();
+
+        Ok(())
+    }
+}
+
+/// Returns the (min, max) of the values
+fn extrema(values: impl Iterator<Item = f64>) -> (f64, f64) {
+    values.fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), x| {
+        (min.min(x), max.max(x))
+    })
+}
+
+/// Returns the ranges of the axes of the plot of the observations - the DATE in
+/// the 1st column and TMAX in the 2nd: `((date_min, date_max), (tmax_min,
+/// tmax_max))`.
+///
+/// A single date or a constant TMAX still gets non-empty ranges - see
+/// [`padded`].
+pub(crate) fn data_bounds(observed: &[Vec<f64>]) -> ((f64, f64), (f64, f64)) {
+    (
+        padded(extrema(observed.iter().map(|x| x[0]))),
+        padded(extrema(observed.iter().map(|x| x[1]))),
+    )
+}
+
+/// The observations of a bin of dates - see [`bin_by_date`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct DateBin {
+    /// Mean date of the observations
+    pub date: f64,
+    /// Mean TMAX
+    pub mean: f64,
+    /// Lowest TMAX
+    pub min: f64,
+    /// Highest TMAX
+    pub max: f64,
+}
+
+/// Splits the range of the dates (1st column) into `bins` intervals of equal
+/// width and summarizes the TMAX (2nd column) of the observations in each -
+/// in date order, the empty bins are skipped.
+pub(crate) fn bin_by_date(observed: &[Vec<f64>], bins: usize) -> Vec<DateBin> {
+    assert!(bins > 0, "At least one bin is required");
+    let (date_min, date_max) = extrema(observed.iter().map(|x| x[0]));
+    let width = (date_max - date_min) / bins as f64;
+
+    // (count, sum of dates, sum of TMAX, min, max)
+    let mut acc = vec![(0usize, 0., 0., f64::INFINITY, f64::NEG_INFINITY); bins];
+    for row in observed {
+        let (date, t_max) = (row[0], row[1]);
+        let i = if width > 0. {
+            (((date - date_min) / width) as usize).min(bins - 1)
+        } else {
+            0
+        };
+        let bin = &mut acc[i];
+        bin.0 += 1;
+        bin.1 += date;
+        bin.2 += t_max;
+        bin.3 = bin.3.min(t_max);
+        bin.4 = bin.4.max(t_max);
+    }
+
+    acc.into_iter()
+        .filter(|bin| bin.0 > 0)
+        .map(|(count, dates, t_max, min, max)| DateBin {
+            date: dates / count as f64,
+            mean: t_max / count as f64,
+            min,
+            max,
+        })
+        .collect()
+}
+
+/// Widens an empty range - `min == max` - by 1 on each side: the charts need
+/// a non-empty range.
+fn padded((min, max): (f64, f64)) -> (f64, f64) {
+    if min < max {
+        (min, max)
+    } else {
+        (min - 1., max + 1.)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tmax_plot_log_response() {
+        let parameters = vec![String::from("DATE"), String::from("TMAX")];
+        let observed = vec![vec![2000., 1.], vec![2002., 3.]];
+        let draw = [1., 0.5, 0.2];
+
+        let p = TMaxPlot::new(
+            observed.clone(),
+            None,
+            parameters.clone(),
+            PlotStyle::default(),
+        )
+        .unwrap();
+        assert_eq!(p.regression_line(&draw, 2002., 2001.), 1.5);
+
+        // the mean of the lognormal
+        let p = TMaxPlot::new(observed, None, parameters, PlotStyle::default())
+            .unwrap()
+            .with_log_response();
+        assert!((p.regression_line(&draw, 2002., 2001.) - (1.5f64 + 0.02).exp()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_derived_plot() {
+        let draws = vec![vec![1., 0.5, 0.2], vec![2., -0.5, 0.3]];
+
+        let p = DerivedPlot::new(
+            &draws,
+            |draw| draw[0] + draw[1] * (2010. - 2000.),
+            String::from("TMAX in 2010"),
+        );
+        assert_eq!(p.values, vec![6., -3.]);
+    }
+
+    #[test]
+    fn test_tmax_plot_sorts_dates() {
+        let parameters = vec![String::from("DATE"), String::from("TMAX")];
+        let shuffled = vec![
+            vec![2002., 3.],
+            vec![2000., 1.],
+            vec![2001., 2.],
+            vec![2000., 4.],
+        ];
+
+        let p = TMaxPlot::new(
+            shuffled.clone(),
+            None,
+            parameters.clone(),
+            PlotStyle::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            p.observed,
+            vec![
+                vec![2000., 1.],
+                vec![2000., 4.],
+                vec![2001., 2.],
+                vec![2002., 3.]
+            ]
+        );
+
+        let style = PlotStyle {
+            sort_by_date: false,
+            ..PlotStyle::default()
+        };
+        let p = TMaxPlot::new(shuffled.clone(), None, parameters, style).unwrap();
+        assert_eq!(p.observed, shuffled);
+    }
+
+    #[test]
+    fn test_titles() {
+        let parameters = vec![String::from("DATE"), String::from("TMAX")];
+        let observed = vec![vec![2000., 1.], vec![2002., 3.]];
+
+        let p = TMaxPlot::new(
+            observed.clone(),
+            None,
+            parameters.clone(),
+            PlotStyle::default(),
+        )
+        .unwrap();
+        assert_eq!(p.title, "TMax (C)");
+        let p = p.with_title(String::from("TMax (F) - Paris"));
+        assert_eq!(p.title, "TMax (F) - Paris");
+
+        let anomalies = vec![String::from("DATE"), String::from("TMAX_ANOMALY")];
+        let p = TMaxPlot::new(observed.clone(), None, anomalies, PlotStyle::default()).unwrap();
+        assert_eq!(p.title, "TMax anomaly (C)");
+
+        let generic = vec![String::from("DATE"), String::from("VALUE")];
+        let p = TMaxPlot::new(observed.clone(), None, generic, PlotStyle::default()).unwrap();
+        assert_eq!(p.title, "Value");
+
+        let tmin = vec![String::from("DATE"), String::from("TMIN_ANOMALY")];
+        let p = TMaxPlot::new(observed.clone(), None, tmin, PlotStyle::default()).unwrap();
+        assert_eq!(p.title, "TMin anomaly (C)");
+
+        let p = ResidualPlot::new(observed, vec![1., 1.], parameters)
+            .unwrap()
+            .with_title_prefix(String::from("Paris: "));
+        assert_eq!(p.title_prefix, "Paris: ");
+    }
+
+    #[test]
+    fn test_dual_axis_plot() {
+        let p = DualAxisPlot::new(vec![
+            (2000.5, 12., 0.),
+            (2000., 10., 2.),
+            (2000.25, 11., 1.),
+        ]);
+        assert_eq!(
+            p.observed,
+            vec![(2000., 10., 2.), (2000.25, 11., 1.), (2000.5, 12., 0.)]
+        );
+        assert_eq!(p.bar_width(), 0.25);
+        assert_eq!(p.title, "TMax (C) and PRCP (mm)");
+
+        let p = DualAxisPlot::new(vec![(2000., 10., 2.)]).with_title(String::from("Paris"));
+        assert_eq!(p.bar_width(), 1. / 365.25);
+        assert_eq!(p.title, "Paris");
+    }
+
+    #[test]
+    fn test_legend_position() {
+        assert_eq!(
+            PlotStyle::default().legend.position(),
+            Some(SeriesLabelPosition::MiddleRight)
+        );
+        assert_eq!(
+            LegendPosition::LowerLeft.position(),
+            Some(SeriesLabelPosition::LowerLeft)
+        );
+        assert_eq!(LegendPosition::Hidden.position(), None);
+    }
+
+    #[test]
+    fn test_padded() {
+        assert_eq!(padded((2000., 2010.)), (2000., 2010.));
+        assert_eq!(padded(extrema(std::iter::once(12.5))), (11.5, 13.5));
+
+        let observed = vec![vec![2001., 12.], vec![2000., 14.], vec![2003., 13.]];
+        assert_eq!(data_bounds(&observed), ((2000., 2003.), (12., 14.)));
+        assert_eq!(data_bounds(&observed[..1]), ((2000., 2002.), (11., 13.)));
+    }
+
+    #[test]
+    fn test_bin_by_date() {
+        let observed = vec![
+            vec![2000., 10.],
+            vec![2001., 14.],
+            vec![2009., 20.],
+            vec![2010., 30.],
+            vec![2003., 12.],
+        ];
+
+        // [2000, 2005) and [2005, 2010] - the last date in the last bin
+        assert_eq!(
+            bin_by_date(&observed, 2),
+            vec![
+                DateBin {
+                    date: 6004. / 3.,
+                    mean: 12.,
+                    min: 10.,
+                    max: 14.
+                },
+                DateBin {
+                    date: 2009.5,
+                    mean: 25.,
+                    min: 20.,
+                    max: 30.
+                }
+            ]
+        );
+
+        // empty bins are skipped - 2009 and 2010 share the last one
+        assert_eq!(bin_by_date(&observed, 10).len(), 4);
+        // a single date
+        let bins = bin_by_date(&observed[..1], 3);
+        assert_eq!(bins.len(), 1);
+        assert_eq!((bins[0].min, bins[0].max), (10., 10.));
+        assert!(bin_by_date(&[], 3).is_empty());
+    }
+
+    #[test]
+    fn test_tmax_plot_truncated_regression() {
+        let parameters = vec![String::from("DATE"), String::from("TMAX")];
+        let observed = vec![vec![2000., 1.], vec![2002., 3.]];
+        let regression = vec![vec![1., 0.5, 0.2], vec![1., 0.5]];
+
+        assert!(matches!(
+            TMaxPlot::new(observed, Some(regression), parameters, PlotStyle::default()),
+            Err(MyError::RegressionRowTooShort {
+                row: 1,
+                width: 2,
+                expected: 3
+            })
+        ));
+    }
+
+    #[test]
+    fn test_unexpected_columns() {
+        let observed = vec![vec![2000., 1.], vec![2002., 3.]];
+        // e.g. columns selected by index
+        let selected = vec![String::from("YEAR"), String::from("TEMP")];
+        let unknown = vec![String::from("DATE"), String::from("TEMP")];
+
+        for parameters in [selected.clone(), unknown] {
+            assert!(matches!(
+                TMaxPlot::new(observed.clone(), None, parameters.clone(), PlotStyle::default()),
+                Err(MyError::UnexpectedColumns(columns)) if columns == parameters
+            ));
+        }
+        assert!(matches!(
+            ResidualPlot::new(observed.clone(), vec![1., 1.], selected),
+            Err(MyError::UnexpectedColumns(_))
+        ));
+        assert!(matches!(
+            ResidualPlot::new(
+                observed.clone(),
+                vec![1.],
+                vec![String::from("DATE"), String::from("TMAX")]
+            ),
+            Err(MyError::PosteriorMeanTooShort(1))
+        ));
+
+        // the columns after the value are left out
+        let counted = vec![vec![2000., 1., 3.], vec![2002., 3., 1.]];
+        let parameters = vec![
+            String::from("DATE"),
+            String::from("TMAX"),
+            String::from("COUNT"),
+        ];
+        let plot = TMaxPlot::new(counted, None, parameters, PlotStyle::default()).unwrap();
+        assert_eq!(plot.observed, observed);
+        assert!(matches!(
+            TMaxPlot::new(
+                vec![vec![2000.]],
+                None,
+                vec![String::from("DATE")],
+                PlotStyle::default()
+            ),
+            Err(MyError::UnexpectedColumns(_))
+        ));
     }
 }
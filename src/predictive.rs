@@ -0,0 +1,270 @@
+//! Posterior predictive checks for the regression
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use rand_distr::{Distribution, StandardNormal};
+
+use crate::stats::{mean, quantile, quantile_with, variance, QuantileMethod};
+
+/// Simulate new datasets from the posterior.
+///
+/// For each posterior draw `[alpha, beta, sigma, ...]`, simulate one
+/// observation at each `x`:
+/// `y ~ Normal(alpha + beta * (x - x0), sigma)`
+/// where `x0` is the reference used to center the dates in the regression.
+///
+/// Returns one simulated dataset (same length as `xs`) per draw.
+pub(crate) fn simulate_posterior_predictive(
+    draws: &[Vec<f64>],
+    xs: &[f64],
+    x0: f64,
+    seed: u64,
+) -> Vec<Vec<f64>> {
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    draws
+        .iter()
+        .map(|draw| {
+            let (alpha, beta, sigma) = (draw[0], draw[1], draw[2]);
+
+            xs.iter()
+                .map(|x| {
+                    let z: f64 = StandardNormal.sample(&mut rng);
+                    alpha + beta * (x - x0) + sigma * z
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Posterior predictive draws at arbitrary dates - e.g. to extrapolate the
+/// trend.
+///
+/// Same model as [`simulate_posterior_predictive`] but grouped the other way
+/// around: returns the draws (one per posterior draw) at each query `x`.
+pub(crate) fn predict(draws: &[Vec<f64>], query_xs: &[f64], x0: f64, seed: u64) -> Vec<Vec<f64>> {
+    let simulated = simulate_posterior_predictive(draws, query_xs, x0, seed);
+
+    (0..query_xs.len())
+        .map(|i| simulated.iter().map(|s| s[i]).collect())
+        .collect()
+}
+
+/// Credible interval of the mean of the regression - `alpha + beta * (x - x0)`,
+/// without the noise - at `x`.
+///
+/// Returns the posterior mean and the central `prob` interval of the line at `x`
+/// - its bounds computed with `method`.
+pub(crate) fn line_interval(
+    draws: &[Vec<f64>],
+    x: f64,
+    x0: f64,
+    prob: f64,
+    method: QuantileMethod,
+) -> (f64, f64, f64) {
+    assert!(!draws.is_empty(), "No draws");
+
+    let line = draws
+        .iter()
+        .map(|draw| draw[0] + draw[1] * (x - x0))
+        .collect::<Vec<_>>();
+
+    let tail = (1. - prob) / 2.;
+    (
+        mean(&line),
+        quantile_with(&line, tail, method),
+        quantile_with(&line, 1. - tail, method),
+    )
+}
+
+/// Fraction of the observations falling within the central `prob` predictive
+/// interval of the simulated datasets.
+///
+/// A well-calibrated noise model gives a coverage close to `prob`.
+pub(crate) fn coverage(observed: &[f64], simulated: &[Vec<f64>], prob: f64) -> f64 {
+    assert!(!simulated.is_empty(), "No simulated datasets");
+
+    let tail = (1. - prob) / 2.;
+
+    let covered = observed
+        .iter()
+        .enumerate()
+        .filter(|(i, y)| {
+            let at_i = simulated.iter().map(|s| s[*i]).collect::<Vec<_>>();
+            let (low, high) = (quantile(&at_i, tail), quantile(&at_i, 1. - tail));
+            (low..=high).contains(*y)
+        })
+        .count();
+
+    covered as f64 / observed.len() as f64
+}
+
+/// Nominal levels of the default calibration curve: 10%, 20%, ..., 90%.
+pub(crate) const CALIBRATION_LEVELS: [f64; 9] = [0.1, 0.2, 0.3, 0.4, 0.5, 0.6, 0.7, 0.8, 0.9];
+
+/// Calibration curve of the predictive intervals: the [`coverage`] of the
+/// central interval of each nominal level in `levels`.
+///
+/// Returns the (nominal, empirical) pairs. A well-calibrated model lies on the
+/// diagonal; an empirical coverage above the nominal one means too wide
+/// intervals, below too narrow ones.
+pub(crate) fn calibration_curve(
+    observed: &[f64],
+    simulated: &[Vec<f64>],
+    levels: &[f64],
+) -> Vec<(f64, f64)> {
+    levels
+        .iter()
+        .map(|level| {
+            assert!(
+                *level > 0. && *level < 1.,
+                "The levels must be between 0 and 1"
+            );
+            (*level, coverage(observed, simulated, *level))
+        })
+        .collect()
+}
+
+/// Bayesian p-value of the variance: fraction of the simulated datasets with a
+/// variance at least as large as the observed one.
+///
+/// Values close to 0 or 1 mean the model does not reproduce the spread of
+/// the data.
+pub(crate) fn variance_p_value(observed: &[f64], simulated: &[Vec<f64>]) -> f64 {
+    assert!(!simulated.is_empty(), "No simulated datasets");
+
+    let observed_variance = variance(observed);
+
+    let above = simulated
+        .iter()
+        .filter(|s| variance(s) >= observed_variance)
+        .count();
+
+    above as f64 / simulated.len() as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_simulate_posterior_predictive() {
+        let draws = vec![vec![10., 0.5, 0.1], vec![20., -1., 2.]];
+        let xs = (0..1000)
+            .map(|i| 2000. + i as f64 / 100.)
+            .collect::<Vec<_>>();
+        let x0 = 2005.;
+
+        let simulated = simulate_posterior_predictive(&draws, &xs, x0, 42);
+
+        assert_eq!(simulated.len(), 2);
+        assert_eq!(simulated[0].len(), xs.len());
+        assert_eq!(
+            simulated,
+            simulate_posterior_predictive(&draws, &xs, x0, 42)
+        );
+
+        // the residuals have the sigma of the draw
+        for (draw, y) in draws.iter().zip(simulated.iter()) {
+            let residuals = xs
+                .iter()
+                .zip(y.iter())
+                .map(|(x, y)| y - (draw[0] + draw[1] * (x - x0)))
+                .collect::<Vec<_>>();
+            let n = residuals.len() as f64;
+            let mean = residuals.iter().sum::<f64>() / n;
+            let sd = (residuals.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / n).sqrt();
+
+            assert!(mean.abs() < 4. * draw[2] / n.sqrt());
+            assert!((sd - draw[2]).abs() < 0.1 * draw[2]);
+        }
+    }
+
+    #[test]
+    fn test_predict() {
+        let draws = (0..2000)
+            .map(|i| vec![10. + (i % 2) as f64, 0.1, 0.5])
+            .collect::<Vec<_>>();
+        let query_xs = [2000., 2050.];
+
+        let predictions = predict(&draws, &query_xs, 2000., 7);
+
+        assert_eq!(predictions.len(), 2);
+        assert!(predictions.iter().all(|p| p.len() == draws.len()));
+
+        // centered on the extrapolated trend - with the noise of the model
+        for (x, p) in query_xs.iter().zip(predictions.iter()) {
+            let expected = 10.5 + 0.1 * (x - 2000.);
+            let mean = p.iter().sum::<f64>() / p.len() as f64;
+            assert!((mean - expected).abs() < 0.05, "mean = {}", mean);
+            assert!(quantile(p, 0.975) - quantile(p, 0.025) > 2.);
+        }
+    }
+
+    #[test]
+    fn test_line_interval() {
+        // beta is 0.1 or 0.3 - the line is known exactly at x0
+        let draws = (0..100)
+            .map(|i| vec![10., if i % 2 == 0 { 0.1 } else { 0.3 }, 5.])
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            line_interval(&draws, 2000., 2000., 0.95, QuantileMethod::Linear),
+            (10., 10., 10.)
+        );
+
+        let (mean, low, high) = line_interval(&draws, 2010., 2000., 0.95, QuantileMethod::Linear);
+        assert!((mean - 12.).abs() < 1e-12);
+        assert!((low - 11.).abs() < 1e-12);
+        assert!((high - 13.).abs() < 1e-12);
+
+        // the bounds are draws with the other methods
+        let (_, low, high) = line_interval(&draws, 2010., 2000., 0.5, QuantileMethod::Nearest);
+        assert!((low - 11.).abs() < 1e-12);
+        assert!((high - 13.).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_coverage() {
+        let xs = (0..500).map(|i| i as f64).collect::<Vec<_>>();
+        let draws = vec![vec![1., 0., 2.]; 200];
+
+        // data from the model itself is well calibrated
+        let observed = simulate_posterior_predictive(&draws[..1], &xs, 0., 1).remove(0);
+        let simulated = simulate_posterior_predictive(&draws, &xs, 0., 2);
+
+        let c = coverage(&observed, &simulated, 0.9);
+        assert!((c - 0.9).abs() < 0.05, "coverage = {}", c);
+
+        let p = variance_p_value(&observed, &simulated);
+        assert!(p > 0.01 && p < 0.99, "p = {}", p);
+
+        // a noise model too narrow for the data is not
+        let narrow = vec![vec![1., 0., 0.5]; 200];
+        let simulated = simulate_posterior_predictive(&narrow, &xs, 0., 2);
+
+        assert!(coverage(&observed, &simulated, 0.9) < 0.5);
+        assert_eq!(variance_p_value(&observed, &simulated), 0.);
+    }
+
+    #[test]
+    fn test_calibration_curve() {
+        let xs = (0..500).map(|i| i as f64).collect::<Vec<_>>();
+        let draws = vec![vec![1., 0., 2.]; 200];
+        let observed = simulate_posterior_predictive(&draws[..1], &xs, 0., 1).remove(0);
+
+        // on the diagonal
+        let simulated = simulate_posterior_predictive(&draws, &xs, 0., 2);
+        let curve = calibration_curve(&observed, &simulated, &CALIBRATION_LEVELS);
+        assert_eq!(curve.len(), 9);
+        for (nominal, empirical) in &curve {
+            assert!((nominal - empirical).abs() < 0.06, "{:?}", curve);
+        }
+        assert_eq!(curve[4].0, 0.5);
+
+        // below the diagonal at every level with too narrow intervals
+        let narrow = vec![vec![1., 0., 1.]; 200];
+        let simulated = simulate_posterior_predictive(&narrow, &xs, 0., 2);
+        let curve = calibration_curve(&observed, &simulated, &CALIBRATION_LEVELS);
+        assert!(curve.iter().all(|(nominal, empirical)| empirical < nominal));
+    }
+}
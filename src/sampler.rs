@@ -1,43 +1,238 @@
 //! Interface to the sampler
-use nuts_rs::{new_sampler, Chain, CpuLogpFunc, SampleStats, SamplerArgs};
+use arrow2::array::{PrimitiveArray, StructArray};
+use nuts_rs::{new_sampler, ArrowBuilder, Chain, CpuLogpFunc, SampleStats, SamplerArgs};
 
 use rand::rngs::SmallRng;
 use rand::SeedableRng;
+use serde::{Deserialize, Serialize};
+
+use crate::utils::now_millis;
+
+/// How many draws between two checks of the time budget.
+const BUDGET_CHECK_INTERVAL: u64 = 10;
 
 /// Diveregence info - WASM friendly
 #[derive(Debug)]
 pub struct MyDivergenceInfo {
-    pub start_momentum: Option<Box<[f64]>>,
+    /// Position where the divergent trajectory started - if known
     pub start_location: Option<Box<[f64]>>,
-    pub start_gradient: Option<Box<[f64]>>,
-    pub end_location: Option<Box<[f64]>>,
-    pub energy_error: Option<f64>,
-    pub end_idx_in_trajectory: Option<i64>,
-    pub start_idx_in_trajectory: Option<i64>,
+    /// Index of the post-warmup draw that diverged
+    pub draw: usize,
 }
 
-impl From<&nuts_rs::DivergenceInfo> for MyDivergenceInfo {
-    fn from(div_info: &nuts_rs::DivergenceInfo) -> Self {
+impl MyDivergenceInfo {
+    /// The divergence of the post-warmup draw with index `draw`.
+    fn new(div_info: &nuts_rs::DivergenceInfo, draw: usize) -> Self {
         Self {
-            start_momentum: div_info.start_momentum.clone(),
             start_location: div_info.start_location.clone(),
-            start_gradient: div_info.start_gradient.clone(),
-            end_location: div_info.end_location.clone(),
-            energy_error: div_info.energy_error,
-            end_idx_in_trajectory: div_info.end_idx_in_trajectory,
-            start_idx_in_trajectory: div_info.start_idx_in_trajectory,
+            draw,
+        }
+    }
+}
+
+/// The state of adapted chains - to start other chains where they stopped.
+///
+/// nuts-rs cannot start from a given mass matrix: only the position and the
+/// step size of each chain are kept.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub(crate) struct SamplerState {
+    /// Last draw of each chain
+    pub positions: Vec<Vec<f64>>,
+    /// Step size adapted in each chain
+    pub step_sizes: Vec<f64>,
+}
+
+/// Acceptance, step size and tree depth of a draw - to follow the adaptation.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct DrawStats {
+    /// Mean acceptance probability of the trajectory of the draw
+    pub accept: f64,
+    /// Step size of the draw
+    pub step_size: f64,
+    /// Depth of the tree of the trajectory of the draw
+    pub depth: u64,
+}
+
+impl DrawStats {
+    /// The stats of a draw `info` of `sampler`.
+    fn new<C: Chain>(sampler: &C, info: &C::Stats) -> Self {
+        let row = stats_row(sampler, info);
+        Self {
+            accept: row_value(&row, "mean_tree_accept"),
+            step_size: row_value(&row, "step_size"),
+            depth: info.depth(),
         }
     }
 }
 
+/// Returns the stats of a draw `info` of `sampler` as an arrow row - nuts-rs
+/// only exposes the step size and the acceptance of a draw there.
+fn stats_row<C: Chain>(sampler: &C, info: &C::Stats) -> Option<StructArray> {
+    // a builder for this row only
+    let args = SamplerArgs {
+        num_tune: 0,
+        num_draws: 1,
+        ..Default::default()
+    };
+    let mut builder = sampler.stats_builder(sampler.dim(), &args);
+    builder.append_value(info);
+    builder.finalize()
+}
+
+/// Returns the value of the column `name` of a stats row - NaN if it has no
+/// such column.
+fn row_value(row: &Option<StructArray>, name: &str) -> f64 {
+    row.as_ref()
+        .and_then(|row| {
+            let column = row.fields().iter().position(|field| field.name == name)?;
+            row.values()[column]
+                .as_any()
+                .downcast_ref::<PrimitiveArray<f64>>()
+                .map(|values| values.value(0))
+        })
+        .unwrap_or(f64::NAN)
+}
+
+/// The outcome of [`be_nuts`].
+#[derive(Debug, Default)]
+pub(crate) struct ChainDraws {
+    /// The post-warmup draws
+    pub trace: Vec<Box<[f64]>>,
+    /// The divergences of the post-warmup draws
+    pub divergences: Vec<MyDivergenceInfo>,
+    /// Acceptance, step size and tree depth of each warmup draw
+    pub warmup: Vec<DrawStats>,
+    /// Acceptance, step size and tree depth of each post-warmup draw
+    pub sampling: Vec<DrawStats>,
+    /// The error that stopped the chain early - if any
+    pub failure: Option<String>,
+}
+
+impl ChainDraws {
+    /// The step size of the last draw - the one adapted during the warmup once
+    /// the warmup is over.
+    pub fn step_size(&self) -> f64 {
+        self.sampling
+            .last()
+            .or_else(|| self.warmup.last())
+            .map_or(f64::NAN, |stats| stats.step_size)
+    }
+}
+
 /// Run the sampler
+///
+/// The sampler adapts its step size and mass matrix during the first
+/// `num_tune` draws. These warmup draws are discarded: the returned trace holds
+/// exactly the `num_samples` draws that follow. The acceptance and the step
+/// size of every draw - warmup included - are kept to follow the adaptation.
+///
+/// With a `max_millis` budget, the sampling stops early once the budget is
+/// exhausted - the warmup always completes. The clock is checked every
+/// [`BUDGET_CHECK_INTERVAL`] draws, so the trace may be shorter than
+/// `num_samples`.
+///
+/// The step size is adapted to reach an acceptance rate of `target_accept` -
+/// 0.8 by default. Higher targets mean smaller steps and fewer divergences.
+///
+/// The adaptation starts from a step size of `initial_step` - the default of
+/// the sampler if not set. Without warmup, this is the step size of all the
+/// draws.
+///
+/// The trajectories are at most `2^maxdepth` steps long - 10 by default - and a
+/// draw whose energy error exceeds `max_energy_error` - 1000 by default - is a
+/// divergence.
+///
+/// An unrecoverable error of the sampler stops the chain: the draws made so far
+/// are kept and the error is returned along with them.
+#[allow(clippy::too_many_arguments)]
 pub fn be_nuts<F>(
     logp_func: F,
     num_tune: u64,
     num_samples: u64,
     position: &[f64],
     seed: u64,
-) -> (Vec<Box<[f64]>>, Vec<MyDivergenceInfo>)
+    max_millis: Option<f64>,
+    target_accept: Option<f64>,
+    initial_step: Option<f64>,
+    maxdepth: Option<u64>,
+    max_energy_error: Option<f64>,
+) -> ChainDraws
+where
+    F: CpuLogpFunc,
+{
+    let deadline = max_millis.map(|budget| now_millis() + budget);
+
+    let mut draws = ChainDraws::default();
+
+    let sampler = new_chain(
+        logp_func,
+        num_tune,
+        position,
+        seed,
+        target_accept,
+        initial_step,
+        maxdepth,
+        max_energy_error,
+    );
+    let mut sampler = match sampler {
+        Ok(sampler) => sampler,
+        Err(e) => {
+            draws.failure = Some(format!("at the start: {}", e));
+            return draws;
+        }
+    };
+
+    // Burn the warmup draws - they are not from the posterior: the chain is
+    // still moving away from the initial position and the sampler is adapting
+    for _ in 0..num_tune {
+        match sampler.draw() {
+            Ok((_, info)) => draws.warmup.push(DrawStats::new(&sampler, &info)),
+            Err(e) => {
+                draws.failure = Some(format!("during warmup: {}", e));
+                return draws;
+            }
+        }
+    }
+
+    for i in 0..num_samples {
+        if let Some(deadline) = deadline {
+            if i > 0 && i % BUDGET_CHECK_INTERVAL == 0 && now_millis() > deadline {
+                break;
+            }
+        }
+
+        let (draw, info) = match sampler.draw() {
+            Ok(draw) => draw,
+            Err(e) => {
+                draws.failure = Some(format!("after {} draws: {}", draws.trace.len(), e));
+                return draws;
+            }
+        };
+        if let Some(div_info) = info.divergence_info() {
+            let divergence = MyDivergenceInfo::new(div_info, draws.trace.len());
+            draws.divergences.push(divergence);
+        }
+        draws.trace.push(draw);
+        draws.sampling.push(DrawStats::new(&sampler, &info));
+    }
+
+    draws
+}
+
+/// Creates a sampler adapting during `num_tune` draws and starting at `position`.
+///
+/// Fails if the sampler cannot be set at `position`.
+#[allow(clippy::too_many_arguments)]
+fn new_chain<F>(
+    logp_func: F,
+    num_tune: u64,
+    position: &[f64],
+    seed: u64,
+    target_accept: Option<f64>,
+    initial_step: Option<f64>,
+    maxdepth: Option<u64>,
+    max_energy_error: Option<f64>,
+) -> Result<impl Chain, String>
 where
     F: CpuLogpFunc,
 {
@@ -48,30 +243,82 @@ where
     assert_eq!(dim, position.len(), "Dimension mismatch");
 
     sampler_args.num_tune = num_tune;
+    if let Some(maxdepth) = maxdepth {
+        sampler_args.maxdepth = maxdepth;
+    }
+    if let Some(max_energy_error) = max_energy_error {
+        sampler_args.max_energy_error = max_energy_error;
+    }
+    if let Some(target_accept) = target_accept {
+        sampler_args
+            .mass_matrix_adapt
+            .dual_average_options
+            .target_accept = target_accept;
+    }
+    if let Some(initial_step) = initial_step {
+        sampler_args
+            .mass_matrix_adapt
+            .dual_average_options
+            .initial_step = initial_step;
+    }
 
     let chain = 0;
     let mut rng = SmallRng::seed_from_u64(seed);
     let mut sampler = new_sampler(logp_func, sampler_args, chain, &mut rng);
 
     // Set to some initial position
-    sampler
-        .set_position(position)
-        .expect("Unrecoverable error during init");
+    sampler.set_position(position).map_err(|e| e.to_string())?;
 
-    // Burn the first x samples to get away from the initial position
-    for _ in 0..num_tune {
-        sampler.draw().expect("Unrecoverable error during burning");
-    }
+    Ok(sampler)
+}
 
-    let mut trace = vec![]; // Collection of all draws
-    let mut stats = vec![]; // Collection of statistics like the acceptance rate for each draw
-    for _ in 0..num_samples {
-        let (draw, info) = sampler.draw().expect("Unrecoverable error during sampling");
-        trace.push(draw);
-        if let Some(div_info) = info.divergence_info() {
-            stats.push(div_info.into());
-        }
-    }
+/// A draw of a [`Stepper`]: the position, the divergence of a post-warmup draw
+/// if any and the acceptance, step size and tree depth.
+pub(crate) type Step = (Box<[f64]>, Option<MyDivergenceInfo>, DrawStats);
+
+/// Draws one position at a time - see [`be_nuts_stepper`]. A draw fails with
+/// the unrecoverable error of the sampler.
+pub(crate) type Stepper = Box<dyn FnMut() -> Result<Step, String>>;
+
+/// Creates a sampler as [`be_nuts`] does, but returns the draws one at a time -
+/// the warmup draws included - so that the caller can do something in between
+/// (e.g. plot the partial traces).
+///
+/// Fails if the sampler cannot be set at `position`.
+#[allow(clippy::too_many_arguments)]
+pub fn be_nuts_stepper<F>(
+    logp_func: F,
+    num_tune: u64,
+    position: &[f64],
+    seed: u64,
+    target_accept: Option<f64>,
+    initial_step: Option<f64>,
+    maxdepth: Option<u64>,
+    max_energy_error: Option<f64>,
+) -> Result<Stepper, String>
+where
+    F: CpuLogpFunc + 'static,
+{
+    let mut sampler = new_chain(
+        logp_func,
+        num_tune,
+        position,
+        seed,
+        target_accept,
+        initial_step,
+        maxdepth,
+        max_energy_error,
+    )?;
 
-    (trace, stats)
+    let mut drawn = 0;
+    Ok(Box::new(move || {
+        let (draw, info) = sampler.draw().map_err(|e| e.to_string())?;
+        // only the divergences of the post-warmup draws are kept
+        let divergence = info
+            .divergence_info()
+            .filter(|_| drawn >= num_tune)
+            .map(|div_info| MyDivergenceInfo::new(div_info, (drawn - num_tune) as usize));
+        drawn += 1;
+        Ok((draw, divergence, DrawStats::new(&sampler, &info)))
+    }))
 }
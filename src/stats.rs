@@ -0,0 +1,612 @@
+//! Statistics of the draws - pure functions shared by the diagnostics, the
+//! summaries and the plots
+use wasm_bindgen::prelude::*;
+
+use crate::numerics::{kahan_sum, KahanSum};
+
+/// Returns the mean of the values - with a compensated sum (see
+/// [`KahanSum`](crate::numerics::KahanSum)).
+pub(crate) fn mean(values: &[f64]) -> f64 {
+    kahan_sum(values.iter().copied()) / values.len() as f64
+}
+
+/// Returns the sample variance of the values - with Bessel's correction.
+///
+/// A single value has a variance of 0.
+pub(crate) fn variance(values: &[f64]) -> f64 {
+    if values.len() < 2 {
+        return 0.;
+    }
+
+    let mean = mean(values);
+    kahan_sum(values.iter().map(|x| (x - mean).powi(2))) / (values.len() - 1) as f64
+}
+
+/// Returns the sample standard deviation of the values.
+pub(crate) fn sd(values: &[f64]) -> f64 {
+    variance(values).sqrt()
+}
+
+/// Autocovariance of a chain at a given lag - around `mean` and divided by the
+/// length of the chain (the biased estimator, as for ESS).
+pub(crate) fn autocovariance(chain: &[f64], mean: f64, lag: usize) -> f64 {
+    chain
+        .iter()
+        .zip(chain.iter().skip(lag))
+        .map(|(x, y)| (x - mean) * (y - mean))
+        .sum::<f64>()
+        / chain.len() as f64
+}
+
+/// Returns a sorted copy of the draws.
+fn sorted(draws: &[f64]) -> Vec<f64> {
+    let mut sorted = draws.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    sorted
+}
+
+/// How a quantile falling between two draws is computed - the `method` of
+/// `numpy.quantile`.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum QuantileMethod {
+    /// Linear interpolation between the two draws - type 7 of R, the default
+    /// of NumPy and pandas.
+    #[default]
+    Linear,
+    /// The lower of the two draws.
+    Lower,
+    /// The higher of the two draws.
+    Higher,
+    /// The closest of the two draws - the one of even rank when halfway.
+    Nearest,
+}
+
+/// Returns the `q` quantile (0 <= q <= 1) of the draws - with linear
+/// interpolation between the closest ranks.
+pub(crate) fn quantile(draws: &[f64], q: f64) -> f64 {
+    quantile_with(draws, q, QuantileMethod::default())
+}
+
+/// Returns the `q` quantile (0 <= q <= 1) of the draws - with the given method
+/// between the closest ranks.
+pub(crate) fn quantile_with(draws: &[f64], q: f64, method: QuantileMethod) -> f64 {
+    assert!(!draws.is_empty(), "No draws");
+    assert!((0. ..=1.).contains(&q), "q must be in [0, 1]");
+
+    let sorted = sorted(draws);
+
+    let h = (sorted.len() - 1) as f64 * q;
+    let lo = h.floor() as usize;
+    let hi = h.ceil() as usize;
+
+    match method {
+        QuantileMethod::Linear => sorted[lo] + (h - lo as f64) * (sorted[hi] - sorted[lo]),
+        QuantileMethod::Lower => sorted[lo],
+        QuantileMethod::Higher => sorted[hi],
+        QuantileMethod::Nearest => {
+            let fraction = h - lo as f64;
+            // ties go to the even rank - as numpy.around does
+            if fraction < 0.5 || (fraction == 0.5 && lo.is_multiple_of(2)) {
+                sorted[lo]
+            } else {
+                sorted[hi]
+            }
+        }
+    }
+}
+
+/// Returns the highest-density interval: the narrowest interval containing a
+/// fraction `prob` of the draws.
+///
+/// Unlike equal-tailed intervals, this is not misleading for skewed
+/// posteriors.
+pub(crate) fn hdi(draws: &[f64], prob: f64) -> (f64, f64) {
+    assert!(!draws.is_empty(), "No draws");
+    assert!(prob > 0. && prob <= 1., "prob must be in (0, 1]");
+
+    let sorted = sorted(draws);
+    let n = sorted.len();
+
+    // number of draws spanned by each candidate interval
+    let width = ((prob * n as f64).floor() as usize).min(n - 1);
+
+    // slide the window and keep the narrowest interval
+    let start = (0..n - width)
+        .min_by(|&a, &b| {
+            (sorted[a + width] - sorted[a]).total_cmp(&(sorted[b + width] - sorted[b]))
+        })
+        .unwrap();
+
+    (sorted[start], sorted[start + width])
+}
+
+/// Returns the mode of the draws: the peak of their Gaussian kernel density
+/// estimate.
+///
+/// The bandwidth follows Silverman's rule of thumb and the density is
+/// evaluated on a regular grid between the smallest and largest draws.
+pub(crate) fn posterior_mode(draws: &[f64]) -> f64 {
+    assert!(!draws.is_empty(), "No draws");
+
+    const GRID_POINTS: usize = 512;
+
+    let n = draws.len() as f64;
+    let sd = variance(draws).sqrt();
+    let iqr = quantile(draws, 0.75) - quantile(draws, 0.25);
+
+    let spread = if iqr > 0. { sd.min(iqr / 1.34) } else { sd };
+    if spread <= 0. {
+        // constant draws
+        return draws[0];
+    }
+    let bandwidth = 0.9 * spread * n.powf(-0.2);
+
+    let (min_, max_) = draws
+        .iter()
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), x| {
+            (lo.min(*x), hi.max(*x))
+        });
+
+    // unnormalized density - only its peak matters
+    let density = |x: f64| {
+        draws
+            .iter()
+            .map(|d| (-0.5 * ((x - d) / bandwidth).powi(2)).exp())
+            .sum::<f64>()
+    };
+
+    (0..GRID_POINTS)
+        .map(|i| min_ + (max_ - min_) * i as f64 / (GRID_POINTS - 1) as f64)
+        .map(|x| (x, density(x)))
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .unwrap()
+        .0
+}
+
+/// Effective sample size of the draws of multiple chains of the same length.
+///
+/// The autocorrelations are estimated across chains and summed with Geyer's
+/// initial monotone sequence - as in Stan. For independent draws this is the
+/// number of draws, for autocorrelated draws it is (much) less.
+///
+/// A single chain is fine. With fewer than 4 draws per chain the
+/// autocorrelations cannot be estimated: the draws are then counted as
+/// independent - the ESS is never NaN.
+pub(crate) fn ess(chains: &[Vec<f64>]) -> f64 {
+    let total = chains.iter().map(|chain| chain.len()).sum::<usize>() as f64;
+    if chains.iter().any(|chain| chain.len() < 4) {
+        return total;
+    }
+
+    match autocorrelation_time(chains) {
+        // at most total * log10(total) - a negative sum of autocorrelations
+        // is noise of short chains
+        Some(tau) if tau > 0. => (total / tau).min(total * total.log10()),
+        Some(_) => total * total.log10(),
+        // constant draws
+        None => total,
+    }
+}
+
+/// Integrated autocorrelation time of the draws of multiple chains of the
+/// same length - see [`ess`].
+///
+/// This is the number of draws worth one independent draw: keeping one draw
+/// out of `ceil(tau)` gives roughly independent draws. 1 for constant draws.
+pub(crate) fn integrated_autocorr_time(chains: &[Vec<f64>]) -> f64 {
+    autocorrelation_time(chains).unwrap_or(1.)
+}
+
+/// The integrated autocorrelation time of [`integrated_autocorr_time`] - `None`
+/// for constant draws.
+fn autocorrelation_time(chains: &[Vec<f64>]) -> Option<f64> {
+    assert!(!chains.is_empty(), "No chains");
+
+    let m = chains.len();
+    let n = chains[0].len();
+    assert!(
+        chains.iter().all(|chain| chain.len() == n),
+        "The chains must have the same length"
+    );
+    assert!(n >= 2, "At least 2 draws per chain are required");
+
+    let means = chains.iter().map(|chain| mean(chain)).collect::<Vec<_>>();
+
+    // within-chain variance
+    let w = mean(
+        &chains
+            .iter()
+            .map(|chain| variance(chain))
+            .collect::<Vec<_>>(),
+    );
+
+    // between-chain variance - divided by n
+    let b_n = if m > 1 { variance(&means) } else { 0. };
+
+    let var_plus = w * (n - 1) as f64 / n as f64 + b_n;
+    if var_plus <= 0. {
+        return None;
+    }
+
+    // autocorrelation at a given lag - combined across chains
+    let rho = |lag: usize| {
+        if lag == 0 {
+            return 1.;
+        }
+
+        let acov = chains
+            .iter()
+            .zip(means.iter())
+            .map(|(chain, mean)| autocovariance(chain, *mean, lag))
+            .sum::<f64>()
+            / m as f64;
+
+        1. - (w - acov) / var_plus
+    };
+
+    // sum the pairs of autocorrelations while they are positive and
+    // decreasing - the tail is mostly noise
+    let mut tau = -1.;
+    let mut previous = f64::INFINITY;
+    let mut lag = 0;
+    while lag + 1 < n {
+        let pair = rho(lag) + rho(lag + 1);
+        if pair < 0. {
+            break;
+        }
+
+        let pair = pair.min(previous);
+        tau += 2. * pair;
+        previous = pair;
+        lag += 2;
+    }
+
+    Some(tau)
+}
+
+/// Split R-hat of the draws of multiple chains of the same length.
+///
+/// Each chain is split in halves - so that a drift within a chain shows up as
+/// a disagreement between its halves - and the variance of the draws of all
+/// the halves is compared to the variance within each half (Gelman et al.,
+/// BDA3). Close to 1 when the chains mix - above ~1.01 they have not
+/// converged.
+pub(crate) fn split_r_hat(chains: &[Vec<f64>]) -> f64 {
+    assert!(!chains.is_empty(), "No chains");
+
+    let len = chains[0].len();
+    assert!(
+        chains.iter().all(|chain| chain.len() == len),
+        "The chains must have the same length"
+    );
+    // the middle draw of an odd chain is dropped
+    let n = len / 2;
+    assert!(n >= 2, "At least 4 draws per chain are required");
+
+    let halves = chains
+        .iter()
+        .flat_map(|chain| vec![&chain[..n], &chain[len - n..]])
+        .collect::<Vec<_>>();
+
+    let means = halves.iter().map(|half| mean(half)).collect::<Vec<_>>();
+
+    // within-half variance
+    let w = mean(&halves.iter().map(|half| variance(half)).collect::<Vec<_>>());
+
+    // between-half variance - divided by n
+    let b_n = variance(&means);
+
+    if w <= 0. {
+        // constant halves: they agree only if they are the same constant
+        return if b_n <= 0. { 1. } else { f64::INFINITY };
+    }
+
+    let var_plus = w * (n - 1) as f64 / n as f64 + b_n;
+    (var_plus / w).sqrt()
+}
+
+/// Number of samples per chain to reach `target_ess`
+///
+/// Extrapolated from a run of `current_samples` samples per chain with an ESS
+/// of `current_ess` - assuming the ESS grows linearly with the number of
+/// samples. Without any effective sample, no run length is enough: the
+/// result is `u64::MAX`.
+#[wasm_bindgen]
+pub fn samples_for_ess(current_ess: f64, current_samples: u64, target_ess: f64) -> u64 {
+    if current_ess.is_nan() || current_ess <= 0. {
+        return u64::MAX;
+    }
+
+    (current_samples as f64 * target_ess / current_ess).ceil() as u64
+}
+
+/// Returns the centered moving average of the values over `window` points.
+///
+/// Each value is replaced by the mean of the values within `window / 2` points
+/// on each side - an even window is thus widened by one point. Near the edges,
+/// the window shrinks to the points available on both sides so that it stays
+/// centered: the first and the last values are kept as they are. A window of 0
+/// or 1 returns the values unchanged.
+pub(crate) fn moving_average(values: &[f64], window: usize) -> Vec<f64> {
+    let half = window / 2;
+
+    // sums[i] is the sum of the first i values
+    let sums = std::iter::once(0.)
+        .chain(values.iter().scan(0., |sum, x| {
+            *sum += x;
+            Some(*sum)
+        }))
+        .collect::<Vec<_>>();
+
+    (0..values.len())
+        .map(|i| {
+            let half = half.min(i).min(values.len() - 1 - i);
+            (sums[i + half + 1] - sums[i - half]) / (2 * half + 1) as f64
+        })
+        .collect()
+}
+
+/// Returns the running mean of the values: the mean of the first `i + 1`
+/// values at index `i`.
+///
+/// On a trace, it flattens as the chain converges.
+pub(crate) fn running_mean(values: &[f64]) -> Vec<f64> {
+    let mut sum = KahanSum::default();
+    values
+        .iter()
+        .enumerate()
+        .map(|(i, x)| {
+            sum.add(*x);
+            sum.value() / (i + 1) as f64
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand_distr::Distribution;
+
+    use super::*;
+
+    #[test]
+    fn test_mean_variance() {
+        let values = [2., 4., 4., 4., 5., 5., 7., 9.];
+
+        assert_eq!(mean(&values), 5.);
+        assert!((variance(&values) - 32. / 7.).abs() < 1e-12);
+        assert!((sd(&values) - (32f64 / 7.).sqrt()).abs() < 1e-12);
+        assert_eq!(variance(&[3.]), 0.);
+    }
+
+    #[test]
+    fn test_moving_average() {
+        let values = [1., 2., 6., 4., 5., 9.];
+
+        assert_eq!(moving_average(&values, 3), vec![1., 3., 4., 5., 6., 9.]);
+        // shrinking at the edges
+        assert_eq!(moving_average(&values, 5), vec![1., 3., 3.6, 5.2, 6., 9.]);
+        // an even window is widened
+        assert_eq!(moving_average(&values, 4), moving_average(&values, 5));
+        assert_eq!(moving_average(&values, 1), values.to_vec());
+        assert_eq!(moving_average(&values, 0), values.to_vec());
+        assert!(moving_average(&[], 3).is_empty());
+    }
+
+    #[test]
+    fn test_running_mean() {
+        assert_eq!(running_mean(&[2., 4., 0., 6.]), vec![2., 3., 2., 3.]);
+        assert!(running_mean(&[]).is_empty());
+
+        // converges to the mean of a long trace
+        let values = (0..1000).map(|i| (i % 10) as f64).collect::<Vec<_>>();
+        assert_eq!(*running_mean(&values).last().unwrap(), mean(&values));
+    }
+
+    #[test]
+    fn test_autocovariance() {
+        let alternating = (0..100)
+            .map(|i| if i % 2 == 0 { 1. } else { -1. })
+            .collect::<Vec<f64>>();
+
+        assert_eq!(autocovariance(&alternating, 0., 0), 1.);
+        assert!((autocovariance(&alternating, 0., 1) - -0.99).abs() < 1e-12);
+        assert!((autocovariance(&alternating, 0., 2) - 0.98).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_quantile() {
+        let draws = [3., 1., 4., 1., 5., 9., 2., 6.];
+
+        // reference values from numpy.quantile
+        assert_eq!(quantile(&draws, 0.), 1.);
+        assert_eq!(quantile(&draws, 1.), 9.);
+        assert!((quantile(&draws, 0.5) - 3.5).abs() < 1e-12);
+        assert!((quantile(&draws, 0.25) - 1.75).abs() < 1e-12);
+
+        // sorted: [1, 1, 2, 3, 4, 5, 6, 9]
+        let methods = [
+            QuantileMethod::Linear,
+            QuantileMethod::Lower,
+            QuantileMethod::Higher,
+            QuantileMethod::Nearest,
+        ];
+        let expected = [
+            (0.5, [3.5, 3., 4., 4.]),
+            (0.25, [1.75, 1., 2., 2.]),
+            (0.1, [1., 1., 1., 1.]),
+            (0.3, [2.1, 2., 3., 2.]),
+        ];
+        for (q, values) in expected {
+            for (method, value) in methods.iter().zip(values) {
+                assert!(
+                    (quantile_with(&draws, q, *method) - value).abs() < 1e-12,
+                    "{:?} at {}",
+                    method,
+                    q
+                );
+            }
+        }
+
+        // halfway between two ranks: the even one
+        let draws = [10., 20., 30., 40., 50.];
+        assert_eq!(quantile_with(&draws, 0.125, QuantileMethod::Nearest), 10.);
+        assert_eq!(quantile_with(&draws, 0.375, QuantileMethod::Nearest), 30.);
+        // type 7 of R: quantile(1:10, 0.1)
+        let draws = (1..=10).map(f64::from).collect::<Vec<_>>();
+        assert!((quantile(&draws, 0.1) - 1.9).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_hdi_skewed() {
+        let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(42);
+        let exp = rand_distr::Exp::new(1.).unwrap();
+        let draws = (0..10000).map(|_| exp.sample(&mut rng)).collect::<Vec<_>>();
+
+        let (low, high) = hdi(&draws, 0.95);
+
+        // for an exponential the HDI starts at 0 and ends at -ln(0.05)
+        assert!(low < 0.01, "low = {}", low);
+        assert!((high - 0.05f64.ln().abs()).abs() < 0.15, "high = {}", high);
+
+        // and it is narrower than the equal-tailed interval
+        let equal_tailed = quantile(&draws, 0.975) - quantile(&draws, 0.025);
+        assert!(high - low < equal_tailed);
+    }
+
+    #[test]
+    fn test_posterior_mode() {
+        let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(42);
+
+        // Gamma(shape = 3, scale = 1): mode 2, mean 3
+        let gamma = rand_distr::Gamma::new(3., 1.).unwrap();
+        let draws = (0..5000)
+            .map(|_| gamma.sample(&mut rng))
+            .collect::<Vec<f64>>();
+
+        let mode = posterior_mode(&draws);
+        assert!((mode - 2.).abs() < 0.25, "mode = {}", mode);
+
+        let mean = draws.iter().sum::<f64>() / draws.len() as f64;
+        assert!(mean - mode > 0.5);
+
+        assert_eq!(posterior_mode(&[1.5; 10]), 1.5);
+    }
+
+    #[test]
+    fn test_samples_for_ess() {
+        assert_eq!(samples_for_ess(100., 800, 400.), 3200);
+        assert_eq!(samples_for_ess(300., 1000, 400.), 1334);
+        // already there
+        assert_eq!(samples_for_ess(800., 1000, 400.), 500);
+        assert_eq!(samples_for_ess(0., 1000, 400.), u64::MAX);
+        assert_eq!(samples_for_ess(f64::NAN, 1000, 400.), u64::MAX);
+    }
+
+    #[test]
+    fn test_split_r_hat() {
+        let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(42);
+        let normal = rand_distr::StandardNormal;
+
+        // chains of the same distribution
+        let chains = (0..4)
+            .map(|_| {
+                (0..1000)
+                    .map(|_| normal.sample(&mut rng))
+                    .collect::<Vec<f64>>()
+            })
+            .collect::<Vec<_>>();
+        let r_hat = split_r_hat(&chains);
+        assert!((r_hat - 1.).abs() < 0.01, "r_hat = {}", r_hat);
+
+        // a drift within a single chain
+        let drifting = (0..1000).map(|i| i as f64 / 100.).collect::<Vec<_>>();
+        assert!(split_r_hat(&[drifting]) > 1.5);
+
+        assert_eq!(split_r_hat(&[vec![2.; 10], vec![2.; 10]]), 1.);
+        assert_eq!(split_r_hat(&[vec![2.; 10], vec![3.; 10]]), f64::INFINITY);
+    }
+
+    #[test]
+    fn test_ess() {
+        let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(42);
+        let normal = rand_distr::StandardNormal;
+
+        // independent draws
+        let chains = (0..4)
+            .map(|_| {
+                (0..5000)
+                    .map(|_| normal.sample(&mut rng))
+                    .collect::<Vec<f64>>()
+            })
+            .collect::<Vec<_>>();
+        let ess_iid = ess(&chains);
+        assert!((ess_iid / 20000. - 1.).abs() < 0.1, "ess = {}", ess_iid);
+
+        // AR(1) draws: the ESS is n (1 - phi) / (1 + phi)
+        let phi = 0.9;
+        let chains = (0..4)
+            .map(|_| {
+                let mut x = 0.;
+                (0..5000)
+                    .map(|_| {
+                        let z: f64 = normal.sample(&mut rng);
+                        x = phi * x + z;
+                        x
+                    })
+                    .collect::<Vec<f64>>()
+            })
+            .collect::<Vec<_>>();
+        let expected = 20000. * (1. - phi) / (1. + phi);
+        let ess_ar = ess(&chains);
+        assert!((ess_ar / expected - 1.).abs() < 0.25, "ess = {}", ess_ar);
+
+        // a single chain
+        let ess_single = ess(&chains[..1]);
+        assert!(
+            (ess_single / (expected / 4.) - 1.).abs() < 0.4,
+            "ess = {}",
+            ess_single
+        );
+
+        // too short to estimate the autocorrelations
+        assert_eq!(ess(&[vec![1.]]), 1.);
+        assert_eq!(ess(&[vec![1., 2., 3.], vec![2., 1., 3.]]), 6.);
+        // barely long enough: finite
+        for chains in [
+            vec![vec![1., 2., 1., 2.]],
+            vec![vec![1., 2., 3., 4.], vec![4., 3., 2., 1.]],
+            vec![vec![0., 1., 0., 1., 0.]],
+        ] {
+            let ess = ess(&chains);
+            assert!(ess.is_finite() && ess > 0., "ess = {}", ess);
+        }
+    }
+
+    #[test]
+    fn test_integrated_autocorr_time() {
+        let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(7);
+        let normal = rand_distr::StandardNormal;
+
+        // AR(1) draws: the IACT is (1 + phi) / (1 - phi)
+        let phi = 0.8;
+        let chains = (0..4)
+            .map(|_| {
+                let mut x = 0.;
+                (0..5000)
+                    .map(|_| {
+                        let z: f64 = normal.sample(&mut rng);
+                        x = phi * x + z;
+                        x
+                    })
+                    .collect::<Vec<f64>>()
+            })
+            .collect::<Vec<_>>();
+        let tau = integrated_autocorr_time(&chains);
+        assert!((tau / 9. - 1.).abs() < 0.2, "tau = {}", tau);
+        assert!((ess(&chains) * tau / 20000. - 1.).abs() < 1e-12);
+
+        assert_eq!(integrated_autocorr_time(&[vec![1.; 10], vec![1.; 10]]), 1.);
+    }
+}
@@ -1,3 +1,4 @@
+use wasm_bindgen::prelude::*;
 // use wasm_bindgen::{JsCast, JsValue};
 // use wasm_bindgen_futures::JsFuture;
 // use web_sys::{Request, RequestInit, RequestMode, Response};
@@ -13,6 +14,59 @@ pub fn set_panic_hook() {
     console_error_panic_hook::set_once();
 }
 
+/// Time spent in each stage of a run, in milliseconds - see `benchmark`
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Timings {
+    /// Reading the input and building the model
+    pub parse_millis: f64,
+    /// Warmup draws of all the chains
+    pub warmup_millis: f64,
+    /// Post-warmup draws of all the chains
+    pub sampling_millis: f64,
+    /// Plotting the traces and the posteriors
+    pub plotting_millis: f64,
+}
+
+/// Returns the current time in milliseconds - for time budgets.
+///
+/// `std::time` is not available in the browser, so it relies on `Date.now()`
+/// there.
+pub fn now_millis() -> f64 {
+    #[cfg(target_arch = "wasm32")]
+    {
+        js_sys::Date::now()
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs_f64()
+            * 1000.
+    }
+}
+
+/// Returns the variant of an enum exported to JS from its value there - its
+/// index in `variants`, as wasm-bindgen numbers the variants.
+pub(crate) fn variant_from_index<T: Copy>(variants: &[T], index: u32) -> Result<T, String> {
+    variants.get(index as usize).copied().ok_or_else(|| {
+        format!(
+            "{} is not a variant - expected 0 to {}",
+            index,
+            variants.len() - 1
+        )
+    })
+}
+
+/// Runs `f` and returns its result and the milliseconds it took.
+pub fn timed<T>(f: impl FnOnce() -> T) -> (T, f64) {
+    let start = now_millis();
+    let result = f();
+    (result, now_millis() - start)
+}
+
 // pub async fn download(url: String) -> Result<JsValue, JsValue> {
 //     let mut opts = RequestInit::new();
 //     opts.method("GET");
@@ -35,3 +89,18 @@ pub fn set_panic_hook() {
 //     // Send the JSON response back to JS.
 //     Ok(text)
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timed() {
+        let (result, millis) = timed(|| {
+            std::thread::sleep(std::time::Duration::from_millis(20));
+            42
+        });
+        assert_eq!(result, 42);
+        assert!(millis >= 20., "{}", millis);
+    }
+}